@@ -4,7 +4,8 @@ use crate::scheduler::Task;
 use chan::Sender;
 use std::time::Duration;
 use crate::input::I3BarEvent;
-use crate::widget::I3BarWidget;
+use crate::widget::{I3BarWidget, State};
+use std::collections::HashMap;
 
 pub trait Block {
     /// Updates the internal state of a Block
@@ -23,6 +24,21 @@ pub trait Block {
 
     /// This function returns a unique id.
     fn id(&self) -> &str;
+
+    /// Returns the most severe state among the widgets of the block
+    fn state(&self) -> State {
+        self.view()
+            .iter()
+            .map(|widget| widget.get_state())
+            .max_by_key(|state| state.severity())
+            .unwrap_or(State::Idle)
+    }
+
+    /// Returns the placeholder values of the last update, keyed like in the
+    /// block's format string (e.g. `{percentage}`)
+    fn placeholders(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
 }
 
 pub trait ConfigBlock: Block {
@@ -7,6 +7,7 @@
 //! brightness levels using `xrandr`, see the
 //! [`Xrandr`](../xrandr/struct.Xrandr.html) block.
 
+use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
@@ -19,6 +20,7 @@ use uuid::Uuid;
 
 use crate::block::{Block, ConfigBlock};
 use crate::config::Config;
+use crate::de::deserialize_expanded;
 use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
@@ -52,9 +54,9 @@ pub struct BacklitDevice {
 
 impl BacklitDevice {
     /// Use the default backlit device, i.e. the first one found in the
-    /// `/sys/class/backlight` directory.
-    pub fn default() -> Result<Self> {
-        let devices = Path::new("/sys/class/backlight")
+    /// `/sys/class/backlight` directory, which is passed in as `class_path`.
+    pub fn default(class_path: &Path) -> Result<Self> {
+        let devices = class_path
                            .read_dir() // Iterate over entries in the directory.
                            .block_error("backlight",
                                         "Failed to read backlight device directory")?;
@@ -82,10 +84,10 @@ impl BacklitDevice {
         })
     }
 
-    /// Use the backlit device `device`. Returns an error if a directory for
-    /// that device is not found.
-    pub fn from_device(device: String) -> Result<Self> {
-        let device_path = Path::new("/sys/class/backlight").join(device);
+    /// Use the backlit device `device` in `class_path`. Returns an error if a
+    /// directory for that device is not found.
+    pub fn from_device(class_path: &Path, device: String) -> Result<Self> {
+        let device_path = class_path.join(device);
         if !device_path.exists() {
             return Err(BlockError(
                 "backlight".to_string(),
@@ -149,6 +151,8 @@ pub struct Backlight {
     output: ButtonWidget,
     device: BacklitDevice,
     step_width: u64,
+    /// Brightness at the last update, in percent
+    brightness: u64,
 }
 
 /// Configuration for the [`Backlight`](./struct.Backlight.html) block.
@@ -156,7 +160,7 @@ pub struct Backlight {
 #[serde(deny_unknown_fields)]
 pub struct BacklightConfig {
     /// The backlight device in `/sys/class/backlight/` to read brightness from.
-    #[serde(default = "BacklightConfig::default_device")]
+    #[serde(default = "BacklightConfig::default_device", deserialize_with = "deserialize_expanded")]
     pub device: Option<String>,
 
     /// The steps brightness is in/decreased for the selected screen (When greater than 50 it gets limited to 50)
@@ -178,9 +182,10 @@ impl ConfigBlock for Backlight {
     type Config = BacklightConfig;
 
     fn new(block_config: Self::Config, config: Config, tx_update_request: Sender<Task>) -> Result<Self> {
+        let class_path = config.host_path("/sys/class/backlight");
         let device = match block_config.device {
-            Some(path) => BacklitDevice::from_device(path),
-            None => BacklitDevice::default(),
+            Some(path) => BacklitDevice::from_device(&class_path, path),
+            None => BacklitDevice::default(&class_path),
         }?;
 
         let id = Uuid::new_v4().simple().to_string();
@@ -191,6 +196,7 @@ impl ConfigBlock for Backlight {
             id: id.clone(),
             device,
             step_width: block_config.step_width,
+            brightness: 0,
         };
 
         // Spin up a thread to watch for changes to the brightness file for the
@@ -226,6 +232,7 @@ impl ConfigBlock for Backlight {
 impl Block for Backlight {
     fn update(&mut self) -> Result<Option<Duration>> {
         let brightness = self.device.brightness()?;
+        self.brightness = brightness;
         self.output.set_text(format!("{}%", brightness));
         match brightness {
             0...19 => self.output.set_icon("backlight_empty"),
@@ -264,7 +271,40 @@ impl Block for Backlight {
         Ok(())
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        let device = self.device.device_path.file_name().map(|name| name.to_string_lossy().into_owned());
+        map_to_owned!("{brightness}" => format!("{}", self.brightness),
+                      "{device}" => device.unwrap_or_default())
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::BacklitDevice;
+    use crate::testing::testdata;
+
+    #[test]
+    fn default_device_is_the_first_one_listed() {
+        let class_path = testdata("fs_root/sys/class/backlight");
+        let listed: Vec<_> = class_path.read_dir().unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert!(listed.len() > 1, "the fixture should have several devices");
+
+        // Whatever order the directory lists them in, not sorted by name
+        let device = BacklitDevice::default(&class_path).unwrap();
+        assert_eq!(device.device_path, listed[0]);
+    }
+
+    #[test]
+    fn brightness_is_relative_to_the_maximum() {
+        let class_path = testdata("fs_root/sys/class/backlight");
+        for device in &["acpi_video0", "intel_backlight", "nv_backlight"] {
+            let device = BacklitDevice::from_device(&class_path, device.to_string()).unwrap();
+            assert_eq!(device.brightness().unwrap(), 50);
+        }
+        assert!(BacklitDevice::from_device(&class_path, "nothing".to_owned()).is_err());
+    }
+}
@@ -4,6 +4,7 @@
 //! display the status, capacity, and time remaining for (dis)charge for an
 //! internal power supply.
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use crate::util::FormatTemplate;
 use std::time::{Duration, Instant};
@@ -16,9 +17,11 @@ use crate::block::{Block, ConfigBlock};
 use crate::blocks::dbus;
 use crate::blocks::dbus::stdintf::org_freedesktop_dbus::Properties;
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::{deserialize_duration, deserialize_opt_duration};
 use crate::errors::*;
+use crate::rate::{Average, Smoothing};
 use crate::scheduler::Task;
+use crate::threshold::{Thresholds, Trigger};
 use crate::util::read_file;
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::text::TextWidget;
@@ -50,10 +53,10 @@ pub struct PowerSupplyDevice {
 
 impl PowerSupplyDevice {
     /// Use the power supply device `device`, as found in the
-    /// `/sys/class/power_supply` directory. Raises an error if a directory for
-    /// that device is not found.
-    pub fn from_device(device: &str) -> Result<Self> {
-        let device_path = Path::new("/sys/class/power_supply").join(device);
+    /// `/sys/class/power_supply` directory, which is passed in as `class_path`.
+    /// Raises an error if a directory for that device is not found.
+    pub fn from_device(class_path: &Path, device: &str) -> Result<Self> {
+        let device_path = class_path.join(device);
         if !device_path.exists() {
             return Err(BlockError(
                 "battery".to_string(),
@@ -350,6 +353,9 @@ pub struct Battery {
     device: Box<BatteryDevice>,
     format: FormatTemplate,
     driver: BatteryDriver,
+    thresholds: Thresholds,
+    power_average: Average,
+    values: HashMap<String, String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -383,7 +389,7 @@ pub struct BatteryConfig {
     pub show: Option<String>,
 
     /// Format string for displaying battery information.
-    /// placeholders: {percentage}, {time} and {power}
+    /// placeholders: {percentage}, {time}, {power} and {power_avg}
     #[serde(default = "BatteryConfig::default_format")]
     pub format: String,
 
@@ -393,6 +399,31 @@ pub struct BatteryConfig {
 
     /// The "driver" to use for powering the block. One of "sysfs" or "upower".
     pub driver: Option<BatteryDriver>,
+
+    /// Capacity in percent, at or below which the state is set to info
+    #[serde(default = "BatteryConfig::default_info")]
+    pub info: f64,
+
+    /// Capacity in percent, at or below which the state is set to warning
+    #[serde(default = "BatteryConfig::default_warning")]
+    pub warning: f64,
+
+    /// Capacity in percent, at or below which the state is set to critical
+    #[serde(default = "BatteryConfig::default_critical")]
+    pub critical: f64,
+
+    /// Percentage points the capacity has to rise above a threshold before the
+    /// state is relaxed again.
+    #[serde(default = "BatteryConfig::default_hysteresis")]
+    pub hysteresis: f64,
+
+    /// Minimum time a new state has to persist before it is displayed.
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub min_dwell: Option<Duration>,
+
+    /// How to average the power draw for `{power_avg}`.
+    #[serde(default = "BatteryConfig::default_power_average")]
+    pub power_average: Smoothing,
 }
 
 impl BatteryConfig {
@@ -411,6 +442,26 @@ impl BatteryConfig {
     fn default_upower() -> bool {
         false
     }
+
+    fn default_info() -> f64 {
+        60.
+    }
+
+    fn default_warning() -> f64 {
+        30.
+    }
+
+    fn default_critical() -> f64 {
+        15.
+    }
+
+    fn default_hysteresis() -> f64 {
+        0.
+    }
+
+    fn default_power_average() -> Smoothing {
+        Smoothing::Window(6)
+    }
 }
 
 impl ConfigBlock for Battery {
@@ -447,9 +498,13 @@ impl ConfigBlock for Battery {
                 out.monitor(id.clone(), update_request);
                 Box::new(out)
             },
-            BatteryDriver::Sysfs => Box::new(PowerSupplyDevice::from_device(&block_config.device)?),
+            BatteryDriver::Sysfs => Box::new(PowerSupplyDevice::from_device(
+                &config.host_path("/sys/class/power_supply"),
+                &block_config.device,
+            )?),
         };
 
+        let clock = config.clock.clone();
         Ok(Battery {
             id,
             update_interval: block_config.interval,
@@ -457,6 +512,12 @@ impl ConfigBlock for Battery {
             device,
             format: FormatTemplate::from_string(&format)?,
             driver,
+            thresholds: Thresholds::new(State::Good, block_config.hysteresis, block_config.min_dwell, clock)
+                .with_level(State::Info, Trigger::AtMost, block_config.info)
+                .with_level(State::Warning, Trigger::AtMost, block_config.warning)
+                .with_level(State::Critical, Trigger::AtMost, block_config.critical),
+            power_average: Average::new(block_config.power_average),
+            values: HashMap::new(),
         })
     }
 }
@@ -471,6 +532,15 @@ impl Block for Battery {
             self.output.set_icon("bat_full");
             self.output.set_text("".to_string());
             self.output.set_state(State::Good);
+            let percentage = match self.device.capacity() {
+                Ok(capacity) => format!("{}", capacity),
+                Err(_) => "×".into(),
+            };
+            self.power_average.reset();
+            self.values = map_to_owned!("{percentage}" => percentage,
+                                        "{time}" => "",
+                                        "{power}" => "",
+                                        "{power_avg}" => "");
         } else {
             let capacity = self.device.capacity();
             let percentage = match capacity {
@@ -481,14 +551,19 @@ impl Block for Battery {
                 Ok(time) => format!("{}:{:02}", time / 60, time % 60),
                 Err(_) => "×".into(),
             };
-            let power = match self.device.power_consumption() {
-                Ok(power) => format!("{:.2}", power as f64 / 1000.0 / 1000.0),
-                Err(_) => "×".into(),
+            let (power, power_avg) = match self.device.power_consumption() {
+                Ok(power) => {
+                    let watts = power as f64 / 1000.0 / 1000.0;
+                    (format!("{:.2}", watts), format!("{:.2}", self.power_average.add(watts)))
+                }
+                Err(_) => ("×".into(), "×".into()),
             };
             let values = map!("{percentage}" => percentage,
                               "{time}" => time,
-                              "{power}" => power);
+                              "{power}" => power,
+                              "{power_avg}" => power_avg);
             self.output.set_text(self.format.render_static_str(&values)?);
+            self.values = values.into_iter().map(|(k, v)| (k.to_owned(), v)).collect();
 
             // Check if the battery is in charging mode and change the state to Good.
             // Otherwise, adjust the state depeding the power percentance.
@@ -496,10 +571,7 @@ impl Block for Battery {
                 "Charging" => { self.output.set_state(State::Good); },
                 _ =>
                     { self.output.set_state(match capacity {
-                    Ok(0...15) => State::Critical,
-                    Ok(16...30) => State::Warning,
-                    Ok(31...60) => State::Info,
-                    Ok(61...100) => State::Good,
+                    Ok(capacity) if capacity <= 100 => self.thresholds.state(capacity as f64),
                     _ => State::Warning,
                     });
                 }
@@ -522,6 +594,10 @@ impl Block for Battery {
         vec![&self.output]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
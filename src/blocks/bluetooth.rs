@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -106,7 +107,9 @@ impl BluetoothDevice {
                 .block_error("bluetooth", "Failed to build D-Bus method.")?;
 
         // Swallow errors rather than nuke the bar.
-        let _ = self.con.send(msg);
+        if self.con.send(msg).is_err() {
+            warn!("failed to send {} to {}", method, self.path);
+        }
         Ok(())
     }
 
@@ -147,6 +150,9 @@ pub struct Bluetooth {
     id: String,
     output: ButtonWidget,
     device: BluetoothDevice,
+    /// Whether the device was connected at the last update, and its battery level
+    connected: bool,
+    battery: Option<u8>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -173,6 +179,8 @@ impl ConfigBlock for Bluetooth {
                 _ => "bluetooth",
             }),
             device,
+            connected: false,
+            battery: None,
         })
     }
 }
@@ -184,6 +192,8 @@ impl Block for Bluetooth {
 
     fn update(&mut self) -> Result<Option<Duration>> {
         let connected = self.device.connected();
+        self.connected = connected;
+        self.battery = self.device.battery();
         self.output.set_text(match connected {
             true => "".to_string(),
             false => " ×".to_string(),
@@ -223,4 +233,12 @@ impl Block for Bluetooth {
     fn view(&self) -> Vec<&I3BarWidget> {
         vec![&self.output]
     }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        let mut values = map_to_owned!("{connected}" => format!("{}", self.connected));
+        if let Some(battery) = self.battery {
+            values.insert("{battery}".to_owned(), format!("{}", battery));
+        }
+        values
+    }
 }
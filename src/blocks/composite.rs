@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chan::Sender;
+use regex::{Captures, Regex};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+use super::script::{parse_state, sandboxed_engine};
+use crate::block::{Block, ConfigBlock};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::registry;
+use crate::scheduler::Task;
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::button::ButtonWidget;
+
+use uuid::Uuid;
+
+lazy_static! {
+    /// A placeholder of another block, e.g. `{cpu.utilization}`
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{(\w+)\.(\w+)\}").unwrap();
+}
+
+pub struct Composite {
+    id: String,
+    update_interval: Duration,
+    output: ButtonWidget,
+    format: String,
+    text: String,
+    engine: Engine,
+    state: Option<AST>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CompositeConfig {
+    /// Update interval in seconds
+    #[serde(default = "CompositeConfig::default_interval", deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+
+    /// Format string with placeholders of other blocks, e.g. `{cpu.utilization}`
+    pub format: String,
+
+    /// Expression over the placeholders of other blocks that gives the state of the block
+    pub state: Option<String>,
+}
+
+impl CompositeConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(5)
+    }
+}
+
+/// A placeholder value as a number if it is one, so that expressions can compare it.
+fn to_dynamic(value: &str) -> Dynamic {
+    let trimmed = value.trim();
+    if let Ok(int) = trimmed.parse::<i64>() {
+        Dynamic::from_int(int)
+    } else if let Ok(float) = trimmed.parse::<f64>() {
+        Dynamic::from_float(float)
+    } else {
+        Dynamic::from(value.to_owned())
+    }
+}
+
+impl ConfigBlock for Composite {
+    type Config = CompositeConfig;
+
+    fn new(block_config: Self::Config, config: Config, _tx_update_request: Sender<Task>) -> Result<Self> {
+        let engine = sandboxed_engine();
+        let state = match block_config.state {
+            Some(ref state) => Some(engine.compile(state).map_err(|e| {
+                BlockError("composite".to_owned(), format!("failed to compile state expression: {}", e))
+            })?),
+            None => None,
+        };
+
+        let id = Uuid::new_v4().simple().to_string();
+        Ok(Composite {
+            output: ButtonWidget::new(config, &id),
+            id,
+            update_interval: block_config.interval,
+            format: block_config.format,
+            text: String::new(),
+            engine,
+            state,
+        })
+    }
+}
+
+impl Composite {
+    /// Fills in the format string, with `?` for values that haven't been published (yet).
+    fn render(&self, values: &HashMap<String, HashMap<String, String>>) -> String {
+        PLACEHOLDER
+            .replace_all(&self.format, |captures: &Captures| {
+                values
+                    .get(&captures[1])
+                    .and_then(|values| values.get(&captures[2]))
+                    .map_or("?", String::as_str)
+                    .to_owned()
+            })
+            .into_owned()
+    }
+
+    /// Evaluates the state expression, with every block being a map of its values.
+    fn evaluate_state(&self, state: &AST, values: &HashMap<String, HashMap<String, String>>) -> Result<State> {
+        let mut scope = Scope::new();
+        for (instance, values) in values {
+            let map: Map = values
+                .iter()
+                .map(|(key, value)| (key.as_str().into(), to_dynamic(value)))
+                .collect();
+            scope.push_constant(instance.as_str(), map);
+        }
+
+        let state = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, state)
+            .map_err(|e| BlockError("composite".to_owned(), format!("state expression failed: {}", e)))?;
+        if state.is_unit() {
+            return Ok(State::Idle);
+        }
+        parse_state(&state.to_string())
+            .block_error("composite", &format!("state expression gave unknown state {}", state))
+    }
+}
+
+impl Block for Composite {
+    fn update(&mut self) -> Result<Option<Duration>> {
+        let values = registry::all();
+        self.text = self.render(&values);
+        self.output.set_text(self.text.clone());
+
+        let state = match self.state {
+            Some(ref state) => self.evaluate_state(state, &values).unwrap_or_else(|e| {
+                warn!("{:?}", e);
+                State::Critical
+            }),
+            None => State::Idle,
+        };
+        self.output.set_state(state);
+
+        Ok(Some(self.update_interval))
+    }
+
+    fn view(&self) -> Vec<&I3BarWidget> {
+        vec![&self.output]
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        values.insert("{text}".to_owned(), self.text.clone());
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chan;
+
+    use super::Composite;
+    use crate::block::{Block, ConfigBlock};
+    use crate::registry;
+    use crate::testing::{config, render_on_fixture};
+    use crate::widget::State;
+
+    fn composite(block_config: &str) -> Composite {
+        let (tx_update_request, _rx_update_request) = chan::r#async();
+        Composite::new(::toml::from_str(block_config).unwrap(), config(""), tx_update_request).unwrap()
+    }
+
+    fn text(block: &Composite) -> String {
+        block.view()[0].get_rendered()["full_text"].as_str().unwrap().trim().to_owned()
+    }
+
+    #[test]
+    fn values_of_other_blocks_are_shown() {
+        // Publishes the load of the fixture, 1.50 0.75 0.25, as block `load`
+        render_on_fixture("load", "");
+        let mut block = composite(
+            r#"
+            format = "{load.1m}/{load.15m} {nothing.x}"
+            state = ''' if load["1m"] > 1.0 { "Warning" } '''
+            "#,
+        );
+        block.update().unwrap();
+        assert_eq!(text(&block), "1.50/0.25 ?");
+        assert_eq!(block.state(), State::Warning);
+        assert_eq!(block.placeholders()["{text}"], "1.50/0.25 ?");
+    }
+
+    #[test]
+    fn state_without_a_result_is_idle_and_failures_are_critical() {
+        let mut values = ::std::collections::HashMap::new();
+        values.insert("{value}".to_owned(), "5".to_owned());
+        registry::publish("composite_state", values);
+
+        let mut block = composite(
+            r#"
+            format = "{composite_state.value}"
+            state = ''' if composite_state.value > 10 { "Critical" } '''
+            "#,
+        );
+        block.update().unwrap();
+        assert_eq!(block.state(), State::Idle);
+
+        let mut block = composite(
+            r#"
+            format = "x"
+            state = ''' composite_state.value.nothing() '''
+            "#,
+        );
+        block.update().unwrap();
+        assert_eq!(block.state(), State::Critical);
+
+        let mut block = composite(
+            r#"
+            format = "x"
+            state = ''' "Unknown" '''
+            "#,
+        );
+        block.update().unwrap();
+        assert_eq!(block.state(), State::Critical);
+    }
+}
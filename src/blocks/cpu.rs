@@ -1,30 +1,35 @@
 use chan::Sender;
 use crate::scheduler::Task;
+use std::collections::HashMap;
 use std::time::Duration;
 
 use crate::block::{Block, ConfigBlock};
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::{deserialize_duration, deserialize_opt_duration};
 use crate::errors::*;
+use crate::rate::{Average, Counter, Smoothing};
+use crate::threshold::{Thresholds, Trigger};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::text::TextWidget;
 
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::path::PathBuf;
 
 use uuid::Uuid;
 
 pub struct Cpu {
     utilization: TextWidget,
-    prev_idle: u64,
-    prev_non_idle: u64,
+    idle: Counter,
+    total: Counter,
+    average: Average,
     id: String,
     update_interval: Duration,
-    minimum_info: u64,
-    minimum_warning: u64,
-    minimum_critical: u64,
+    thresholds: Thresholds,
     frequency: bool,
+    values: HashMap<String, String>,
+    proc_path: PathBuf,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -49,6 +54,18 @@ pub struct CpuConfig {
     /// Display frequency
     #[serde(default = "CpuConfig::default_frequency")]
     pub frequency: bool,
+
+    /// How far usage has to drop below a threshold before the state is relaxed again
+    #[serde(default = "CpuConfig::default_hysteresis")]
+    pub hysteresis: f64,
+
+    /// Minimum time a new state has to persist before it is displayed
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub min_dwell: Option<Duration>,
+
+    /// How to smooth the usage, e.g. `"exponential 0.3"`
+    #[serde(default)]
+    pub smoothing: Smoothing,
 }
 
 impl CpuConfig {
@@ -71,34 +88,45 @@ impl CpuConfig {
     fn default_frequency() -> bool {
         false
     }
+
+    fn default_hysteresis() -> f64 {
+        0.
+    }
 }
 
 impl ConfigBlock for Cpu {
     type Config = CpuConfig;
 
     fn new(block_config: Self::Config, config: Config, _tx_update_request: Sender<Task>) -> Result<Self> {
+        let proc_path = config.host_path("/proc");
+        let clock = config.clock.clone();
         Ok(Cpu {
             id: Uuid::new_v4().simple().to_string(),
             update_interval: block_config.interval,
             utilization: TextWidget::new(config).with_icon("cpu"),
-            prev_idle: 0,
-            prev_non_idle: 0,
-            minimum_info: block_config.info,
-            minimum_warning: block_config.warning,
-            minimum_critical: block_config.critical,
+            proc_path,
+            // The first update shows the usage since boot
+            idle: Counter::starting_at(0),
+            total: Counter::starting_at(0),
+            average: Average::new(block_config.smoothing),
+            thresholds: Thresholds::new(State::Idle, block_config.hysteresis, block_config.min_dwell, clock)
+                .with_level(State::Info, Trigger::Above, block_config.info as f64)
+                .with_level(State::Warning, Trigger::Above, block_config.warning as f64)
+                .with_level(State::Critical, Trigger::Above, block_config.critical as f64),
             frequency: block_config.frequency,
+            values: HashMap::new(),
         })
     }
 }
 
 impl Block for Cpu {
     fn update(&mut self) -> Result<Option<Duration>> {
-        let f = File::open("/proc/stat").block_error("cpu", "Your system doesn't support /proc/stat")?;
+        let f = File::open(self.proc_path.join("stat")).block_error("cpu", "Your system doesn't support /proc/stat")?;
         let f = BufReader::new(f);
 
         let mut freq: f32 = 0.0;
         if self.frequency {
-            let freq_file = File::open("/proc/cpuinfo").block_error("cpu", "failed to read /proc/cpuinfo")?;
+            let freq_file = File::open(self.proc_path.join("cpuinfo")).block_error("cpu", "failed to read /proc/cpuinfo")?;
             let freq_file_content = BufReader::new(freq_file);
             let mut cores = 0;
             // read frequency of each cpu and calculate the average which we will display
@@ -129,37 +157,27 @@ impl Block for Cpu {
                                 data[6] + // softirq
                                 data[7]; // steal
 
-                let prev_total = self.prev_idle + self.prev_non_idle;
-                let total = idle + non_idle;
-
-                // This check is needed because the new values may be reset, for
-                // example after hibernation.
-
-                let (total_delta, idle_delta) = if prev_total < total && self.prev_idle <= idle {
-                    (total - prev_total, idle - self.prev_idle)
-                } else {
-                    (1, 1)
-                };
-
-                utilization = (((total_delta - idle_delta) as f64 / total_delta as f64) * 100.) as u64;
-
-                self.prev_idle = idle;
-                self.prev_non_idle = non_idle;
+                // The counters may be reset, for example after hibernation
+                match (self.total.delta(idle + non_idle), self.idle.delta(idle)) {
+                    (Some(total_delta), Some(idle_delta)) if total_delta > 0 => {
+                        let usage = (total_delta - idle_delta.min(total_delta)) as f64 / total_delta as f64 * 100.;
+                        utilization = self.average.add(usage) as u64;
+                    }
+                    _ => self.average.reset(),
+                }
             }
         }
 
-        self.utilization.set_state(match utilization {
-            x if x > self.minimum_critical => State::Critical,
-            x if x > self.minimum_warning => State::Warning,
-            x if x > self.minimum_info => State::Info,
-            _ => State::Idle,
-        });
+        let state = self.thresholds.state(utilization as f64);
+        self.utilization.set_state(state);
+        let frequency = format!("{:.*}", 1, freq);
         if self.frequency {
-            let frequency = format!("{:.*}", 1, freq);
             self.utilization.set_text(format!("{:02}% {}GHz", utilization, frequency));
         } else {
             self.utilization.set_text(format!("{:02}%", utilization));
         }
+        self.values = map_to_owned!("{utilization}" => format!("{:02}", utilization),
+                                    "{frequency}" => frequency);
         Ok(Some(self.update_interval))
     }
 
@@ -167,6 +185,10 @@ impl Block for Cpu {
         vec![&self.utilization]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
@@ -1,18 +1,28 @@
 use std::time::{Duration, Instant};
-use std::process::Command;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Stdio;
+use std::io::{BufRead, BufReader};
 use std::iter::{Cycle, Peekable};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::vec;
-use std::env;
 use chan::Sender;
+use dbus::{BusType, Connection, ConnectionItem, Message};
+use inotify::{Inotify, WatchDescriptor, WatchMask};
 
 use crate::block::{Block, ConfigBlock};
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::{deserialize_duration, deserialize_expanded, deserialize_expanded_command};
 use crate::errors::*;
 use crate::widgets::button::ButtonWidget;
-use crate::widget::I3BarWidget;
+use crate::widget::{I3BarWidget, State};
 use crate::input::I3BarEvent;
+use crate::persist;
 use crate::scheduler::Task;
+use crate::subprocess;
 
 use uuid::Uuid;
 
@@ -23,6 +33,16 @@ pub struct Custom {
     command: Option<String>,
     on_click: Option<String>,
     cycle: Option<Peekable<Cycle<vec::IntoIter<String>>>>,
+    cycle_len: usize,
+    cycle_position: usize,
+    json: bool,
+    timeout: Duration,
+    /// Last line written by a `persistent` command, if that's what the block shows
+    last_line: Option<Arc<Mutex<Option<String>>>>,
+    persistent: Option<Persistent>,
+    /// Text shown since the last update
+    text: String,
+    instance: String,
     tx_update_request: Sender<Task>,
 }
 
@@ -34,25 +54,354 @@ pub struct CustomConfig {
     pub interval: Duration,
 
     /// Shell Command to execute & display
+    #[serde(default, deserialize_with = "deserialize_expanded_command")]
     pub command: Option<String>,
 
     /// Command to execute when the button is clicked
+    #[serde(default, deserialize_with = "deserialize_expanded_command")]
     pub on_click: Option<String>,
 
     /// Commands to execute and change when the button is clicked
+    #[serde(default, deserialize_with = "deserialize_expanded_command")]
     pub cycle: Option<Vec<String>>,
+
+    /// Parse the output of the command as JSON, see `CustomOutput`
+    #[serde(default = "CustomConfig::default_json")]
+    pub json: bool,
+
+    /// Keep the command running and show each line it prints, instead of running it every `interval`
+    #[serde(default = "CustomConfig::default_persistent")]
+    pub persistent: bool,
+
+    /// Time after which `command` and `cycle` commands are killed
+    #[serde(default = "CustomConfig::default_timeout", deserialize_with = "deserialize_duration")]
+    pub timeout: Duration,
+
+    /// Files or directories that make the block update when they change
+    #[serde(default, deserialize_with = "deserialize_expanded")]
+    pub watch_files: Vec<String>,
+
+    /// D-Bus signals that make the block update when they arrive
+    #[serde(default)]
+    pub dbus_signals: Vec<DbusSignal>,
+}
+
+/// A D-Bus signal, e.g. `{ bus = "system", interface = "org.freedesktop.NetworkManager",
+/// member = "StateChanged" }`. Keys that are left out match any value.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DbusSignal {
+    #[serde(default = "DbusSignal::default_bus")]
+    pub bus: Bus,
+    pub sender: Option<String>,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    Session,
+    System,
+}
+
+impl DbusSignal {
+    fn default_bus() -> Bus {
+        Bus::Session
+    }
+
+    fn match_rule(&self) -> String {
+        let mut rule = "type='signal'".to_owned();
+        for &(key, ref value) in &[
+            ("sender", &self.sender),
+            ("path", &self.path),
+            ("interface", &self.interface),
+            ("member", &self.member),
+        ] {
+            if let Some(ref value) = *value {
+                rule.push_str(&format!(",{}={}", key, quote_match_value(value)));
+            }
+        }
+        rule
+    }
+}
+
+/// Quotes a value of a D-Bus match rule. Nothing is special inside the quotes, so a quote in
+/// the value ends them, is added escaped and opens them again.
+fn quote_match_value(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// What a command prints when `json` is enabled, e.g.
+/// `{"icon": "music", "state": "Info", "text": "Playing", "short_text": "P"}`
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CustomOutput {
+    #[serde(default)]
+    text: String,
+    short_text: Option<String>,
+    /// Name of an icon in the icon set
+    #[serde(default)]
+    icon: String,
+    state: Option<State>,
 }
 
 impl CustomConfig {
     fn default_interval() -> Duration {
         Duration::from_secs(10)
     }
+
+    fn default_json() -> bool {
+        false
+    }
+
+    fn default_persistent() -> bool {
+        false
+    }
+
+    fn default_timeout() -> Duration {
+        subprocess::DEFAULT_TIMEOUT
+    }
+}
+
+/// Changes that are this close together only cause one update, which is asked for once they
+/// have settled
+const TRIGGER_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Asks for an update of the block `id` whenever one of `paths` changes. Files are watched
+/// through their directory, so that they are still followed after an editor replaced them.
+fn watch_files(paths: &[String], id: String, tx_update_request: Sender<Task>) -> Result<()> {
+    let mut inotify = Inotify::init().block_error("custom", "failed to start inotify")?;
+    let mask = WatchMask::MODIFY | WatchMask::CLOSE_WRITE | WatchMask::CREATE | WatchMask::DELETE
+        | WatchMask::MOVED_TO | WatchMask::MOVED_FROM;
+
+    // For each watched directory the names of the files in it that are watched, or `None`
+    // if any change in it counts
+    let mut watches: HashMap<WatchDescriptor, Option<Vec<OsString>>> = HashMap::new();
+    for path in paths {
+        let path = Path::new(path);
+        let (dir, name) = match (path.is_dir(), path.parent(), path.file_name()) {
+            (false, Some(dir), Some(name)) => {
+                let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+                (dir, Some(name.to_owned()))
+            }
+            _ => (path, None),
+        };
+        let wd = inotify
+            .add_watch(dir, mask)
+            .block_error("custom", &format!("failed to watch {}", dir.display()))?;
+        let names = watches.entry(wd).or_insert_with(|| Some(Vec::new()));
+        match (names.as_mut(), name) {
+            (Some(names), Some(name)) => names.push(name),
+            _ => *names = None,
+        }
+    }
+
+    thread::spawn(move || {
+        let mut buffer = [0; 1024];
+        loop {
+            let changed = match inotify.read_events_blocking(&mut buffer) {
+                Ok(mut events) => events.any(|event| match watches.get(&event.wd) {
+                    Some(&Some(ref names)) => event.name.map_or(false, |name| names.iter().any(|n| n == name)),
+                    Some(&None) => true,
+                    None => false,
+                }),
+                Err(e) => {
+                    error!("failed to read inotify events, no longer watching files: {}", e);
+                    return;
+                }
+            };
+
+            if changed {
+                thread::sleep(TRIGGER_DEBOUNCE);
+                // The update covers whatever else changed in the meantime
+                loop {
+                    match inotify.read_events(&mut buffer) {
+                        Ok(mut events) => if events.next().is_none() {
+                            break;
+                        },
+                        Err(e) => {
+                            error!("failed to read inotify events, no longer watching files: {}", e);
+                            return;
+                        }
+                    }
+                }
+                tx_update_request.send(Task {
+                    id: id.clone(),
+                    update_time: Instant::now(),
+                });
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Asks for an update of the block `id` whenever `signal` arrives.
+fn watch_dbus_signal(signal: &DbusSignal, id: String, tx_update_request: Sender<Task>) {
+    let rule = signal.match_rule();
+    let member = signal.member.clone();
+    let bus = match signal.bus {
+        Bus::Session => BusType::Session,
+        Bus::System => BusType::System,
+    };
+
+    thread::spawn(move || {
+        let c = match Connection::get_private(bus) {
+            Ok(c) => c,
+            Err(e) => {
+                error!("failed to connect to the D-Bus {:?} bus: {}", bus, e);
+                return;
+            }
+        };
+        if let Err(e) = c.add_match(&rule) {
+            error!("failed to add D-Bus match rule {:?}: {}", rule, e);
+            return;
+        }
+
+        loop {
+            for item in c.iter(100_000) {
+                if let ConnectionItem::Signal(message) = item {
+                    if is_own_name_signal(&message, member.as_ref().map(String::as_str)) {
+                        continue;
+                    }
+                    debug!("{:?} matched {:?}", message, rule);
+
+                    thread::sleep(TRIGGER_DEBOUNCE);
+                    // The update covers the signals that arrived in the meantime
+                    while let Some(ConnectionItem::Signal(_)) = c.iter(0).next() {}
+                    tx_update_request.send(Task {
+                        id: id.clone(),
+                        update_time: Instant::now(),
+                    });
+                }
+            }
+        }
+    });
+}
+
+/// Whether `message` is one of the signals the bus sends every connection about its own
+/// names, e.g. `NameAcquired` right after connecting, which arrive whatever the match rule
+/// says. They still count if the signal asks for exactly that `member`.
+fn is_own_name_signal(message: &Message, member: Option<&str>) -> bool {
+    let from_bus = message.sender().map_or(false, |sender| &*sender == "org.freedesktop.DBus");
+    let name = message.member().map(|name| name.to_string());
+    match name {
+        Some(name) => from_bus && (name == "NameAcquired" || name == "NameLost") && member != Some(name.as_str()),
+        None => false,
+    }
+}
+
+/// A `persistent` command that exits is started again after this, doubled for every
+/// time it exits quickly again
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Handle of a `persistent` command, which stops it and its restarts when dropped
+struct Persistent {
+    stopped: Arc<AtomicBool>,
+    /// Id of the running child, locked while it is started or stopped
+    child: Arc<Mutex<Option<u32>>>,
+}
+
+impl Drop for Persistent {
+    fn drop(&mut self) {
+        let child = self.child.lock().unwrap();
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(id) = *child {
+            subprocess::stop_long_running(id);
+        }
+    }
+}
+
+/// Runs `command` and restarts it whenever it exits, storing each line it prints in
+/// `last_line` and asking for an update of the block `id`, until the returned handle
+/// is dropped.
+fn run_persistent(
+    command: String,
+    id: String,
+    last_line: Arc<Mutex<Option<String>>>,
+    tx_update_request: Sender<Task>,
+) -> Persistent {
+    let persistent = Persistent {
+        stopped: Arc::new(AtomicBool::new(false)),
+        child: Arc::new(Mutex::new(None)),
+    };
+    let stopped = persistent.stopped.clone();
+    let running = persistent.child.clone();
+    thread::spawn(move || {
+        let mut delay = RESTART_DELAY;
+        loop {
+            let started = Instant::now();
+            let spawned = {
+                let mut running = running.lock().unwrap();
+                if stopped.load(Ordering::SeqCst) {
+                    return;
+                }
+                let spawned = subprocess::spawn_long_running(
+                    subprocess::command(&subprocess::shell(), &["-c", &command])
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::piped()),
+                );
+                *running = spawned.as_ref().ok().map(|child| child.id());
+                spawned
+            };
+            match spawned {
+                Ok(mut child) => {
+                    if let Some(stdout) = child.stdout.take() {
+                        for line in BufReader::new(stdout).lines() {
+                            let line = match line {
+                                Ok(line) => line,
+                                Err(e) => {
+                                    warn!("failed to read output of {:?}: {}", command, e);
+                                    break;
+                                }
+                            };
+                            *last_line.lock().unwrap() = Some(line.trim().to_owned());
+                            tx_update_request.send(Task {
+                                id: id.clone(),
+                                update_time: Instant::now(),
+                            });
+                        }
+                    }
+                    let status = child.wait();
+                    *running.lock().unwrap() = None;
+                    subprocess::forget_long_running(child.id());
+                    if stopped.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    match status {
+                        Ok(status) => warn!("{:?} exited with {}", command, status),
+                        Err(e) => warn!("failed to wait for {:?}: {}", command, e),
+                    }
+                }
+                Err(e) => warn!("failed to run {:?}: {}", command, e),
+            }
+
+            // A command that ran for a while before exiting starts over with the shortest delay
+            if started.elapsed() > MAX_RESTART_DELAY {
+                delay = RESTART_DELAY;
+            }
+            info!("restarting {:?} in {:?}", command, delay);
+            thread::sleep(delay);
+            delay = (delay * 2).min(MAX_RESTART_DELAY);
+        }
+    });
+    persistent
 }
 
 impl ConfigBlock for Custom {
     type Config = CustomConfig;
 
     fn new(block_config: Self::Config, config: Config, tx: Sender<Task>) -> Result<Self> {
+        if block_config.persistent && block_config.cycle.is_some() {
+            return Err(BlockError(
+                "custom".to_owned(),
+                "`persistent` can't be used with `cycle`".to_owned(),
+            ));
+        }
+
         let mut custom = Custom {
             id: Uuid::new_v4().simple().to_string(),
             update_interval: block_config.interval,
@@ -60,42 +409,120 @@ impl ConfigBlock for Custom {
             command: None,
             on_click: None,
             cycle: None,
+            cycle_len: 0,
+            cycle_position: 0,
+            json: block_config.json,
+            timeout: block_config.timeout,
+            last_line: None,
+            persistent: None,
+            text: String::new(),
+            instance: config.instance.clone(),
             tx_update_request: tx,
         };
         custom.output = ButtonWidget::new(config, &custom.id);
 
+        if !block_config.watch_files.is_empty() {
+            watch_files(&block_config.watch_files, custom.id.clone(), custom.tx_update_request.clone())?;
+        }
+        for signal in &block_config.dbus_signals {
+            watch_dbus_signal(signal, custom.id.clone(), custom.tx_update_request.clone());
+        }
+
         if let Some(on_click) = block_config.on_click {
             custom.on_click = Some(on_click.to_string())
         };
 
         if let Some(cycle) = block_config.cycle {
-            custom.cycle = Some(cycle.into_iter().cycle().peekable());
+            custom.cycle_len = cycle.len();
+            let mut cycle = cycle.into_iter().cycle().peekable();
+
+            // Pick up where the cycle was left, unless the list got shorter in the meantime
+            let position = persist::load::<usize>(&custom.instance).unwrap_or(0);
+            if position < custom.cycle_len {
+                for _ in 0..position {
+                    cycle.next();
+                }
+                custom.cycle_position = position;
+            }
+
+            custom.cycle = Some(cycle);
             return Ok(custom);
         };
 
         if let Some(command) = block_config.command {
+            if block_config.persistent {
+                let last_line = Arc::new(Mutex::new(None));
+                custom.persistent = Some(run_persistent(
+                    command.clone(),
+                    custom.id.clone(),
+                    last_line.clone(),
+                    custom.tx_update_request.clone(),
+                ));
+                custom.last_line = Some(last_line);
+            }
             custom.command = Some(command.to_string())
+        } else if block_config.persistent {
+            return Err(BlockError(
+                "custom".to_owned(),
+                "`persistent` needs a `command`".to_owned(),
+            ));
         };
 
         Ok(custom)
     }
 }
 
+impl Custom {
+    /// Shows the output of the command, either as is or taken apart if it is JSON.
+    fn set_output(&mut self, output: &str) {
+        if !self.json {
+            self.text = output.to_owned();
+            self.output.set_text(output);
+            return;
+        }
+
+        match serde_json::from_str::<CustomOutput>(output) {
+            Ok(output) => {
+                self.output.set_icon(&output.icon);
+                self.output.set_short_text(output.short_text);
+                self.output.set_state(output.state.unwrap_or(State::Idle));
+                self.text = output.text.clone();
+                self.output.set_text(output.text);
+            }
+            Err(e) => {
+                warn!("invalid JSON output {:?}: {}", output, e);
+                self.output.set_icon("");
+                self.output.set_short_text(None);
+                self.output.set_state(State::Critical);
+                self.text = "invalid JSON".to_owned();
+                self.output.set_text("invalid JSON");
+            }
+        }
+    }
+}
+
 impl Block for Custom {
     fn update(&mut self) -> Result<Option<Duration>> {
+        if let Some(last_line) = self.last_line.clone() {
+            // Nothing to show until the command printed its first line
+            if let Some(line) = last_line.lock().unwrap().clone() {
+                self.set_output(&line);
+            }
+            // The command asks for updates itself
+            return Ok(None);
+        }
+
         let command_str = self.cycle
             .as_mut()
             .map(|c| c.peek().cloned().unwrap_or_else(|| "".to_owned()))
             .or_else(|| self.command.clone())
             .unwrap_or_else(|| "".to_owned());
 
-        let output = Command::new(env::var("SHELL").unwrap_or("sh".to_owned()))
-            .args(&["-c", &command_str])
-            .output()
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
-            .unwrap_or_else(|e| e.description().to_owned());
+        let output = subprocess::run_shell(&command_str, self.timeout)
+            .map(|o| o.stdout.trim().to_owned())
+            .unwrap_or_else(|e| e.to_string());
 
-        self.output.set_text(output);
+        self.set_output(&output);
 
         Ok(Some(self.update_interval))
     }
@@ -116,13 +543,16 @@ impl Block for Custom {
         let mut update = false;
 
         if let Some(ref on_click) = self.on_click {
-            Command::new(env::var("SHELL").unwrap_or_else(|_|"sh".to_owned()))
-                    .args(&["-c", on_click]).output().ok();
+            if let Err(e) = subprocess::spawn_shell_async(on_click) {
+                warn!("failed to run {:?}: {}", on_click, e);
+            }
             update = true;
         }
 
         if let Some(ref mut cycle) = self.cycle {
             cycle.next();
+            self.cycle_position = (self.cycle_position + 1) % self.cycle_len.max(1);
+            persist::save(&self.instance, &self.cycle_position);
             update = true;
         }
 
@@ -136,7 +566,145 @@ impl Block for Custom {
         Ok(())
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        map_to_owned!("{text}" => self.text)
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    use chan::{self, Receiver};
+    use dbus::{BusType, Connection, NameFlag};
+
+    use super::{watch_dbus_signal, watch_files, Custom, CustomConfig, DbusSignal, TRIGGER_DEBOUNCE};
+    use crate::block::{Block, ConfigBlock};
+    use crate::scheduler::Task;
+    use crate::testing::{config, private_session_bus, scratch_dir};
+    use crate::widget::State;
+
+    /// Whether an update is asked for within `wait`.
+    fn requested_within(rx_update_request: &Receiver<Task>, wait: Duration) -> bool {
+        let timeout = chan::after(wait);
+        let mut requested = false;
+        chan_select! {
+            rx_update_request.recv() => requested = true,
+            timeout.recv() => {},
+        }
+        requested
+    }
+
+    #[test]
+    fn match_rule_values_are_quoted() {
+        let signal: DbusSignal = ::toml::from_str(
+            r#"
+            path = "/org/example"
+            member = "it's"
+            "#,
+        ).unwrap();
+        assert_eq!(signal.match_rule(), r"type='signal',path='/org/example',member='it'\''s'");
+    }
+
+    #[test]
+    fn file_changes_close_together_cause_one_update() {
+        let dir = scratch_dir("watch_files");
+        let file = dir.join("watched");
+        fs::write(&file, "0").unwrap();
+        let (tx_update_request, rx_update_request) = chan::r#async();
+        watch_files(&[file.display().to_string()], "custom".to_owned(), tx_update_request).unwrap();
+
+        for i in 1..5 {
+            fs::write(&file, i.to_string()).unwrap();
+            thread::sleep(TRIGGER_DEBOUNCE / 10);
+        }
+        assert!(requested_within(&rx_update_request, Duration::from_secs(5)));
+        assert!(!requested_within(&rx_update_request, TRIGGER_DEBOUNCE * 2));
+    }
+
+    #[test]
+    fn bus_signals_can_be_watched() {
+        private_session_bus();
+        let signal: DbusSignal = ::toml::from_str(
+            r#"
+            sender = "org.freedesktop.DBus"
+            member = "NameOwnerChanged"
+            "#,
+        ).unwrap();
+        let (tx_update_request, rx_update_request) = chan::r#async();
+        watch_dbus_signal(&signal, "custom".to_owned(), tx_update_request);
+        // Give the watch time to add its match rule
+        thread::sleep(Duration::from_millis(200));
+
+        let c = Connection::get_private(BusType::Session).unwrap();
+        c.register_name("org.example.Watched", NameFlag::DoNotQueue as u32).unwrap();
+        assert!(requested_within(&rx_update_request, Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn persistent_json_is_empty_until_the_first_line() {
+        let block_config: CustomConfig = ::toml::from_str(
+            r#"
+            command = "sleep 0.2; echo '{\"text\": \"first\", \"state\": \"Warning\"}'; sleep 10"
+            persistent = true
+            json = true
+            "#,
+        ).unwrap();
+        let (tx_update_request, rx_update_request) = chan::r#async();
+        let mut block = Custom::new(block_config, config(""), tx_update_request).unwrap();
+
+        block.update().unwrap();
+        assert_eq!(block.view()[0].get_rendered()["full_text"].as_str().map(str::trim), Some(""));
+        assert_eq!(block.state(), State::Idle);
+
+        let timeout = chan::after(Duration::from_secs(5));
+        chan_select! {
+            rx_update_request.recv() => {},
+            timeout.recv() => panic!("the command's first line didn't arrive"),
+        }
+        block.update().unwrap();
+        assert_eq!(block.view()[0].get_rendered()["full_text"].as_str().map(str::trim), Some("first"));
+        assert_eq!(block.state(), State::Warning);
+    }
+
+    #[test]
+    fn persistent_command_is_stopped_with_the_block() {
+        let block_config: CustomConfig = ::toml::from_str(
+            r#"
+            command = "echo $$; exec sleep 30"
+            persistent = true
+            "#,
+        ).unwrap();
+        let (tx_update_request, rx_update_request) = chan::r#async();
+        let block = Custom::new(block_config, config(""), tx_update_request).unwrap();
+        assert!(requested_within(&rx_update_request, Duration::from_secs(5)));
+        let pid = block.last_line.as_ref().unwrap().lock().unwrap().clone().unwrap();
+        let proc_dir = Path::new("/proc").join(&pid);
+        assert!(proc_dir.exists());
+
+        drop(block);
+        for _ in 0..50 {
+            if !proc_dir.exists() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        panic!("the command {} still runs", pid);
+    }
+
+    #[test]
+    fn greeting_of_the_bus_does_not_count() {
+        private_session_bus();
+        let signal: DbusSignal = ::toml::from_str(r#"interface = "org.example.Nothing""#).unwrap();
+        let (tx_update_request, rx_update_request) = chan::r#async();
+        watch_dbus_signal(&signal, "custom".to_owned(), tx_update_request);
+        assert!(!requested_within(&rx_update_request, Duration::from_millis(500)));
+    }
+}
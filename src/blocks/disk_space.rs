@@ -1,46 +1,55 @@
+use std::collections::HashMap;
 use std::time::Duration;
-use std::path::Path;
+use std::str::FromStr;
+use std::path::{Path, PathBuf};
 use chan::Sender;
 use crate::scheduler::Task;
 
 use crate::block::{Block, ConfigBlock};
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::{deserialize_duration, deserialize_expanded, deserialize_opt_duration};
 use crate::errors::*;
+use crate::rate::{Average, Counter, Rate, Smoothing};
+use crate::threshold::{Thresholds, Trigger};
+use crate::unit::{self, Quantity, System, UnitSpec};
+use crate::util::read_file;
 use crate::widgets::text::TextWidget;
 use crate::widget::{I3BarWidget, State};
 
+use serde::de::{self, Deserialize, Deserializer};
 use uuid::Uuid;
 
 extern crate nix;
 
 use self::nix::sys::statvfs::statvfs;
 
-#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Unit {
-    MB,
-    GB,
-    TB,
-    TiB,
-    GiB,
-    MiB,
-    Percent
+    Percent,
+    Bytes(UnitSpec),
 }
 
-impl Unit {
-    fn bytes_in_unit(unit: Unit, bytes: u64) -> f64 {
-        match unit {
-            Unit::MB => bytes as f64 / 1000. / 1000.,
-            Unit::GB => bytes as f64 / 1000. / 1000. / 1000.,
-            Unit::TB => bytes as f64 / 1000. / 1000. / 1000. / 1000.,
-            Unit::MiB => bytes as f64 / 1024. / 1024.,
-            Unit::GiB => bytes as f64 / 1024. / 1024. / 1024.,
-            Unit::TiB => bytes as f64 / 1024. / 1024. / 1024. / 1024.,
-            Unit::Percent => bytes as f64,
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "Percent" => Ok(Unit::Percent),
+            spec => spec.parse().map(Unit::Bytes),
         }
     }
 }
 
+impl<'de> Deserialize<'de> for Unit {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
 #[derive(Copy, Clone, Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum InfoType {
@@ -58,16 +67,80 @@ pub struct DiskSpace {
     path: String,
     info_type: InfoType,
     unit: Unit,
-    warning: f64,
-    alert: f64,
+    digits: usize,
+    thresholds: Thresholds,
     show_percentage: bool,
+    io: Option<DiskIo>,
+    values: HashMap<String, String>,
+}
+
+/// Reads and writes of a block device, from its statistics in sysfs.
+struct DiskIo {
+    stat_path: PathBuf,
+    read: Rate,
+    written: Rate,
+    read_average: Average,
+    written_average: Average,
+}
+
+/// Rate of a sector count, which is an `unsigned long` in the kernel and so wraps around on 32
+/// bit systems.
+fn sector_rate() -> Rate {
+    Rate::new(Counter::new().wrapping_at(usize::max_value() as u64))
+}
+
+impl DiskIo {
+    fn new(stat_path: PathBuf, smoothing: Smoothing) -> Result<Self> {
+        let mut io = DiskIo {
+            stat_path,
+            read: sector_rate(),
+            written: sector_rate(),
+            read_average: Average::new(smoothing),
+            written_average: Average::new(smoothing),
+        };
+        io.update()?;
+        Ok(io)
+    }
+
+    /// Bytes read and written per second since the last update, or `None` while the device
+    /// is missing, e.g. because a USB disk is unplugged.
+    fn update(&mut self) -> Result<Option<(f64, f64)>> {
+        if !self.stat_path.exists() {
+            // Start over when the device is back, its counters start at 0 again
+            self.read = sector_rate();
+            self.written = sector_rate();
+            self.read_average.reset();
+            self.written_average.reset();
+            return Ok(None);
+        }
+
+        // The fields are described in Documentation/block/stat.rst of the kernel
+        let stat = read_file("disk_space", &self.stat_path)?;
+        let fields: Vec<u64> = stat.split_whitespace().filter_map(|field| field.parse().ok()).collect();
+        if fields.len() < 7 {
+            return Err(BlockError(
+                "disk_space".to_owned(),
+                format!("unexpected statistics in {}", self.stat_path.to_string_lossy()),
+            ));
+        }
+
+        // Sectors are always 512 bytes in these statistics, whatever the device uses
+        let read = self.read.update(fields[2]).map(|sectors| sectors * 512.);
+        let written = self.written.update(fields[6]).map(|sectors| sectors * 512.);
+        Ok(Some((self.read_average.add_rate(read), self.written_average.add_rate(written))))
+    }
+}
+
+fn format_rate(bytes: f64, digits: usize) -> String {
+    let (value, unit) = UnitSpec::Auto(System::Si, Quantity::Bytes).format(bytes, digits);
+    format!("{}{}/s", value, unit)
 }
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct DiskSpaceConfig {
     /// Path to collect information from
-    #[serde(default = "DiskSpaceConfig::default_path")]
+    #[serde(default = "DiskSpaceConfig::default_path", deserialize_with = "deserialize_expanded")]
     pub path: String,
 
     /// Alias that is displayed for path
@@ -78,10 +151,14 @@ pub struct DiskSpaceConfig {
     #[serde(default = "DiskSpaceConfig::default_info_type")]
     pub info_type: InfoType,
 
-    /// Unit that is used to display disk space. Options are MB, MiB, GB, GiB, TB and TiB
+    /// Unit that is used to display disk space, e.g. MB, GiB or TB, Percent, or auto/auto_iec to scale automatically
     #[serde(default = "DiskSpaceConfig::default_unit")]
     pub unit: Unit,
 
+    /// Significant digits of automatically scaled values and of the read and write rates
+    #[serde(default = "unit::default_digits", deserialize_with = "unit::deserialize_digits")]
+    pub digits: usize,
+
     /// Update interval in seconds
     #[serde(default = "DiskSpaceConfig::default_interval", deserialize_with = "deserialize_duration")]
     pub interval: Duration,
@@ -97,6 +174,34 @@ pub struct DiskSpaceConfig {
     /// Show percentage
     #[serde(default = "DiskSpaceConfig::default_show_percentage")]
     pub show_percentage: bool,
+
+    /// How far the value has to move back past a threshold before the state is relaxed again
+    #[serde(default = "DiskSpaceConfig::default_hysteresis")]
+    pub hysteresis: f64,
+
+    /// Minimum time a new state has to persist before it is displayed
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub min_dwell: Option<Duration>,
+
+    /// Block device in `/sys/class/block/` whose reads and writes are shown, e.g. `sda`
+    #[serde(default, deserialize_with = "deserialize_device")]
+    pub device: Option<String>,
+
+    /// How to smooth the read and write rates, e.g. `"window 3"`
+    #[serde(default)]
+    pub smoothing: Smoothing,
+}
+
+/// Accepts the names of devices in `/sys/class/block/`, but no paths that could lead out of it.
+fn deserialize_device<'de, D>(deserializer: D) -> ::std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let device = String::deserialize(deserializer)?;
+    if device.is_empty() || device.contains('/') || device.contains("..") {
+        return Err(de::Error::custom(format!("{:?} is not the name of a block device, e.g. \"sda\"", device)));
+    }
+    Ok(Some(device))
 }
 
 impl DiskSpaceConfig {
@@ -113,7 +218,7 @@ impl DiskSpaceConfig {
     }
 
     fn default_unit() -> Unit {
-        Unit::GB
+        Unit::Bytes(UnitSpec::Fixed(unit::GB))
     }
 
     fn default_interval() -> Duration {
@@ -131,35 +236,16 @@ impl DiskSpaceConfig {
     fn default_show_percentage() -> bool {
         false
     }
+
+    fn default_hysteresis() -> f64 {
+        0.
+    }
 }
 
 impl DiskSpace {
-    fn compute_state(&self, bytes: u64, warning: f64, alert: f64) -> State {
-        let value = if self.unit == Unit::Percent { bytes as f64 } else { Unit::bytes_in_unit(Unit::GB, bytes) };
-        match self.unit {
-            Unit::Percent => {
-                match self.info_type {
-                    InfoType::Available | InfoType::Free | InfoType::Total | InfoType::Used => if value > alert {
-                        State::Critical
-                    } else if value <= alert && value > warning {
-                        State::Warning
-                    } else {
-                        State::Idle
-                    }
-                }
-            }
-            _ => {
-                match self.info_type {
-                    InfoType::Available | InfoType::Free | InfoType::Total | InfoType::Used => if 0. <= value && value < alert {
-                        State::Critical
-                    } else if alert <= value && value < warning {
-                        State::Warning
-                    } else {
-                        State::Idle
-                    }
-                }
-            }
-        }
+    fn compute_state(&mut self, bytes: u64) -> State {
+        let value = if self.unit == Unit::Percent { bytes as f64 } else { unit::GB.convert(bytes as f64) };
+        self.thresholds.state(value)
     }
 }
 
@@ -167,17 +253,33 @@ impl ConfigBlock for DiskSpace {
     type Config = DiskSpaceConfig;
 
     fn new(block_config: Self::Config, config: Config, _tx_update_request: Sender<Task>) -> Result<Self> {
+        // A percentage is a share of the disk in use, an absolute value is the space left over.
+        let trigger = if block_config.unit == Unit::Percent {
+            Trigger::Above
+        } else {
+            Trigger::Below
+        };
         Ok(DiskSpace {
             id: Uuid::new_v4().simple().to_string(),
             update_interval: block_config.interval,
-            disk_space: TextWidget::new(config).with_text("DiskSpace"),
+            disk_space: TextWidget::new(config.clone()).with_text("DiskSpace"),
             alias: block_config.alias,
             path: block_config.path,
             info_type: block_config.info_type,
             unit: block_config.unit,
-            warning: block_config.warning,
-            alert: block_config.alert,
+            digits: block_config.digits,
+            thresholds: Thresholds::new(State::Idle, block_config.hysteresis, block_config.min_dwell, config.clock.clone())
+                .with_level(State::Warning, trigger, block_config.warning)
+                .with_level(State::Critical, trigger, block_config.alert),
             show_percentage: block_config.show_percentage,
+            io: match block_config.device {
+                Some(device) => Some(DiskIo::new(
+                    config.host_path("/sys/class/block").join(device).join("stat"),
+                    block_config.smoothing,
+                )?),
+                None => None,
+            },
+            values: HashMap::new(),
         })
     }
 }
@@ -187,66 +289,74 @@ impl Block for DiskSpace {
         let statvfs = statvfs(Path::new(self.path.as_str()))
             .block_error("disk_space", "failed to retrieve statvfs")?;
         let mut result;
-        let mut converted = 0.0f64;
-        let mut converted_str = String::new();
         let total = statvfs.blocks() * statvfs.fragment_size();
         let used = (statvfs.blocks() - statvfs.blocks_free()) * statvfs.fragment_size();
 
-        match self.info_type {
-            InfoType::Available => {
-                result = statvfs.blocks_available() * statvfs.block_size();
-                converted = Unit::bytes_in_unit(self.unit, result);
-            }
-            InfoType::Free => {
-                result = statvfs.blocks_free() * statvfs.block_size();
-                converted = Unit::bytes_in_unit(self.unit, result);
-            }
+        result = match self.info_type {
+            InfoType::Available => statvfs.blocks_available() * statvfs.block_size(),
+            InfoType::Free => statvfs.blocks_free() * statvfs.block_size(),
+            InfoType::Total | InfoType::Used => used,
+        };
+
+        // Fixed units keep two decimals, automatically scaled ones `digits` significant digits
+        let spec = match self.unit {
+            Unit::Bytes(spec) => spec,
+            Unit::Percent => UnitSpec::Fixed(unit::GB),
+        };
+        let format_value = |bytes: u64, unit: unit::Unit| match spec {
+            UnitSpec::Fixed(_) => format!("{0:.2}", unit.convert(bytes as f64)),
+            UnitSpec::Auto(..) => unit::format_significant(unit.convert(bytes as f64), self.digits),
+        };
+        let (converted_str, display_unit) = match self.info_type {
             InfoType::Total => {
-                result = used;
-                let converted_used = Unit::bytes_in_unit(self.unit, result);
-                let converted_total = Unit::bytes_in_unit(self.unit, total);
-
-                converted_str = format!(
-                                    "{0:.2}/{1:.2}",
-                                    converted_used,
-                                    converted_total
-                                );
+                // Both values are shown in the unit of the total, so that they can be compared
+                let display_unit = spec.unit_for(total as f64, self.digits);
+                (
+                    format!("{}/{}", format_value(result, display_unit), format_value(total, display_unit)),
+                    display_unit,
+                )
             }
-            InfoType::Used => {
-                result = used;
-                converted = Unit::bytes_in_unit(self.unit, result);
+            _ => {
+                let display_unit = spec.unit_for(result as f64, self.digits);
+                (format_value(result, display_unit), display_unit)
             }
-        }
+        };
 
         let percentage = (result as f32) / (total as f32) * 100f32;
-        if converted_str.is_empty() {
-            converted_str = format!("{0:.2}", converted);
-        }
+        let unit_str = match self.unit {
+            Unit::Percent => "Percent".to_owned(),
+            Unit::Bytes(_) => display_unit.to_string(),
+        };
+        self.values = map_to_owned!("{alias}" => self.alias.clone(),
+                                    "{value}" => converted_str.clone(),
+                                    "{unit}" => unit_str.clone(),
+                                    "{percentage}" => format!("{0:.2}", percentage));
+
+        let io = match self.io {
+            Some(ref mut io) => {
+                let (read, written) = match io.update()? {
+                    Some((read, written)) => (format_rate(read, self.digits), format_rate(written, self.digits)),
+                    None => ("×".to_owned(), "×".to_owned()),
+                };
+                let io = format!(" R {} W {}", read, written);
+                self.values.insert("{read_rate}".to_owned(), read);
+                self.values.insert("{write_rate}".to_owned(), written);
+                io
+            }
+            None => String::new(),
+        };
 
-        if self.unit == Unit::Percent {
-            self.disk_space.set_text(format!("{0} {1:.2}%",
-                self.alias,
-                percentage
-            ));
+        let text = if self.unit == Unit::Percent {
             result = percentage as u64;
+            format!("{0} {1:.2}%", self.alias, percentage)
         } else if self.show_percentage {
-            self.disk_space.set_text(format!(
-                    "{0} {1} ({2:.2}%) {3:?}",
-                    self.alias,
-                    converted_str,
-                    percentage,
-                    self.unit
-                    ));
+            format!("{0} {1} ({2:.2}%) {3}", self.alias, converted_str, percentage, unit_str)
         } else {
-            self.disk_space.set_text(format!(
-                    "{0} {1} {2:?}",
-                    self.alias,
-                    converted_str,
-                    self.unit
-                    ));
-        }
+            format!("{0} {1} {2}", self.alias, converted_str, unit_str)
+        };
+        self.disk_space.set_text(format!("{}{}", text, io));
 
-        let state = self.compute_state(result, self.warning, self.alert);
+        let state = self.compute_state(result);
         self.disk_space.set_state(state);
 
         Ok(Some(self.update_interval))
@@ -256,7 +366,58 @@ impl Block for DiskSpace {
         vec![&self.disk_space]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{format_rate, DiskIo, DiskSpaceConfig};
+    use crate::rate::Smoothing;
+    use crate::testing::scratch_dir;
+
+    #[test]
+    fn device_must_be_a_name() {
+        for device in &["sda", "nvme0n1", "dm-0"] {
+            let config = format!("device = {:?}", device);
+            assert!(::toml::from_str::<DiskSpaceConfig>(&config).is_ok(), "{}", config);
+        }
+        for device in &["", "../../../etc", "sda/../..", "..", "/dev/sda"] {
+            let config = format!("device = {:?}", device);
+            assert!(::toml::from_str::<DiskSpaceConfig>(&config).is_err(), "{}", config);
+        }
+    }
+
+    #[test]
+    fn digits_apply_to_rates() {
+        assert_eq!(format_rate(1_234_567., 3), "1.23MB/s");
+        assert_eq!(format_rate(1_234_567., 5), "1.2346MB/s");
+        assert_eq!(::toml::from_str::<DiskSpaceConfig>("").unwrap().digits, 3);
+        assert_eq!(::toml::from_str::<DiskSpaceConfig>("digits = 5").unwrap().digits, 5);
+        assert!(::toml::from_str::<DiskSpaceConfig>("digits = 0").is_err());
+    }
+
+    #[test]
+    fn missing_device_has_no_rates() {
+        let stat = scratch_dir("disk_io").join("stat");
+        let write_stat = |sectors: u64| {
+            fs::write(&stat, format!("100 0 {} 10 50 0 {} 20 0 30 30 0 0 0 0\n", sectors, sectors)).unwrap()
+        };
+
+        write_stat(1000);
+        let mut io = DiskIo::new(stat.clone(), Smoothing::None).unwrap();
+        fs::remove_file(&stat).unwrap();
+        assert_eq!(io.update().unwrap(), None);
+
+        // Back with fresh counters, the first reading only starts the count again
+        write_stat(10);
+        assert_eq!(io.update().unwrap(), Some((0., 0.)));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use chan::Sender;
 use std::thread;
@@ -138,6 +139,11 @@ impl Block for FocusedWindow {
         }
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        let title = self.title.lock().map(|title| title.clone()).unwrap_or_default();
+        map_to_owned!("{title}" => title)
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
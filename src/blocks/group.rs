@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::ops::DerefMut;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chan::{self, Receiver, Sender};
+use toml::value::Value;
+
+use super::{create_block, is_shown_on_bar};
+use crate::block::{Block, ConfigBlock};
+use crate::config::Config;
+use crate::errors::*;
+use crate::input::I3BarEvent;
+use crate::persist;
+use crate::scheduler::{Task, UpdateScheduler};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::button::ButtonWidget;
+
+use uuid::Uuid;
+
+/// Children due within this time are updated right away, as the group is asked to update
+/// at their time, which may come out a little early
+const DUE_TOLERANCE: Duration = Duration::from_millis(10);
+
+/// A drawer of blocks, shown as a single summary widget until it is clicked.
+pub struct Group {
+    id: String,
+    instance: String,
+    summary: ButtonWidget,
+    collapsed: bool,
+    children: Vec<Box<Block>>,
+    /// Schedules the updates of the children, the group asks to be updated in time for the next one
+    scheduler: UpdateScheduler,
+    /// Ids of the children that asked for an update
+    requested: Arc<Mutex<Vec<String>>>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GroupConfig {
+    /// Icon of the summary widget
+    #[serde(default)]
+    pub icon: String,
+
+    /// Text of the summary widget
+    pub text: Option<String>,
+
+    /// Show only the summary widget until the group is clicked
+    #[serde(default = "GroupConfig::default_collapsed")]
+    pub collapsed: bool,
+
+    /// Configs of the blocks in the group, like `[[block]]` ones
+    pub blocks: Vec<Value>,
+}
+
+impl GroupConfig {
+    fn default_collapsed() -> bool {
+        true
+    }
+}
+
+/// Splits the `block` key off the configs of the blocks in a group.
+pub fn child_configs(blocks: Vec<Value>) -> Result<Vec<(String, Value)>> {
+    blocks
+        .into_iter()
+        .map(|mut block| {
+            let name = match block {
+                Value::Table(ref mut table) => table.remove("block"),
+                _ => None,
+            };
+            match name {
+                Some(Value::String(name)) => Ok((name, block)),
+                _ => Err(ConfigurationError(
+                    "every block in a group needs a `block` name".to_owned(),
+                    (block.to_string(), format!("{:?}", block)),
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Passes update requests of the children on as requests for the group `id`, noting
+/// which child asked.
+fn forward_requests(rx: Receiver<Task>, requested: Arc<Mutex<Vec<String>>>, id: String, tx_update_request: Sender<Task>) {
+    thread::spawn(move || {
+        while let Some(task) = rx.recv() {
+            requested.lock().unwrap().push(task.id);
+            tx_update_request.send(Task {
+                id: id.clone(),
+                update_time: Instant::now(),
+            });
+        }
+    });
+}
+
+impl ConfigBlock for Group {
+    type Config = GroupConfig;
+
+    fn new(block_config: Self::Config, config: Config, tx_update_request: Sender<Task>) -> Result<Self> {
+        let id = Uuid::new_v4().simple().to_string();
+        let instance = config.instance.clone();
+
+        let (tx_children, rx_children) = chan::r#async();
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        forward_requests(rx_children, requested.clone(), id.clone(), tx_update_request);
+
+        // Children are named after the group, e.g. `system_disk_space`, `system_disk_space_2`
+        let mut children = Vec::new();
+        let mut instances: HashMap<String, usize> = HashMap::new();
+        for (name, child_config) in child_configs(block_config.blocks)? {
+            let count = instances.entry(name.clone()).or_insert(0);
+            *count += 1;
+            if !is_shown_on_bar(&child_config, &config) {
+                continue;
+            }
+            let mut shared_config = config.clone();
+            shared_config.instance = if *count == 1 {
+                format!("{}_{}", instance, name)
+            } else {
+                format!("{}_{}_{}", instance, name, count)
+            };
+            children.push(create_block(&name, child_config, shared_config, tx_children.clone())?);
+        }
+
+        let scheduler = UpdateScheduler::new(&children, Box::new(config.clock.clone()));
+        Ok(Group {
+            summary: ButtonWidget::new(config, &id)
+                .with_icon(&block_config.icon)
+                .with_content(block_config.text),
+            collapsed: persist::load(&instance).unwrap_or(block_config.collapsed),
+            scheduler,
+            id,
+            instance,
+            children,
+            requested,
+        })
+    }
+}
+
+impl Block for Group {
+    fn update(&mut self) -> Result<Option<Duration>> {
+        let requested: Vec<String> = self.requested.lock().unwrap().drain(..).collect();
+        for id in requested {
+            if let Some(child) = self.children.iter_mut().find(|child| child.id() == id) {
+                child.update()?;
+            }
+        }
+
+        // The group is also updated when a child asks for it, which needn't be when the
+        // next scheduled update of a child is due
+        if self.scheduler.time_to_next_update().map_or(false, |wait| wait <= DUE_TOLERANCE) {
+            let mut block_map: HashMap<String, &mut Block> = HashMap::new();
+            for child in &mut self.children {
+                block_map.insert(String::from(child.id()), (*child).deref_mut());
+            }
+            self.scheduler.do_scheduled_updates(&mut block_map)?;
+        }
+
+        let state = self.children
+            .iter()
+            .map(|child| child.state())
+            .max_by_key(|state| state.severity())
+            .unwrap_or(State::Idle);
+        self.summary.set_state(state);
+
+        Ok(self.scheduler.time_to_next_update())
+    }
+
+    fn view(&self) -> Vec<&I3BarWidget> {
+        let mut widgets: Vec<&I3BarWidget> = vec![&self.summary];
+        if !self.collapsed {
+            for child in &self.children {
+                widgets.extend(child.view());
+            }
+        }
+        widgets
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.name.as_ref().map_or(false, |name| name == &self.id) {
+            self.collapsed = !self.collapsed;
+            persist::save(&self.instance, &self.collapsed);
+            return Ok(());
+        }
+
+        if !self.collapsed {
+            for child in &mut self.children {
+                child.click(event)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chan::{self, Receiver};
+
+    use super::Group;
+    use crate::block::{Block, ConfigBlock};
+    use crate::input::{I3BarEvent, MouseButton};
+    use crate::scheduler::{Clock, SharedClock, Task};
+    use crate::testing::{config, scratch_dir, FakeClock};
+    use crate::widget::State;
+
+    fn group(instance: &str, block_config: &str, clock: SharedClock) -> (Group, Receiver<Task>) {
+        let mut config = config("");
+        config.instance = instance.to_owned();
+        config.clock = clock;
+        let (tx_update_request, rx_update_request) = chan::r#async();
+        let group = Group::new(::toml::from_str(block_config).unwrap(), config, tx_update_request).unwrap();
+        (group, rx_update_request)
+    }
+
+    fn texts(group: &Group) -> Vec<String> {
+        group
+            .view()
+            .iter()
+            .map(|widget| widget.get_rendered()["full_text"].as_str().unwrap().trim().to_owned())
+            .collect()
+    }
+
+    fn click(group: &mut Group, name: &str) {
+        group
+            .click(&I3BarEvent {
+                name: Some(name.to_owned()),
+                instance: None,
+                x: 0,
+                y: 0,
+                button: MouseButton::Left,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn clicks_on_the_summary_collapse_and_expand() {
+        let (mut group, _rx) = group(
+            "group_collapse",
+            r#"
+            text = "sys"
+            [[blocks]]
+            block = "custom"
+            command = "echo one"
+            [[blocks]]
+            block = "custom"
+            command = "echo two"
+            "#,
+            SharedClock::default(),
+        );
+        group.update().unwrap();
+        assert_eq!(texts(&group), vec!["sys"]);
+
+        let id = group.id().to_owned();
+        click(&mut group, &id);
+        assert_eq!(texts(&group), vec!["sys", "one", "two"]);
+        click(&mut group, &id);
+        assert_eq!(texts(&group), vec!["sys"]);
+    }
+
+    #[test]
+    fn summary_has_the_worst_state_of_the_children() {
+        let (mut group, _rx) = group(
+            "group_state",
+            r#"
+            collapsed = false
+            [[blocks]]
+            block = "custom"
+            json = true
+            command = "echo '{\"text\": \"a\", \"state\": \"Warning\"}'"
+            [[blocks]]
+            block = "custom"
+            json = true
+            command = "echo '{\"text\": \"b\", \"state\": \"Critical\"}'"
+            [[blocks]]
+            block = "custom"
+            command = "echo c"
+            "#,
+            SharedClock::default(),
+        );
+        group.update().unwrap();
+        assert_eq!(texts(&group)[1..].to_vec(), vec!["a", "b", "c"]);
+        assert_eq!(group.view()[0].get_state(), State::Critical);
+    }
+
+    #[test]
+    fn requests_of_children_are_forwarded_to_the_group() {
+        let (mut group, rx_update_request) = group(
+            "group_requests",
+            r#"
+            collapsed = false
+            [[blocks]]
+            block = "custom"
+            command = "echo first; sleep 30"
+            persistent = true
+            "#,
+            SharedClock::default(),
+        );
+        let timeout = chan::after(Duration::from_secs(5));
+        chan_select! {
+            rx_update_request.recv() -> task => assert_eq!(task.unwrap().id, group.id()),
+            timeout.recv() => panic!("the child's request wasn't forwarded"),
+        }
+        group.update().unwrap();
+        assert_eq!(texts(&group), vec!["", "first"]);
+    }
+
+    #[test]
+    fn children_are_updated_by_the_clock_of_the_bar() {
+        let counter = scratch_dir("group_clock").join("count");
+        let clock = SharedClock::new(FakeClock::new(Duration::from_secs(3600)));
+        let (mut group, _rx) = group(
+            "group_clock",
+            &format!(
+                r#"
+                collapsed = false
+                [[blocks]]
+                block = "custom"
+                interval = 60
+                command = "n=$(( $(cat {0} 2>/dev/null || echo 0) + 1 )); echo $n > {0}; echo $n"
+                "#,
+                counter.display()
+            ),
+            clock.clone(),
+        );
+        assert_eq!(group.update().unwrap(), Some(Duration::from_secs(60)));
+        assert_eq!(texts(&group)[1], "1");
+
+        // Too early for the child
+        clock.sleep(Duration::from_secs(30));
+        assert_eq!(group.update().unwrap(), Some(Duration::from_secs(30)));
+        assert_eq!(texts(&group)[1], "1");
+
+        clock.sleep(Duration::from_secs(30));
+        assert_eq!(group.update().unwrap(), Some(Duration::from_secs(60)));
+        assert_eq!(texts(&group)[1], "2");
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::env;
 use std::fs::{read_dir, File};
 use std::io::prelude::*;
@@ -113,6 +114,11 @@ impl Block for IBus {
         vec![&self.text]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        let engine = self.engine.lock().map(|engine| engine.clone()).unwrap_or_default();
+        map_to_owned!("{engine}" => engine)
+    }
+
     // This function is called on every block for every click.
     // TODO: Filter events by using the event.name property,
     // and use to switch between input engines?
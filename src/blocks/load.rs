@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
 use crate::block::{Block, ConfigBlock};
@@ -13,6 +14,7 @@ use crate::scheduler::Task;
 use std::io::BufReader;
 use std::io::prelude::*;
 use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
 
 use uuid::Uuid;
 
@@ -22,6 +24,8 @@ pub struct Load {
     format: FormatTemplate,
     id: String,
     update_interval: Duration,
+    proc_path: PathBuf,
+    values: HashMap<String, String>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -47,11 +51,12 @@ impl ConfigBlock for Load {
     type Config = LoadConfig;
 
     fn new(block_config: Self::Config, config: Config, _tx_update_request: Sender<Task>) -> Result<Self> {
+        let proc_path = config.host_path("/proc");
         let text = TextWidget::new(config)
             .with_icon("cogs")
             .with_state(State::Info);
 
-        let f = File::open("/proc/cpuinfo")
+        let f = File::open(proc_path.join("cpuinfo"))
             .block_error("load", "Your system doesn't support /proc/cpuinfo")?;
         let f = BufReader::new(f);
 
@@ -71,6 +76,8 @@ impl ConfigBlock for Load {
         Ok(Load {
             id: Uuid::new_v4().simple().to_string(),
             logical_cores,
+            proc_path,
+            values: HashMap::new(),
             update_interval: block_config.interval,
             format: FormatTemplate::from_string(&block_config.format)
                 .block_error("load", "Invalid format specified for load")?,
@@ -83,7 +90,7 @@ impl Block for Load {
     fn update(&mut self) -> Result<Option<Duration>> {
         let mut f = OpenOptions::new()
             .read(true)
-            .open("/proc/loadavg")
+            .open(self.proc_path.join("loadavg"))
             .block_error(
                 "load",
                 "Your system does not support reading the load average from /proc/loadavg",
@@ -110,6 +117,10 @@ impl Block for Load {
         );
 
         self.text.set_text(self.format.render_static_str(&values)?);
+        self.values = values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.trim().to_owned()))
+            .collect();
 
         Ok(Some(self.update_interval))
     }
@@ -118,6 +129,10 @@ impl Block for Load {
         vec![&self.text]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
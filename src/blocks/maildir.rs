@@ -1,10 +1,12 @@
+use std::collections::HashMap;
 use std::time::Duration;
 use chan::Sender;
 
 use crate::block::{Block, ConfigBlock};
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::{deserialize_duration, deserialize_expanded, deserialize_opt_duration};
 use crate::errors::*;
+use crate::threshold::{Thresholds, Trigger};
 use crate::widgets::text::TextWidget;
 use crate::widget::{I3BarWidget, State};
 use crate::input::I3BarEvent;
@@ -18,8 +20,8 @@ pub struct Maildir {
     id: String,
     update_interval: Duration,
     inboxes: Vec<String>,
-    threshold_warning: usize,
-    threshold_critical: usize,
+    thresholds: Thresholds,
+    values: HashMap<String, String>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -28,11 +30,16 @@ pub struct MaildirConfig {
     /// Update interval in seconds
     #[serde(default = "MaildirConfig::default_interval", deserialize_with = "deserialize_duration")]
     pub interval: Duration,
+    #[serde(deserialize_with = "deserialize_expanded")]
     pub inboxes: Vec<String>,
     #[serde(default = "MaildirConfig::default_threshold_warning")]
     pub threshold_warning: usize,
     #[serde(default = "MaildirConfig::default_threshold_critical")]
     pub threshold_critical: usize,
+    #[serde(default = "MaildirConfig::default_hysteresis")]
+    pub hysteresis: f64,
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub min_dwell: Option<Duration>,
 }
 
 impl MaildirConfig {
@@ -45,6 +52,9 @@ impl MaildirConfig {
     fn default_threshold_critical() -> usize {
         10 as usize
     }
+    fn default_hysteresis() -> f64 {
+        0.
+    }
 }
 
 impl ConfigBlock for Maildir {
@@ -58,8 +68,10 @@ impl ConfigBlock for Maildir {
                 .with_icon("mail")
                 .with_text(""),
             inboxes: block_config.inboxes,
-            threshold_warning: block_config.threshold_warning,
-            threshold_critical: block_config.threshold_critical,
+            thresholds: Thresholds::new(State::Idle, block_config.hysteresis, block_config.min_dwell, config.clock.clone())
+                .with_level(State::Warning, Trigger::AtLeast, block_config.threshold_warning as f64)
+                .with_level(State::Critical, Trigger::AtLeast, block_config.threshold_critical as f64),
+            values: HashMap::new(),
         })
     }
 }
@@ -72,14 +84,10 @@ impl Block for Maildir {
             let maildir = ExtMaildir::from(isl);
             newmails += maildir.count_new();
         }
-        let mut state = { State::Idle };
-        if newmails >= self.threshold_critical {
-            state = { State::Critical };
-        } else if newmails >= self.threshold_warning {
-            state = { State::Warning };
-        }
+        let state = self.thresholds.state(newmails as f64);
         self.text.set_state(state);
         self.text.set_text(format!("{}", newmails));
+        self.values = map_to_owned!("{count}" => format!("{}", newmails));
         Ok(Some(self.update_interval))
     }
 
@@ -87,6 +95,10 @@ impl Block for Maildir {
         vec![&self.text]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
+
     fn click(&mut self, _: &I3BarEvent) -> Result<()> {
         Ok(())
     }
@@ -31,6 +31,10 @@
 //! warning_swap | Percentage of swap usage, where state is set to warning | No | 80.0
 //! critical_mem | Percentage of memory usage, where state is set to critical | No | 95.0
 //! critical_swap | Percentage of swap usage, where state is set to critical | No | 95.0
+//! unit | Unit of the {MT}, {MF}, ... values, a fixed unit like `GiB` or `MB`, or `auto`/`auto_iec` to scale automatically | No | auto_iec
+//! digits | Significant digits of the {MT}, {MF}, ... values in an automatically scaled unit | No | 3
+//! hysteresis | Percentage points usage has to drop below a threshold before the state is relaxed again | No | 0.0
+//! min_dwell | Minimum time in seconds a new state has to persist before it is displayed | No | None
 //!
 //! ### Format string specification
 //!
@@ -70,6 +74,9 @@
 //! {SUm}  | Swap used (MiB)
 //! {SUp}  | Swap used (%)
 //! {SUpi} | Swap used (%) as integer
+//!
+//! The memory and swap amounts are also available in the unit set by `unit`, including the unit
+//! symbol: {MT}, {MA}, {MF}, {Mu}, {MU}, {C}, {B}, {ST}, {SF} and {SU}.
 
 //!
 use std::time::{Duration, Instant};
@@ -77,16 +84,18 @@ use std::collections::HashMap;
 use crate::util::*;
 use chan::Sender;
 use std::fs::File;
+use std::path::PathBuf;
 use std::io::{BufRead, BufReader};
 use crate::block::{Block, ConfigBlock};
 use crate::input::{I3BarEvent, MouseButton};
 use std::str::FromStr;
 use uuid::Uuid;
-use std::fmt;
 
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::{deserialize_duration, deserialize_opt_duration};
 use crate::errors::*;
+use crate::threshold::{Thresholds, Trigger};
+use crate::unit::{self, UnitSpec};
 use crate::widgets::button::ButtonWidget;
 use crate::widget::{I3BarWidget, State};
 use crate::scheduler::Task;
@@ -101,56 +110,34 @@ pub enum Memtype {
     Memory,
 }
 
+/// An amount of memory as found in /proc/meminfo, in KiB
 #[derive(Clone, Copy)]
-enum Unit {
-    MiB(u64),
-    GiB(f32),
-    KiB(u64),
-}
+struct Kib(u64);
 
-impl fmt::Display for Unit {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Unit::MiB(n) => n.fmt(f),
-            Unit::KiB(n) => n.fmt(f),
-            Unit::GiB(n) => n.fmt(f),
-        }
+impl Kib {
+    fn n(self) -> u64 {
+        self.0
     }
-}
 
-impl Unit {
-    fn n(&self) -> u64 {
-        match self.kib() {
-            Unit::KiB(n) => n,
-            _ => 0,
-        }
+    fn bytes(self) -> f64 {
+        self.0 as f64 * 1024.
     }
 
-    fn gib(&self) -> Unit {
-        match *self {
-            Unit::KiB(n) => Unit::GiB((n as f32) / 1024f32.powi(2)),
-            Unit::MiB(n) => Unit::GiB((n as f32) / 1024f32),
-            Unit::GiB(n) => Unit::GiB(n),
-        }
+    fn gib(self) -> String {
+        format!("{:.1}", unit::GIB.convert(self.bytes()))
     }
 
-    fn mib(&self) -> Unit {
-        match *self {
-            Unit::KiB(n) => Unit::MiB(n / 1024),
-            Unit::MiB(n) => Unit::MiB(n),
-            Unit::GiB(n) => Unit::MiB((n * 1024f32) as u64),
-        }
+    fn mib(self) -> String {
+        format!("{}", unit::MIB.convert(self.bytes()) as u64)
     }
 
-    fn kib(&self) -> Unit {
-        match *self {
-            Unit::KiB(n) => Unit::KiB(n),
-            Unit::MiB(n) => Unit::KiB(n * 1024),
-            Unit::GiB(n) => Unit::KiB((n * 1024f32.powi(2)) as u64),
-        }
+    /// Formatted in the unit chosen in the config, including the unit symbol
+    fn scaled(self, spec: UnitSpec, digits: usize) -> String {
+        let (value, unit) = spec.format(self.bytes(), digits);
+        format!("{}{}", value, unit)
     }
 
-    fn percent(&self, reference: Unit) -> f32 {
+    fn percent(self, reference: Kib) -> f32 {
         if reference.n() < 1 {
             100f32
         } else {
@@ -233,11 +220,14 @@ pub struct Memory {
     update_interval: Duration,
     tx_update_request: Sender<Task>,
     values: HashMap<String, String>,
-    warning: (f64, f64),
-    critical: (f64, f64),
+    thresholds: (Thresholds, Thresholds),
+    unit: UnitSpec,
+    digits: usize,
+    proc_path: PathBuf,
 }
 
 #[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct MemoryConfig {
     /// Format string for Memory view. All format values are described below.
     #[serde(default = "MemoryConfig::default_format_mem")]
@@ -278,6 +268,22 @@ pub struct MemoryConfig {
     /// Percentage of swap usage, where state is set to critical
     #[serde(default = "MemoryConfig::default_critical_swap")]
     pub critical_swap: f64,
+
+    /// Unit of the {MT}, {MF}, ... placeholders, e.g. GiB or auto_iec
+    #[serde(default = "MemoryConfig::default_unit")]
+    pub unit: UnitSpec,
+
+    /// Significant digits of the {MT}, {MF}, ... placeholders in an automatically scaled unit
+    #[serde(default = "unit::default_digits", deserialize_with = "unit::deserialize_digits")]
+    pub digits: usize,
+
+    /// Percentage points usage has to drop below a threshold before the state is relaxed again
+    #[serde(default = "MemoryConfig::default_hysteresis")]
+    pub hysteresis: f64,
+
+    /// Minimum time a new state has to persist before it is displayed
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub min_dwell: Option<Duration>,
 }
 
 impl MemoryConfig {
@@ -320,31 +326,39 @@ impl MemoryConfig {
     fn default_critical_swap() -> f64 {
         95.0
     }
+
+    fn default_hysteresis() -> f64 {
+        0.0
+    }
+
+    fn default_unit() -> UnitSpec {
+        UnitSpec::Auto(unit::System::Iec, unit::Quantity::Bytes)
+    }
 }
 
 impl Memory {
     fn format_insert_values(&mut self, mem_state: Memstate) -> Result<String> {
-        let mem_total = Unit::KiB(mem_state.mem_total());
-        let mem_free = Unit::KiB(mem_state.mem_free());
-        let swap_total = Unit::KiB(mem_state.swap_total());
-        let swap_free = Unit::KiB(mem_state.swap_free());
-        let swap_used = Unit::KiB(mem_state.swap_total() - mem_state.swap_free());
-        let mem_total_used = Unit::KiB(mem_total.n() - mem_free.n());
-        let buffers = Unit::KiB(mem_state.buffers());
-        let cached = Unit::KiB(
+        let mem_total = Kib(mem_state.mem_total());
+        let mem_free = Kib(mem_state.mem_free());
+        let swap_total = Kib(mem_state.swap_total());
+        let swap_free = Kib(mem_state.swap_free());
+        let swap_used = Kib(mem_state.swap_total() - mem_state.swap_free());
+        let mem_total_used = Kib(mem_total.n() - mem_free.n());
+        let buffers = Kib(mem_state.buffers());
+        let cached = Kib(
             mem_state.cached() + mem_state.s_reclaimable() - mem_state.shmem(),
         );
-        let mem_used = Unit::KiB(mem_total_used.n() - (buffers.n() + cached.n()));
-        let mem_avail = Unit::KiB(mem_total.n() - mem_used.n());
+        let mem_used = Kib(mem_total_used.n() - (buffers.n() + cached.n()));
+        let mem_avail = Kib(mem_total.n() - mem_used.n());
 
         self.values
-            .insert("{MTg}".to_string(), format!("{:.1}", mem_total.gib()));
+            .insert("{MTg}".to_string(), mem_total.gib());
         self.values
-            .insert("{MTm}".to_string(), format!("{}", mem_total.mib()));
+            .insert("{MTm}".to_string(), mem_total.mib());
         self.values
-            .insert("{MFg}".to_string(), format!("{:.1}", mem_free.gib()));
+            .insert("{MFg}".to_string(), mem_free.gib());
         self.values
-            .insert("{MFm}".to_string(), format!("{}", mem_free.mib()));
+            .insert("{MFm}".to_string(), mem_free.mib());
         self.values.insert(
             "{MFp}".to_string(),
             format!("{:.2}", mem_free.percent(mem_total)),
@@ -354,9 +368,9 @@ impl Memory {
             format!("{:02}", mem_free.percent(mem_total) as i32),
         );
         self.values
-            .insert("{MUg}".to_string(), format!("{:.1}", mem_total_used.gib()));
+            .insert("{MUg}".to_string(), mem_total_used.gib());
         self.values
-            .insert("{MUm}".to_string(), format!("{}", mem_total_used.mib()));
+            .insert("{MUm}".to_string(), mem_total_used.mib());
         self.values.insert(
             "{MUp}".to_string(),
             format!("{:.2}", mem_total_used.percent(mem_total)),
@@ -366,9 +380,9 @@ impl Memory {
             format!("{:02}", mem_total_used.percent(mem_total) as i32),
         );
         self.values
-            .insert("{Mug}".to_string(), format!("{:.1}", mem_used.gib()));
+            .insert("{Mug}".to_string(), mem_used.gib());
         self.values
-            .insert("{Mum}".to_string(), format!("{}", mem_used.mib()));
+            .insert("{Mum}".to_string(), mem_used.mib());
         self.values.insert(
             "{Mup}".to_string(),
             format!("{:.2}", mem_used.percent(mem_total)),
@@ -378,9 +392,9 @@ impl Memory {
             format!("{:02}", mem_used.percent(mem_total) as i32),
         );
         self.values
-            .insert("{MAg}".to_string(), format!("{:.1}", mem_avail.gib()));
+            .insert("{MAg}".to_string(), mem_avail.gib());
         self.values
-            .insert("{MAm}".to_string(), format!("{}", mem_avail.mib()));
+            .insert("{MAm}".to_string(), mem_avail.mib());
         self.values.insert(
             "{MAp}".to_string(),
             format!("{:.2}", mem_avail.percent(mem_total)),
@@ -390,13 +404,13 @@ impl Memory {
             format!("{:02}", mem_avail.percent(mem_total) as i32),
         );
         self.values
-            .insert("{STg}".to_string(), format!("{:.1}", swap_total.gib()));
+            .insert("{STg}".to_string(), swap_total.gib());
         self.values
-            .insert("{STm}".to_string(), format!("{}", swap_total.mib()));
+            .insert("{STm}".to_string(), swap_total.mib());
         self.values
-            .insert("{SFg}".to_string(), format!("{:.1}", swap_free.gib()));
+            .insert("{SFg}".to_string(), swap_free.gib());
         self.values
-            .insert("{SFm}".to_string(), format!("{}", swap_free.mib()));
+            .insert("{SFm}".to_string(), swap_free.mib());
         self.values.insert(
             "{SFp}".to_string(),
             format!("{:.2}", swap_free.percent(swap_total)),
@@ -406,9 +420,9 @@ impl Memory {
             format!("{:02}", swap_free.percent(swap_total) as i32),
         );
         self.values
-            .insert("{SUg}".to_string(), format!("{:.1}", swap_used.gib()));
+            .insert("{SUg}".to_string(), swap_used.gib());
         self.values
-            .insert("{SUm}".to_string(), format!("{}", swap_used.mib()));
+            .insert("{SUm}".to_string(), swap_used.mib());
         self.values.insert(
             "{SUp}".to_string(),
             format!("{:.2}", swap_used.percent(swap_total)),
@@ -418,9 +432,9 @@ impl Memory {
             format!("{:02}", swap_used.percent(swap_total) as i32),
         );
         self.values
-            .insert("{Bg}".to_string(), format!("{:.1}", buffers.gib()));
+            .insert("{Bg}".to_string(), buffers.gib());
         self.values
-            .insert("{Bm}".to_string(), format!("{}", buffers.mib()));
+            .insert("{Bm}".to_string(), buffers.mib());
         self.values.insert(
             "{Bp}".to_string(),
             format!("{:.2}", buffers.percent(mem_total)),
@@ -430,9 +444,9 @@ impl Memory {
             format!("{:02}", buffers.percent(mem_total) as i32),
         );
         self.values
-            .insert("{Cg}".to_string(), format!("{:.1}", cached.gib()));
+            .insert("{Cg}".to_string(), cached.gib());
         self.values
-            .insert("{Cm}".to_string(), format!("{}", cached.mib()));
+            .insert("{Cm}".to_string(), cached.mib());
         self.values.insert(
             "{Cp}".to_string(),
             format!("{:.2}", cached.percent(mem_total)),
@@ -442,18 +456,27 @@ impl Memory {
             format!("{:02}", cached.percent(mem_total) as i32),
         );
 
+        for &(key, amount) in &[
+            ("{MT}", mem_total),
+            ("{MF}", mem_free),
+            ("{MU}", mem_total_used),
+            ("{Mu}", mem_used),
+            ("{MA}", mem_avail),
+            ("{C}", cached),
+            ("{B}", buffers),
+            ("{ST}", swap_total),
+            ("{SF}", swap_free),
+            ("{SU}", swap_used),
+        ] {
+            self.values.insert(key.to_string(), amount.scaled(self.unit, self.digits));
+        }
+
         match self.memtype {
-            Memtype::Memory => self.output.0.set_state(match mem_used.percent(mem_total) {
-                x if f64::from(x) > self.critical.0 => State::Critical,
-                x if f64::from(x) > self.warning.0 => State::Warning,
-                _ => State::Idle,
-            }),
+            Memtype::Memory => self.output.0.set_state(
+                self.thresholds.0.state(f64::from(mem_used.percent(mem_total))),
+            ),
             Memtype::Swap => self.output.1.set_state(
-                match swap_used.percent(swap_total) {
-                    x if f64::from(x)  > self.critical.1 => State::Critical,
-                    x if f64::from(x) > self.warning.1 => State::Warning,
-                    _ => State::Idle,
-                },
+                self.thresholds.1.state(f64::from(swap_used.percent(swap_total))),
             ),
         };
 
@@ -487,6 +510,8 @@ impl ConfigBlock for Memory {
 
     fn new(block_config: Self::Config, config: Config, tx: Sender<Task>) -> Result<Self> {
         let icons: bool = block_config.icons;
+        let proc_path = config.host_path("/proc");
+        let clock = config.clock.clone();
         let widget = ButtonWidget::new(config, "memory").with_text("");
         Ok(Memory {
             id: Uuid::new_v4().simple().to_string(),
@@ -507,8 +532,17 @@ impl ConfigBlock for Memory {
             update_interval: block_config.interval,
             tx_update_request: tx,
             values: HashMap::<String, String>::new(),
-            warning: (block_config.warning_mem, block_config.warning_swap),
-            critical: (block_config.critical_mem, block_config.critical_swap),
+            unit: block_config.unit,
+            digits: block_config.digits,
+            proc_path,
+            thresholds: (
+                Thresholds::new(State::Idle, block_config.hysteresis, block_config.min_dwell, clock.clone())
+                    .with_level(State::Warning, Trigger::Above, block_config.warning_mem)
+                    .with_level(State::Critical, Trigger::Above, block_config.critical_mem),
+                Thresholds::new(State::Idle, block_config.hysteresis, block_config.min_dwell, clock)
+                    .with_level(State::Warning, Trigger::Above, block_config.warning_swap)
+                    .with_level(State::Critical, Trigger::Above, block_config.critical_swap),
+            ),
         })
     }
 }
@@ -520,7 +554,7 @@ impl Block for Memory {
     }
 
     fn update(&mut self) -> Result<Option<Duration>> {
-        let f = File::open("/proc/meminfo")
+        let f = File::open(self.proc_path.join("meminfo"))
             .block_error("memory", "/proc/meminfo does not exist")?;
         let f = BufReader::new(f);
 
@@ -672,4 +706,8 @@ impl Block for Memory {
             },
         ]
     }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
 }
@@ -5,6 +5,8 @@ mod memory;
 mod cpu;
 mod music;
 pub mod battery;
+#[cfg(feature = "scripting")]
+mod composite;
 mod custom;
 mod disk_space;
 mod pacman;
@@ -13,6 +15,7 @@ mod toggle;
 mod sound;
 mod speedtest;
 mod focused_window;
+mod group;
 mod xrandr;
 mod net;
 pub mod backlight;
@@ -21,6 +24,10 @@ mod uptime;
 pub mod nvidia_gpu;
 pub mod maildir;
 mod networkmanager;
+#[cfg(feature = "scripting")]
+mod script;
+#[cfg(not(feature = "scripting"))]
+mod scripting_disabled;
 mod bluetooth;
 pub mod ibus;
 
@@ -32,6 +39,8 @@ use self::cpu::*;
 use self::load::*;
 use self::memory::*;
 use self::battery::*;
+#[cfg(feature = "scripting")]
+use self::composite::*;
 use self::custom::*;
 use self::disk_space::*;
 use self::pacman::*;
@@ -39,6 +48,7 @@ use self::sound::*;
 use self::speedtest::*;
 use self::toggle::*;
 use self::focused_window::*;
+use self::group::*;
 use self::temperature::*;
 use self::xrandr::*;
 use self::net::*;
@@ -48,11 +58,18 @@ use self::uptime::*;
 use self::nvidia_gpu::*;
 use self::maildir::*;
 use self::networkmanager::*;
+#[cfg(feature = "scripting")]
+use self::script::*;
+#[cfg(not(feature = "scripting"))]
+use self::scripting_disabled::*;
 use self::bluetooth::*;
 use self::ibus::*;
 
 use super::block::{Block, ConfigBlock};
 use crate::errors::*;
+use crate::notification::{NotifyConfig, Notifying};
+use crate::stats::Timed;
+use crate::visibility::{HideRule, Hiding};
 use super::scheduler::Task;
 
 extern crate dbus;
@@ -80,8 +97,29 @@ macro_rules! blocks {
     }
 }
 
-pub fn create_block(name: &str, block_config: Value, config: Config, tx_update_request: Sender<Task>) -> Result<Box<Block>> {
-    blocks!(name, block_config, config, tx_update_request;
+macro_rules! block_configs {
+    ( $name:ident, $block_config:ident ; $( $block_name:expr => $block_type:ident ),+ ) => {
+        match $name {
+            $(
+                $block_name => <$block_type as ConfigBlock>::Config::deserialize($block_config)
+                    .map(|_| ())
+                    .configuration_error("failed to deserialize block config"),
+             )*
+            _ => Err(BlockError($name.to_string(), "Unknown block!".to_string())),
+        }
+    }
+}
+
+macro_rules! block_names {
+    ( ; $( $block_name:expr => $block_type:ident ),+ ) => {
+        &[ $( $block_name ),* ]
+    }
+}
+
+/// Calls `$m!(args; "name" => Type, ...)` with every block there is.
+macro_rules! with_all_blocks {
+    ( $m:ident ! ( $( $args:tt )* ) ) => {
+        $m!( $( $args )* ;
             "time" => Time,
             "template" => Template,
             "music" => Music,
@@ -90,6 +128,7 @@ pub fn create_block(name: &str, block_config: Value, config: Config, tx_update_r
             "cpu" => Cpu,
             "pacman" => Pacman,
             "battery" => Battery,
+            "composite" => Composite,
             "custom" => Custom,
             "disk_space" => DiskSpace,
             "toggle" => Toggle,
@@ -97,6 +136,7 @@ pub fn create_block(name: &str, block_config: Value, config: Config, tx_update_r
             "speedtest" => SpeedTest,
             "temperature" => Temperature,
             "focused_window" => FocusedWindow,
+            "group" => Group,
             "xrandr" => Xrandr,
             "net" => Net,
             "backlight" => Backlight,
@@ -106,6 +146,243 @@ pub fn create_block(name: &str, block_config: Value, config: Config, tx_update_r
             "maildir" => Maildir,
             "networkmanager" => NetworkManager,
             "bluetooth" => Bluetooth,
-            "ibus" => IBus
-    )
+            "ibus" => IBus,
+            "script" => Script
+        )
+    }
+}
+
+/// Names of all blocks, as used for `block = "..."` in the config.
+pub const BLOCK_NAMES: &[&str] = with_all_blocks!(block_names!());
+
+/// Options that can be set on any block, next to the block's own ones.
+pub struct CommonOptions {
+    pub instance: Option<String>,
+    pub notify: Option<NotifyConfig>,
+    pub hide_when: Option<Vec<HideRule>>,
+    pub outputs: Option<Vec<String>>,
+}
+
+/// Keys of the options in `CommonOptions`.
+pub const COMMON_OPTION_KEYS: &[&str] = &["instance", "notify", "hide_when", "outputs"];
+
+impl CommonOptions {
+    /// Takes the common options out of a block's config, so that the rest can be
+    /// deserialized into the block's own config type.
+    pub fn take(block_config: &mut Value) -> Result<Self> {
+        let (instance, notify, hide_when, outputs) = match *block_config {
+            Value::Table(ref mut table) => (
+                table.remove("instance"),
+                table.remove("notify"),
+                table.remove("hide_when"),
+                table.remove("outputs"),
+            ),
+            _ => (None, None, None, None),
+        };
+
+        let instance = match instance {
+            Some(instance) => Some(instance
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| ConfigurationError(
+                    "instance must be a string".to_owned(),
+                    (instance.to_string(), format!("{:?}", instance)),
+                ))?),
+            None => None,
+        };
+
+        let notify = match notify {
+            Some(notify) => Some(NotifyConfig::deserialize(notify)
+                .configuration_error("failed to deserialize notify config")?),
+            None => None,
+        };
+
+        let hide_when = match hide_when {
+            // A single rule can be given as a table instead of an array of tables
+            Some(Value::Table(rule)) => Some(Vec::<HideRule>::deserialize(Value::Array(vec![Value::Table(rule)]))
+                .configuration_error("failed to deserialize hide_when rules")?),
+            Some(rules) => Some(Vec::<HideRule>::deserialize(rules)
+                .configuration_error("failed to deserialize hide_when rules")?),
+            None => None,
+        };
+
+        let outputs = match outputs {
+            Some(outputs) => Some(Vec::<String>::deserialize(outputs)
+                .configuration_error("outputs must be a list of output names")?),
+            None => None,
+        };
+
+        Ok(CommonOptions {
+            instance,
+            notify,
+            hide_when,
+            outputs,
+        })
+    }
+}
+
+/// Whether a block is shown on the bar, i.e. it has no `outputs` or one of them was given
+/// with `--output` or `--bar-id`. A bar that was given neither shows all blocks.
+pub fn is_shown_on_bar(block_config: &Value, config: &Config) -> bool {
+    match block_config.get("outputs").and_then(|outputs| outputs.as_array()) {
+        Some(outputs) if !config.bar_outputs.is_empty() => outputs
+            .iter()
+            .filter_map(|output| output.as_str())
+            .any(|output| config.bar_outputs.iter().any(|bar_output| bar_output == output)),
+        _ => true,
+    }
+}
+
+/// Validates the config of a block the way `create_block` would, without creating the block.
+pub fn check_block_config(name: &str, mut block_config: Value) -> Result<()> {
+    let options = CommonOptions::take(&mut block_config)?;
+    if let Some(ref rules) = options.hide_when {
+        for rule in rules {
+            rule.validate(name)?;
+        }
+    }
+
+    // The blocks of a group are checked like any other
+    if name == "group" {
+        let group = GroupConfig::deserialize(block_config.clone())
+            .configuration_error("failed to deserialize block config")?;
+        for (name, block_config) in group::child_configs(group.blocks)? {
+            check_block_config(&name, block_config)?;
+        }
+    }
+
+    with_all_blocks!(block_configs!(name, block_config))
+}
+
+pub fn create_block(name: &str, mut block_config: Value, mut config: Config, tx_update_request: Sender<Task>) -> Result<Box<Block>> {
+    let options = CommonOptions::take(&mut block_config)?;
+    if let Some(instance) = options.instance {
+        config.instance = instance;
+    }
+    let instance = config.instance.clone();
+    let tx_notify_update = tx_update_request.clone();
+
+    let mut block = with_all_blocks!(blocks!(name, block_config, config, tx_update_request))?;
+
+    if let Some(notify) = options.notify {
+        block = Box::new(Notifying::new(block, name, notify, tx_notify_update)?);
+    }
+
+    if let Some(rules) = options.hide_when {
+        block = Box::new(Hiding::new(block, name, rules)?);
+    }
+
+    Ok(Box::new(Timed::new(block, &instance)))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "scripting"))]
+    use super::check_block_config;
+    use crate::registry;
+    use crate::testing::{assert_golden, render_on_fixture};
+
+    #[cfg(not(feature = "scripting"))]
+    #[test]
+    fn scripting_blocks_need_the_feature() {
+        for name in &["script", "composite"] {
+            let error = check_block_config(name, ::toml::from_str("format = \"x\"").unwrap()).unwrap_err();
+            assert!(format!("{:?}", error).contains("'scripting' feature"), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn battery_on_fixture() {
+        assert_golden("fs_root/battery.txt", &render_on_fixture("battery", "format = \"{percentage}% {time} {power}W\""));
+    }
+
+    #[test]
+    fn battery_thresholds_on_fixture() {
+        assert_golden("fs_root/battery_thresholds.txt", &render_on_fixture("battery", "warning = 45\ncritical = 20"));
+    }
+
+    #[test]
+    fn backlight_on_fixture() {
+        assert_golden("fs_root/backlight.txt", &render_on_fixture("backlight", ""));
+    }
+
+    #[test]
+    fn backlight_devices_on_fixture() {
+        let mut lines = Vec::new();
+        for device in &["acpi_video0", "intel_backlight", "nv_backlight"] {
+            for line in render_on_fixture("backlight", &format!("device = {:?}", device)) {
+                lines.push(format!("{}: {}", device, line));
+            }
+        }
+        assert_golden("fs_root/backlight_devices.txt", &lines);
+    }
+
+    #[test]
+    fn net_on_fixture() {
+        assert_golden("fs_root/net.txt", &render_on_fixture("net", "device = \"eth0\""));
+    }
+
+    #[test]
+    fn net_devices_on_fixture() {
+        let mut lines = Vec::new();
+        for device in &["wlan0", "tun0"] {
+            for line in render_on_fixture("net", &format!("device = {:?}", device)) {
+                lines.push(format!("{}: {}", device, line));
+            }
+        }
+        assert_golden("fs_root/net_devices.txt", &lines);
+    }
+
+    #[test]
+    fn cpu_on_fixture() {
+        assert_golden("fs_root/cpu.txt", &render_on_fixture("cpu", "frequency = true"));
+    }
+
+    #[test]
+    fn load_on_fixture() {
+        assert_golden("fs_root/load.txt", &render_on_fixture("load", "format = \"{1m} {5m} {15m}\""));
+    }
+
+    #[test]
+    fn memory_on_fixture() {
+        assert_golden("fs_root/memory.txt", &render_on_fixture("memory", ""));
+    }
+
+    #[test]
+    fn unit_digits_on_fixture() {
+        let mut lines = Vec::new();
+        for digits in &[1, 5] {
+            let blocks = [
+                ("memory", format!("format_mem = \"{{MT}} {{MU}}\"\ndigits = {}", digits)),
+                ("net", format!("device = \"eth0\"\ndigits = {}", digits)),
+            ];
+            for &(name, ref block_config) in &blocks {
+                for line in render_on_fixture(name, block_config) {
+                    lines.push(format!("{} {}: {}", name, digits, line));
+                }
+            }
+        }
+        assert_golden("fs_root/unit_digits.txt", &lines);
+    }
+
+    #[test]
+    fn uptime_on_fixture() {
+        assert_golden("fs_root/uptime.txt", &render_on_fixture("uptime", ""));
+    }
+
+    #[test]
+    fn placeholders_on_fixture() {
+        let value = |instance: &str, key: &str| registry::values(instance).and_then(|values| values.get(key).cloned());
+
+        render_on_fixture("load", "");
+        assert_eq!(value("load", "1m").as_ref().map(String::as_str), Some("1.50"));
+        assert_eq!(value("load", "15m").as_ref().map(String::as_str), Some("0.25"));
+
+        render_on_fixture("uptime", "");
+        assert_eq!(value("uptime", "seconds").as_ref().map(String::as_str), Some("788645"));
+
+        render_on_fixture("backlight", "device = \"intel_backlight\"");
+        assert_eq!(value("backlight", "brightness").as_ref().map(String::as_str), Some("50"));
+        assert_eq!(value("backlight", "device").as_ref().map(String::as_str), Some("intel_backlight"));
+    }
 }
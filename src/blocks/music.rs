@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use chan::Sender;
 use std::thread;
@@ -12,6 +13,7 @@ use crate::de::deserialize_duration;
 use crate::widgets::rotatingtext::RotatingTextWidget;
 use crate::widgets::button::ButtonWidget;
 use crate::widget::{I3BarWidget, State};
+use crate::persist;
 
 use crate::blocks::dbus::{arg, stdintf, BusType, Connection, ConnectionItem, Message};
 use crate::blocks::dbus::arg::{Array, RefArg};
@@ -28,7 +30,13 @@ pub struct Music {
     player_avail: bool,
     marquee: bool,
     player: Option<String>,
-    auto_discover: bool
+    auto_discover: bool,
+    /// Player that was chosen by auto-discovery last time, preferred while it is running
+    last_player: Option<String>,
+    /// Title and artist of the song playing at the last update
+    title: String,
+    artist: String,
+    instance: String,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -161,6 +169,10 @@ impl ConfigBlock for Music {
                     Some(format!("org.mpris.MediaPlayer2.{}", block_config.player.unwrap()))
                 },
             marquee: block_config.marquee,
+            last_player: persist::load(&config.instance),
+            title: String::new(),
+            artist: String::new(),
+            instance: config.instance,
         })
     }
 }
@@ -170,6 +182,11 @@ impl Block for Music {
         &self.id
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        map_to_owned!("{title}" => self.title,
+                      "{artist}" => self.artist)
+    }
+
     fn update(&mut self) -> Result<Option<Duration>> {
         let (rotated, next) = if self.marquee {
             self.current_song.next()?
@@ -177,7 +194,11 @@ impl Block for Music {
             (false, None)
         };
         if !rotated && self.player.is_none() {
-            self.player = get_first_available_player(&self.dbus_conn)
+            self.player = get_first_available_player(&self.dbus_conn, self.last_player.as_ref().map(String::as_str));
+            if self.player.is_some() && self.player != self.last_player {
+                self.last_player = self.player.clone();
+                persist::save(&self.instance, &self.last_player);
+            }
         }
         if !(rotated || self.player.is_none()) {
             let c = self.dbus_conn.with_path(
@@ -189,6 +210,8 @@ impl Block for Music {
 
             if let Ok(metadata) = data {
                 let (title, artist) = extract_from_metadata(&metadata).unwrap_or((String::new(), String::new()));
+                self.title = title.clone();
+                self.artist = artist.clone();
 
                 if title.is_empty() && artist.is_empty() {
                     self.player_avail = false;
@@ -200,6 +223,8 @@ impl Block for Music {
                 }
             } else {
                 self.current_song.set_text(String::from(""));
+                self.title.clear();
+                self.artist.clear();
                 self.player_avail = false;
                 if self.auto_discover {
                     self.player = None;
@@ -317,14 +342,14 @@ fn extract_from_metadata(metadata: &Box<arg::RefArg>) -> Result<(String, String)
     Ok((title, artist))
 }
 
-fn get_first_available_player(connection: &Connection) -> Option<String> {
+fn get_first_available_player(connection: &Connection, preferred: Option<&str>) -> Option<String> {
     let m = Message::new_method_call("org.freedesktop.DBus", "/", "org.freedesktop.DBus", "ListNames").unwrap();
     let r = connection.send_with_reply_and_block(m, 2000).unwrap();
     // ListNames returns one argument, which is an array of strings.
-    let mut arr: Array<&str, _>  = r.get1().unwrap();
-    if let Some(name) = arr.find(|entry| entry.starts_with("org.mpris.MediaPlayer2")) {
-        Some(String::from(name))
-    } else {
-        None
-    }
+    let arr: Array<&str, _>  = r.get1().unwrap();
+    let players: Vec<&str> = arr.filter(|entry| entry.starts_with("org.mpris.MediaPlayer2")).collect();
+    preferred
+        .filter(|preferred| players.contains(preferred))
+        .or_else(|| players.first().cloned())
+        .map(String::from)
 }
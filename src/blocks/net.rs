@@ -1,7 +1,8 @@
+use std::cmp;
+use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::time::{Duration, Instant};
 use chan::Sender;
 
@@ -13,6 +14,10 @@ use crate::widgets::text::TextWidget;
 use crate::widgets::graph::GraphWidget;
 use crate::widget::I3BarWidget;
 use crate::scheduler::Task;
+use crate::persist;
+use crate::rate::{Average, Counter, Rate, Smoothing};
+use crate::subprocess;
+use crate::unit::{self, Quantity, System, UnitSpec};
 
 use uuid::Uuid;
 
@@ -24,10 +29,9 @@ pub struct NetworkDevice {
 }
 
 impl NetworkDevice {
-    /// Use the network device `device`. Raises an error if a directory for that
-    /// device is not found.
-    pub fn from_device(device: String) -> Self {
-        let device_path = Path::new("/sys/class/net").join(device.clone());
+    /// Use the network device `device` in `class_path`, i.e. `/sys/class/net`.
+    pub fn from_device(class_path: &Path, device: String) -> Self {
+        let device_path = class_path.join(device.clone());
 
         // I don't believe that this should ever change, so set it now:
         let wireless = device_path.join("wireless").exists();
@@ -97,34 +101,27 @@ impl NetworkDevice {
                     .to_string(),
             ));
         }
-        let mut iw_output = Command::new("sh")
-            .args(&[
-                "-c",
-                &format!(
-                    "iw dev {} link | sed -n 's/^\\s\\+SSID: \\(.*\\)/\\1/p'",
-                    self.device
-                ),
-            ])
-            .output()
-            .block_error("net", "Failed to execute SSID query.")?
-            .stdout;
-
-        if iw_output.is_empty() {
-            iw_output = Command::new("nmcli")
-                .args(&["-g", "general.connection", "device", "show", &self.device])
-                .output()
-                .block_error("net", "Failed to execute SSID query.")?
+        // Without `iw`, there is still `nmcli` to ask
+        let iw_output = subprocess::run("iw", &["dev", &self.device, "link"], subprocess::DEFAULT_TIMEOUT)
+            .map(|output| output.stdout)
+            .unwrap_or_default();
+        let mut ssid = iw_output
+            .lines()
+            .map(|line| line.trim_start())
+            .find(|line| line.starts_with("SSID: "))
+            .map(|line| line["SSID: ".len()..].to_owned());
+
+        if ssid.is_none() {
+            let nmcli_output = subprocess::run(
+                "nmcli",
+                &["-g", "general.connection", "device", "show", &self.device],
+                subprocess::DEFAULT_TIMEOUT,
+            ).block_error("net", "Failed to execute SSID query.")?
                 .stdout;
+            ssid = nmcli_output.lines().next().filter(|ssid| !ssid.is_empty()).map(String::from);
         }
 
-        if iw_output.is_empty() {
-            Ok(None)
-        } else {
-            iw_output.pop(); // Remove trailing newline.
-            String::from_utf8(iw_output)
-                .block_error("net", "Non-UTF8 SSID.")
-                .map(Some)
-        }
+        Ok(ssid)
     }
 
     /// Queries the inet IP of this device (using `ip`).
@@ -132,27 +129,26 @@ impl NetworkDevice {
         if !self.is_up()? {
             return Ok(None);
         }
-        let mut ip_output = Command::new("sh")
-            .args(
-                &[
-                    "-c",
-                    &format!(
-                        "ip -oneline -family inet address show {} | sed -rn -e \"s/.*inet ([\\.0-9/]+).*/\\1/; G; s/\\n/ /;h\" -e \"$ P;\"",
-                        self.device
-                    ),
-                ],
-            )
-            .output()
-            .block_error("net", "Failed to execute IP address query.")?
+        let ip_output = subprocess::run(
+            "ip",
+            &["-oneline", "-family", "inet", "address", "show", "dev", &self.device],
+            subprocess::DEFAULT_TIMEOUT,
+        ).block_error("net", "Failed to execute IP address query.")?
             .stdout;
 
-        if ip_output.is_empty() {
+        // Lines look like `2: wlan0    inet 192.168.1.2/24 brd ...`
+        let addresses: Vec<&str> = ip_output
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                words.find(|&word| word == "inet").and_then(|_| words.next())
+            })
+            .collect();
+
+        if addresses.is_empty() {
             Ok(None)
         } else {
-            ip_output.pop(); // Remove trailing newline.
-            String::from_utf8(ip_output)
-                .block_error("net", "Non-UTF8 IP address.")
-                .map(Some)
+            Ok(Some(addresses.join(" ")))
         }
     }
 
@@ -166,26 +162,16 @@ impl NetworkDevice {
                     .to_string(),
             ));
         }
-        let mut bitrate_output = Command::new("sh")
-            .args(&[
-                "-c",
-                &format!(
-                    "iw dev {} link | awk '/tx bitrate/ {{print $3\" \"$4}}'",
-                    self.device
-                ),
-            ])
-            .output()
-            .block_error("net", "Failed to execute bitrate query.")?
-            .stdout;
-
-        if bitrate_output.is_empty() {
-            Ok(None)
-        } else {
-            bitrate_output.pop(); // Remove trailing newline.
-            String::from_utf8(bitrate_output)
-                .block_error("net", "Non-UTF8 bitrate.")
-                .map(Some)
-        }
+        let iw_output = subprocess::run("iw", &["dev", &self.device, "link"], subprocess::DEFAULT_TIMEOUT)
+            .map(|output| output.stdout)
+            .unwrap_or_default();
+
+        // The line looks like `tx bitrate: 866.7 MBit/s VHT-MCS 9 ...`
+        Ok(iw_output
+            .lines()
+            .map(|line| line.trim_start())
+            .find(|line| line.starts_with("tx bitrate:"))
+            .map(|line| line["tx bitrate:".len()..].split_whitespace().take(2).collect::<Vec<_>>().join(" ")))
     }
 }
 
@@ -204,12 +190,20 @@ pub struct Net {
     device: NetworkDevice,
     tx_buff: Vec<u64>,
     rx_buff: Vec<u64>,
-    tx_bytes: u64,
-    rx_bytes: u64,
+    tx_rate: Rate,
+    rx_rate: Rate,
+    tx_average: Average,
+    rx_average: Average,
+    /// Throughput in bytes per second, after smoothing
+    tx_speed: f64,
+    rx_speed: f64,
     active: bool,
     hide_inactive: bool,
     hide_missing: bool,
     last_update: Instant,
+    instance: String,
+    unit: UnitSpec,
+    digits: usize,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -262,6 +256,18 @@ pub struct NetConfig {
     /// Whether to show the download throughput graph of active networks.
     #[serde(default = "NetConfig::default_graph_down")]
     pub graph_down: bool,
+
+    /// Unit of the throughput, e.g. auto_bits for bits per second or a fixed unit like Mb or KiB.
+    #[serde(default = "NetConfig::default_unit")]
+    pub unit: UnitSpec,
+
+    /// Significant digits of an automatically scaled throughput.
+    #[serde(default = "unit::default_digits", deserialize_with = "unit::deserialize_digits")]
+    pub digits: usize,
+
+    /// How to smooth the throughput, e.g. `"window 5"` for the average of the last 5 updates.
+    #[serde(default)]
+    pub smoothing: Smoothing,
 }
 
 impl NetConfig {
@@ -312,17 +318,32 @@ impl NetConfig {
     fn default_graph_down() -> bool {
         false
     }
+
+    fn default_unit() -> UnitSpec {
+        UnitSpec::Auto(System::Si, Quantity::Bytes)
+    }
 }
 
 impl ConfigBlock for Net {
     type Config = NetConfig;
 
     fn new(block_config: Self::Config, config: Config, _tx_update_request: Sender<Task>) -> Result<Self> {
-        let device = NetworkDevice::from_device(block_config.device);
-        let init_rx_bytes = device.rx_bytes().unwrap_or(0);
-        let init_tx_bytes = device.tx_bytes().unwrap_or(0);
+        let device = NetworkDevice::from_device(&config.host_path("/sys/class/net"), block_config.device);
+        // The statistics are `unsigned long`s in the kernel, so they wrap around on 32 bit systems
+        let mut rx_rate = Rate::new(Counter::new().wrapping_at(usize::max_value() as u64));
+        let mut tx_rate = Rate::new(Counter::new().wrapping_at(usize::max_value() as u64));
+        if let Ok(rx_bytes) = device.rx_bytes() {
+            rx_rate.update(rx_bytes);
+        }
+        if let Ok(tx_bytes) = device.tx_bytes() {
+            tx_rate.update(tx_bytes);
+        }
         let wireless = device.is_wireless();
         let vpn = device.is_vpn();
+        // Graph history from before the last restart, as long as it has the expected length
+        let (tx_buff, rx_buff) = persist::load::<(Vec<u64>, Vec<u64>)>(&config.instance)
+            .filter(|&(ref tx, ref rx)| tx.len() == 10 && rx.len() == 10)
+            .unwrap_or_else(|| (vec![0; 10], vec![0; 10]));
         Ok(Net {
             id: Uuid::new_v4().simple().to_string(),
             update_interval: block_config.interval,
@@ -363,14 +384,21 @@ impl ConfigBlock for Net {
                 None
             },
             device,
-            rx_buff: vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-            tx_buff: vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-            rx_bytes: init_rx_bytes,
-            tx_bytes: init_tx_bytes,
+            rx_buff,
+            tx_buff,
+            rx_rate,
+            tx_rate,
+            rx_average: Average::new(block_config.smoothing),
+            tx_average: Average::new(block_config.smoothing),
+            rx_speed: 0.,
+            tx_speed: 0.,
             active: true,
             hide_inactive: block_config.hide_inactive,
             hide_missing: block_config.hide_missing,
             last_update: Instant::now() - Duration::from_secs(30),
+            instance: config.instance,
+            unit: block_config.unit,
+            digits: block_config.digits,
         })
     }
 }
@@ -396,15 +424,16 @@ fn read_file(path: &Path) -> Result<String> {
     Ok(content)
 }
 
-fn convert_speed(speed: u64) -> (f64, &'static str) {
-    // the values for the match are so the speed doesn't go above 3 characters
-    let (speed, unit) = match speed {
-        x if x > 999_999_999 => (speed as f64 / 1_000_000_000.0, "G"),
-        x if x > 999_999 => (speed as f64 / 1_000_000.0, "M"),
-        x if x > 999 => (speed as f64 / 1_000.0, "k"),
-        _ => (speed as f64, "B"),
-    };
-    (speed, unit)
+/// Formats a throughput in bytes per second with a fixed width, so that the bar doesn't jump around.
+fn format_speed(speed: f64, unit: UnitSpec, digits: usize) -> String {
+    let (value, display_unit) = unit.format(speed, digits);
+    format!(
+        "{:>value_width$}{:<unit_width$}",
+        value,
+        display_unit.to_string(),
+        value_width = cmp::max(5, digits + 2),
+        unit_width = unit.symbol_width()
+    )
 }
 
 impl Block for Net {
@@ -421,6 +450,10 @@ impl Block for Net {
             if let Some(ref mut rx_widget) = self.output_rx {
                 rx_widget.set_text("×".to_string());
             };
+            self.tx_average.reset();
+            self.rx_average.reset();
+            self.tx_speed = 0.;
+            self.rx_speed = 0.;
 
             return Ok(Some(self.update_interval));
         } else {
@@ -456,40 +489,30 @@ impl Block for Net {
             self.last_update = now;
         }
 
-        // TODO: consider using `as_nanos`
-        // Update the throughout/graph widgets if they are enabled
-        let update_interval = (self.update_interval.as_secs() as f64) + (self.update_interval.subsec_nanos() as f64 / 1_000_000_000.0);
-        if self.output_tx.is_some() || self.graph_tx.is_some() {
-            let current_tx = self.device.tx_bytes()?;
-            let tx_bytes = ((current_tx - self.tx_bytes) as f64 / update_interval) as u64;
-            let (tx_speed, tx_unit) = convert_speed(tx_bytes);
-            self.tx_bytes = current_tx;
-
-            if let Some(ref mut tx_widget) = self.output_tx {
-                tx_widget.set_text(format!("{:5.1}{}", tx_speed, tx_unit));
-            };
-
-            if let Some(ref mut graph_tx_widget) = self.graph_tx {
-                self.tx_buff.remove(0);
-                self.tx_buff.push(tx_bytes);
-                graph_tx_widget.set_values(&self.tx_buff, None, None);
-            }
+        // Rates are kept up to date even without widgets showing them, for the placeholders
+        let tx_rate = self.tx_rate.update(self.device.tx_bytes()?);
+        self.tx_speed = self.tx_average.add_rate(tx_rate);
+        if let Some(ref mut tx_widget) = self.output_tx {
+            tx_widget.set_text(format_speed(self.tx_speed, self.unit, self.digits));
+        };
+        if let Some(ref mut graph_tx_widget) = self.graph_tx {
+            self.tx_buff.remove(0);
+            self.tx_buff.push(self.tx_speed as u64);
+            graph_tx_widget.set_values(&self.tx_buff, None, None);
         }
-        if self.output_rx.is_some() || self.graph_rx.is_some() {
-            let current_rx = self.device.rx_bytes()?;
-            let rx_bytes = ((current_rx - self.rx_bytes) as f64 / update_interval) as u64;
-            let (rx_speed, rx_unit) = convert_speed(rx_bytes);
-            self.rx_bytes = current_rx;
 
-            if let Some(ref mut rx_widget) = self.output_rx {
-                rx_widget.set_text(format!("{:5.1}{}", rx_speed, rx_unit));
-            };
-
-            if let Some(ref mut graph_rx_widget) = self.graph_rx {
-                self.rx_buff.remove(0);
-                self.rx_buff.push(rx_bytes);
-                graph_rx_widget.set_values(&self.rx_buff, None, None);
-            }
+        let rx_rate = self.rx_rate.update(self.device.rx_bytes()?);
+        self.rx_speed = self.rx_average.add_rate(rx_rate);
+        if let Some(ref mut rx_widget) = self.output_rx {
+            rx_widget.set_text(format_speed(self.rx_speed, self.unit, self.digits));
+        };
+        if let Some(ref mut graph_rx_widget) = self.graph_rx {
+            self.rx_buff.remove(0);
+            self.rx_buff.push(self.rx_speed as u64);
+            graph_rx_widget.set_values(&self.rx_buff, None, None);
+        }
+        if self.graph_tx.is_some() || self.graph_rx.is_some() {
+            persist::save(&self.instance, &(&self.tx_buff, &self.rx_buff));
         }
 
         Ok(Some(self.update_interval))
@@ -531,4 +554,11 @@ impl Block for Net {
     fn id(&self) -> &str {
         &self.id
     }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        map_to_owned!("{speed_up}" => format_speed(self.tx_speed, self.unit, self.digits).trim(),
+                      "{speed_down}" => format_speed(self.rx_speed, self.unit, self.digits).trim(),
+                      "{rate_up}" => format!("{:.0}", self.tx_speed),
+                      "{rate_down}" => format!("{:.0}", self.rx_speed))
+    }
 }
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::time::{Duration, Instant};
 use std::thread;
@@ -133,6 +134,8 @@ pub struct NetworkManager {
     dbus_conn: Connection,
     manager: ConnectionManager,
     show_type: bool,
+    /// State and connection type at the last update
+    values: HashMap<String, String>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -186,6 +189,7 @@ impl ConfigBlock for NetworkManager {
             dbus_conn,
             manager,
             show_type: block_config.show_type,
+            values: HashMap::new(),
         })
     }
 }
@@ -200,6 +204,8 @@ impl Block for NetworkManager {
         let connection_type = self.manager.connection_type(&self.dbus_conn)?;
 
         self.output.set_icon(&connection_type.to_string());
+        self.values = map_to_owned!("{state}" => state.to_string(),
+                                    "{type}" => connection_type.to_string());
         self.output.set_state(match state {
             NetworkState::ConnectedGlobal => State::Good,
             NetworkState::ConnectedSite => State::Info,
@@ -221,4 +227,8 @@ impl Block for NetworkManager {
     fn view(&self) -> Vec<&I3BarWidget> {
         vec![&self.output]
     }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
 }
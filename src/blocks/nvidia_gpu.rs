@@ -1,5 +1,5 @@
+use std::collections::HashMap;
 use std::time::Duration;
-use std::process::Command;
 use chan::Sender;
 
 use crate::block::{Block, ConfigBlock};
@@ -8,6 +8,8 @@ use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
+use crate::subprocess;
+use crate::unit::{self, UnitSpec};
 use uuid::Uuid;
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
@@ -28,11 +30,15 @@ pub struct NvidiaGpu {
     show_memory: Option<ButtonWidget>,
     memory_total: String,
     memory_total_displayed: bool,
+    memory_unit: UnitSpec,
+    digits: usize,
     show_temperature: Option<TextWidget>,
     show_fan: Option<ButtonWidget>,
     fan_speed: u64,
     fan_speed_controlled: bool,
     show_clocks: Option<TextWidget>,
+    /// Values queried at the last update, as `nvidia-smi` reports them
+    values: HashMap<String, String>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -58,6 +64,14 @@ pub struct NvidiaGpuConfig {
     #[serde(default = "NvidiaGpuConfig::default_show_memory")]
     pub show_memory: bool,
 
+    /// Unit VRAM is displayed in, e.g. GiB or auto_iec.
+    #[serde(default = "NvidiaGpuConfig::default_memory_unit")]
+    pub memory_unit: UnitSpec,
+
+    /// Significant digits of memory in an automatically scaled unit.
+    #[serde(default = "unit::default_digits", deserialize_with = "unit::deserialize_digits")]
+    pub digits: usize,
+
     /// Core GPU temperature. In degrees C.
     #[serde(default = "NvidiaGpuConfig::default_show_temperature")]
     pub show_temperature: bool,
@@ -92,6 +106,10 @@ impl NvidiaGpuConfig {
         true
     }
 
+    fn default_memory_unit() -> UnitSpec {
+        UnitSpec::Fixed(unit::MIB)
+    }
+
     fn default_show_temperature() -> bool {
         true
     }
@@ -105,6 +123,40 @@ impl NvidiaGpuConfig {
     }
 }
 
+/// Formats an amount of memory as reported by nvidia-smi, which is in MiB.
+fn format_memory(mib: &str, unit: UnitSpec, digits: usize) -> String {
+    match mib.trim().parse::<f64>() {
+        Ok(mib) => {
+            let (value, display_unit) = unit.format(mib * 1024. * 1024., digits);
+            format!("{}{}", value, display_unit)
+        }
+        Err(_) => format!("{}MiB", mib),
+    }
+}
+
+/// Asks `nvidia-smi` for the comma separated `fields` of a GPU, returning the values
+/// separated by `, `.
+fn query_gpu(gpu_id: u64, fields: &str) -> Result<String> {
+    subprocess::run(
+        "nvidia-smi",
+        &[
+            "-i", &gpu_id.to_string(),
+            &format!("--query-gpu={}", fields),
+            "--format=csv,noheader,nounits"
+        ],
+        subprocess::DEFAULT_TIMEOUT,
+    ).block_error("gpu", "Failed to execute nvidia-smi.")?
+        .into_stdout("gpu", "nvidia-smi failed")
+        .map(|output| output.trim_end_matches('\n').to_owned())
+}
+
+fn nvidia_settings(args: &[&str]) -> Result<()> {
+    subprocess::run("nvidia-settings", args, subprocess::DEFAULT_TIMEOUT)
+        .block_error("gpu", "Failed to execute nvidia-settings.")?
+        .into_stdout("gpu", "nvidia-settings failed")
+        .map(|_| ())
+}
+
 impl ConfigBlock for NvidiaGpu {
     type Config = NvidiaGpuConfig;
 
@@ -112,19 +164,7 @@ impl ConfigBlock for NvidiaGpu {
         let id = Uuid::new_v4().simple().to_string();
         let id_memory = Uuid::new_v4().simple().to_string();
         let id_fans = Uuid::new_v4().simple().to_string();
-        let mut output = Command::new("nvidia-smi")
-            .args(
-                &[
-                    "-i", &block_config.gpu_id.to_string(),
-                    "--query-gpu=name,memory.total",
-                    "--format=csv,noheader,nounits"
-                ],
-            )
-            .output()
-            .block_error("gpu", "Failed to execute nvidia-smi.")?
-            .stdout;
-        output.pop(); // Remove trailing newline.
-        let result_str = String::from_utf8(output).unwrap();
+        let result_str = query_gpu(block_config.gpu_id, "name,memory.total")?;
         let result: Vec<&str> = result_str.split(", ").collect();
 
         Ok(NvidiaGpu {
@@ -148,6 +188,8 @@ impl ConfigBlock for NvidiaGpu {
             },
             memory_total: result[1].to_string(),
             memory_total_displayed: false,
+            memory_unit: block_config.memory_unit,
+            digits: block_config.digits,
             show_temperature: if block_config.show_temperature {
                 Some(TextWidget::new(config.clone())) } else {
                 None
@@ -162,6 +204,7 @@ impl ConfigBlock for NvidiaGpu {
                  Some(TextWidget::new(config.clone())) } else {
                 None
             },
+            values: HashMap::new(),
         })
     }
 }
@@ -185,33 +228,24 @@ impl Block for NvidiaGpu {
             params += "clocks.current.graphics,";
         }
 
-        let mut output = Command::new("nvidia-smi")
-            .args(
-                &[
-                    "-i", &self.gpu_id.to_string(),
-                    &format!("--query-gpu={}", params),
-                    "--format=csv,noheader,nounits"
-                ],
-            )
-            .output()
-            .block_error("gpu", "Failed to execute nvidia-smi.")?
-            .stdout;
-        output.pop(); // Remove trailing newline.
-        let result_str = String::from_utf8(output).unwrap();
+        let result_str = query_gpu(self.gpu_id, &params)?;
         // TODO
         // Change to 'retain' in rust 1.26
         let result: Vec<&str> = result_str.split(", ").collect();
+        self.values = map_to_owned!("{name}" => self.gpu_name);
 
         let mut count: usize = 0;
         if let Some(ref mut utilization_widget) = self.show_utilization {
             utilization_widget.set_text(format!("{}%", result[count]));
+            self.values.insert("{utilization}".to_owned(), result[count].trim().to_owned());
             count += 1;
         }
         if let Some(ref mut memory_widget) = self.show_memory {
+            self.values.insert("{memory}".to_owned(), result[count].trim().to_owned());
             if self.memory_total_displayed {
-                memory_widget.set_text(format!("{}MB", self.memory_total));
+                memory_widget.set_text(format_memory(&self.memory_total, self.memory_unit, self.digits));
             } else {
-                memory_widget.set_text(format!("{}MB", result[count]));
+                memory_widget.set_text(format_memory(result[count], self.memory_unit, self.digits));
             }
             count += 1;
         }
@@ -225,21 +259,24 @@ impl Block for NvidiaGpu {
                 _ => State::Critical,
             });
             temperature_widget.set_text(format!("{:02}°C", temp));
+            self.values.insert("{temperature}".to_owned(), format!("{}", temp));
             count += 1;
         }
         if let Some(ref mut fan_widget) = self.show_fan {
             self.fan_speed = result[count].parse::<u64>().unwrap();
             fan_widget.set_text(format!("{:02}%", self.fan_speed));
+            self.values.insert("{fan}".to_owned(), format!("{}", self.fan_speed));
             count += 1;
         }
         if let Some(ref mut clocks_widget) = self.show_clocks {
             clocks_widget.set_text(format!("{}MHz", result[count]));
+            self.values.insert("{clocks}".to_owned(), result[count].trim().to_owned());
         }
 
         if self.gpu_name_displayed {
-            self.gpu_widget.set_text(self.gpu_name.as_ref());
+            self.gpu_widget.set_text(self.gpu_name.as_str());
         } else {
-            self.gpu_widget.set_text(self.label.as_ref());
+            self.gpu_widget.set_text(self.label.as_str());
         }
 
         Ok(Some(self.update_interval))
@@ -277,9 +314,9 @@ impl Block for NvidiaGpu {
                 };
 
                 if self.gpu_name_displayed {
-                    self.gpu_widget.set_text(self.gpu_name.as_ref());
+                    self.gpu_widget.set_text(self.gpu_name.as_str());
                 } else {
-                    self.gpu_widget.set_text(self.label.as_ref());
+                    self.gpu_widget.set_text(self.label.as_str());
                 }
             }
 
@@ -291,22 +328,10 @@ impl Block for NvidiaGpu {
 
                 if let Some(ref mut memory_widget) = self.show_memory {
                     if self.memory_total_displayed {
-                        memory_widget.set_text(format!("{}MB", self.memory_total));
+                        memory_widget.set_text(format_memory(&self.memory_total, self.memory_unit, self.digits));
                     } else {
-                        let mut output = Command::new("nvidia-smi")
-                            .args(
-                                &[
-                                    "-i", &self.gpu_id.to_string(),
-                                    "--query-gpu=memory.used",
-                                    "--format=csv,noheader,nounits"
-                                ],
-                            )
-                            .output()
-                            .block_error("gpu", "Failed to execute nvidia-smi.")?
-                            .stdout;
-                        output.pop(); // Remove trailing newline.
-                        let result_str = String::from_utf8(output).unwrap();
-                        memory_widget.set_text(format!("{}MB", result_str));
+                        let result_str = query_gpu(self.gpu_id, "memory.used")?;
+                        memory_widget.set_text(format_memory(&result_str, self.memory_unit, self.digits));
                     }
                 }
             }
@@ -335,47 +360,23 @@ impl Block for NvidiaGpu {
                 if let Some(ref mut fan_widget) = self.show_fan {
                     if controlled_changed {
                         if self.fan_speed_controlled {
-                            Command::new("nvidia-settings")
-                                .args(
-                                    &[
-                                        "-a",
-                                        &format!("[gpu:{}]/GPUFanControlState=1",
-                                                 self.gpu_id),
-                                        "-a",
-                                        &format!("[fan:{}]/GPUTargetFanSpeed={}",
-                                                 self.gpu_id,
-                                                 self.fan_speed),
-                                    ],
-                                )
-                                .output()
-                                .block_error("gpu", "Failed to execute nvidia-settings.")?;
+                            nvidia_settings(&[
+                                "-a",
+                                &format!("[gpu:{}]/GPUFanControlState=1", self.gpu_id),
+                                "-a",
+                                &format!("[fan:{}]/GPUTargetFanSpeed={}", self.gpu_id, self.fan_speed),
+                            ])?;
                             fan_widget.set_text(format!("{:02}%", self.fan_speed));
                             fan_widget.set_state(State::Warning);
                         } else {
-                            Command::new("nvidia-settings")
-                                .args(
-                                    &[
-                                        "-a",
-                                        &format!("[gpu:{}]/GPUFanControlState=0",
-                                                 self.gpu_id),
-                                    ],
-                                )
-                                .output()
-                                .block_error("gpu", "Failed to execute nvidia-settings.")?;
+                            nvidia_settings(&["-a", &format!("[gpu:{}]/GPUFanControlState=0", self.gpu_id)])?;
                             fan_widget.set_state(State::Idle);
                         }
                     } else if self.fan_speed_controlled {
-                        Command::new("nvidia-settings")
-                            .args(
-                                &[
-                                    "-a",
-                                    &format!("[fan:{}]/GPUTargetFanSpeed={}",
-                                             self.gpu_id,
-                                             new_fan_speed),
-                                ],
-                            )
-                            .output()
-                            .block_error("gpu", "Failed to execute nvidia-settings.")?;
+                        nvidia_settings(&[
+                            "-a",
+                            &format!("[fan:{}]/GPUTargetFanSpeed={}", self.gpu_id, new_fan_speed),
+                        ])?;
                         self.fan_speed = new_fan_speed;
                         fan_widget.set_text(format!("{:02}%", new_fan_speed));
                     }
@@ -386,6 +387,10 @@ impl Block for NvidiaGpu {
         Ok(())
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
@@ -1,8 +1,8 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use std::os::unix::fs::symlink;
+use std::os::unix::fs::{symlink, PermissionsExt};
 use std::time::Duration;
-use std::process::Command;
 use std::env;
 use std::ffi::OsString;
 use chan::Sender;
@@ -15,6 +15,7 @@ use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::widgets::button::ButtonWidget;
 use crate::widget::{I3BarWidget, State};
+use crate::subprocess;
 
 use uuid::Uuid;
 
@@ -22,6 +23,7 @@ pub struct Pacman {
     output: ButtonWidget,
     id: String,
     update_interval: Duration,
+    count: usize,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -46,37 +48,29 @@ impl ConfigBlock for Pacman {
             id: Uuid::new_v4().simple().to_string(),
             update_interval: block_config.interval,
             output: ButtonWidget::new(config, "pacman").with_icon("update"),
+            count: 0,
         })
     }
 }
 
-fn run_command(var: &str) -> Result<()> {
-    Command::new("sh")
-        .args(&["-c", var])
-        .spawn()
-        .block_error("pacman", &format!("Failed to run command '{}'", var))?
-        .wait()
-        .block_error("pacman", &format!("Failed to wait for command '{}'", var))
-        .map(|_| ())
-}
-
-fn has_fake_root() -> Result<bool> {
-    Ok(
-        String::from_utf8(
-            Command::new("sh")
-                .args(&["-c", "type -P fakeroot"])
-                .output()
-                .block_error("pacman", "failed to start command to check for fakeroot")?
-                .stdout,
-        ).block_error("pacman", "failed to check for fakeroot")?
-            .trim() != "",
-    )
+/// Syncing the package databases can take a while on a slow mirror
+const SYNC_TIMEOUT: Duration = Duration::from_secs(120);
+
+fn has_fake_root() -> bool {
+    env::var_os("PATH")
+        .map(|path| {
+            env::split_paths(&path).any(|dir| {
+                fs::metadata(dir.join("fakeroot"))
+                    .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+                    .unwrap_or(false)
+            })
+        })
+        .unwrap_or(false)
 }
 
-fn get_update_count() -> Result<usize> {
-    if !has_fake_root()? {
-        return Ok(0 as usize);
-    }
+/// Where the copy of the package databases that is synced to count updates lives, like
+/// `checkupdates` keeps it.
+fn updates_db_path() -> Result<String> {
     let tmp_dir = env::temp_dir()
         .into_os_string()
         .into_string()
@@ -85,12 +79,29 @@ fn get_update_count() -> Result<usize> {
         .unwrap_or_else(|| OsString::from(""))
         .into_string()
         .block_error("pacman", "There's a problem with your $USER")?;
-    let updates_db = env::var_os("CHECKUPDATES_DB")
+    env::var_os("CHECKUPDATES_DB")
         .unwrap_or_else(|| {
             OsString::from(format!("{}/checkup-db-{}", tmp_dir, user))
         })
         .into_string()
-        .block_error("pacman", "There's a problem with your $CHECKUPDATES_DB")?;
+        .block_error("pacman", "There's a problem with your $CHECKUPDATES_DB")
+}
+
+/// Arguments to `fakeroot` that sync the databases in `updates_db`.
+fn sync_args(updates_db: &str) -> [&str; 7] {
+    ["--", "pacman", "-Sy", "--dbpath", updates_db, "--logfile", "/dev/null"]
+}
+
+/// Arguments to `env` that list the updates according to the databases in `updates_db`.
+fn count_args(updates_db: &str) -> [&str; 6] {
+    ["LC_ALL=C", "fakeroot", "pacman", "-Qu", "--dbpath", updates_db]
+}
+
+fn get_update_count() -> Result<usize> {
+    if !has_fake_root() {
+        return Ok(0 as usize);
+    }
+    let updates_db = updates_db_path()?;
 
     // Determine pacman database path
     let db_path = env::var_os("DBPath")
@@ -111,34 +122,24 @@ fn get_update_count() -> Result<usize> {
     }
 
     // Update database
-    run_command(&format!(
-        "fakeroot -- pacman -Sy --dbpath \"{}\" --logfile /dev/null &> /dev/null",
-        updates_db
-    ))?;
+    subprocess::run("fakeroot", &sync_args(&updates_db), SYNC_TIMEOUT)
+        .block_error("pacman", "Failed to sync the package databases")?;
 
     // Get update count
     Ok(
-        String::from_utf8(
-            Command::new("sh")
-                .env("LC_ALL", "C")
-                .args(&[
-                    "-c",
-                    &format!("fakeroot pacman -Qu --dbpath \"{}\"", updates_db),
-                ])
-                .output()
-                .block_error("pacman", "There was a problem running the pacman commands")?
-                .stdout,
-        ).block_error("pacman", "there was a problem parsing the output")?
+        subprocess::run("env", &count_args(&updates_db), SYNC_TIMEOUT)
+            .block_error("pacman", "There was a problem running the pacman commands")?
+            .stdout
             .lines()
             .filter(|line| !line.contains("[ignored]"))
             .count(),
     )
 }
 
-
 impl Block for Pacman {
     fn update(&mut self) -> Result<Option<Duration>> {
         let count = get_update_count()?;
+        self.count = count;
         self.output.set_text(format!("{}", count));
         self.output.set_state(match count {
             0 => State::Idle,
@@ -156,6 +157,12 @@ impl Block for Pacman {
         &self.id
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        values.insert("{count}".to_owned(), format!("{}", self.count));
+        values
+    }
+
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
         if event.name.as_ref().map(|s| s == "pacman").unwrap_or(false) && event.button == MouseButton::Left {
             self.update()?;
@@ -164,3 +171,28 @@ impl Block for Pacman {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{count_args, sync_args, updates_db_path};
+
+    #[test]
+    fn checkupdates_db_is_passed_unchanged() {
+        let hostile = "/tmp/db dir/'quoted' \"double\" $(touch pwned) & `id`; rm -rf x";
+        env::set_var("CHECKUPDATES_DB", hostile);
+        let updates_db = updates_db_path().unwrap();
+        env::remove_var("CHECKUPDATES_DB");
+
+        assert_eq!(updates_db, hostile);
+        assert_eq!(
+            sync_args(&updates_db),
+            ["--", "pacman", "-Sy", "--dbpath", hostile, "--logfile", "/dev/null"]
+        );
+        assert_eq!(
+            count_args(&updates_db),
+            ["LC_ALL=C", "fakeroot", "pacman", "-Qu", "--dbpath", hostile]
+        );
+    }
+}
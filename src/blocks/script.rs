@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use chan::Sender;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST};
+
+use crate::block::{Block, ConfigBlock};
+use crate::config::Config;
+use crate::de::{deserialize_duration, deserialize_expanded};
+use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::registry;
+use crate::scheduler::Task;
+use crate::subprocess;
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::button::ButtonWidget;
+
+use uuid::Uuid;
+
+/// How much work a script may do in one call, so that an endless loop can't hang the bar
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+type ScriptResult<T> = ::std::result::Result<T, Box<EvalAltResult>>;
+
+pub struct Script {
+    id: String,
+    update_interval: Duration,
+    output: ButtonWidget,
+    text: String,
+    engine: Engine,
+    ast: AST,
+    /// `this` in the script's functions, kept between calls
+    state: Dynamic,
+    has_update: bool,
+    has_click: bool,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptConfig {
+    /// Update interval in seconds
+    #[serde(default = "ScriptConfig::default_interval", deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+
+    /// File with the script to run
+    #[serde(default, deserialize_with = "deserialize_expanded")]
+    pub file: Option<String>,
+
+    /// The script to run, instead of a `file`
+    pub script: Option<String>,
+
+    /// Time after which commands started with `run` are killed
+    #[serde(default = "ScriptConfig::default_timeout", deserialize_with = "deserialize_duration")]
+    pub timeout: Duration,
+}
+
+impl ScriptConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn default_timeout() -> Duration {
+        subprocess::DEFAULT_TIMEOUT
+    }
+}
+
+/// Sets up an engine that can't reach anything outside of the script, e.g. load modules.
+pub fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| info!("{}", text));
+    engine.on_debug(|text, _, position| debug!("{} ({})", text, position));
+    engine
+}
+
+/// Sets up an engine that offers nothing but the functions registered here to scripts.
+fn engine(timeout: Duration) -> Engine {
+    let mut engine = sandboxed_engine();
+
+    engine.register_fn("read_file", |path: &str| -> ScriptResult<String> {
+        fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e).into())
+    });
+    engine.register_fn("run", move |program: &str| run(program, Array::new(), timeout));
+    engine.register_fn("run", move |program: &str, args: Array| run(program, args, timeout));
+    engine.register_fn("placeholder", |instance: &str, key: &str| {
+        registry::get(instance, key).map_or(Dynamic::UNIT, Dynamic::from)
+    });
+
+    engine
+}
+
+/// `run(program, args)` for scripts, which returns `#{status, stdout, stderr}`.
+fn run(program: &str, args: Array, timeout: Duration) -> ScriptResult<Map> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let output = subprocess::run(program, &args, timeout)
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+
+    let mut result = Map::new();
+    result.insert("status".into(), Dynamic::from(i64::from(output.status.code().unwrap_or(-1))));
+    result.insert("stdout".into(), Dynamic::from(output.stdout));
+    result.insert("stderr".into(), Dynamic::from(output.stderr));
+    Ok(result)
+}
+
+pub fn parse_state(state: &str) -> Option<State> {
+    match state {
+        "Idle" => Some(State::Idle),
+        "Info" => Some(State::Info),
+        "Good" => Some(State::Good),
+        "Warning" => Some(State::Warning),
+        "Critical" => Some(State::Critical),
+        _ => None,
+    }
+}
+
+fn button_name(button: &MouseButton) -> &'static str {
+    match *button {
+        MouseButton::Left => "left",
+        MouseButton::Middle => "middle",
+        MouseButton::Right => "right",
+        MouseButton::WheelUp => "wheel_up",
+        MouseButton::WheelDown => "wheel_down",
+        MouseButton::Forward => "forward",
+        MouseButton::Back => "back",
+        MouseButton::Unknown => "unknown",
+    }
+}
+
+impl ConfigBlock for Script {
+    type Config = ScriptConfig;
+
+    fn new(block_config: Self::Config, config: Config, _tx_update_request: Sender<Task>) -> Result<Self> {
+        let source = match (block_config.file, block_config.script) {
+            (Some(file), None) => fs::read_to_string(&file).block_error("script", &format!("failed to read {}", file))?,
+            (None, Some(script)) => script,
+            _ => {
+                return Err(BlockError(
+                    "script".to_owned(),
+                    "either `file` or `script` is needed".to_owned(),
+                ))
+            }
+        };
+
+        let engine = engine(block_config.timeout);
+        let ast = engine
+            .compile(&source)
+            .map_err(|e| BlockError("script".to_owned(), format!("failed to compile script: {}", e)))?;
+        let has_function = |name: &str| ast.iter_functions().any(|function| function.name == name);
+        let has_update = has_function("update");
+        let has_click = has_function("click");
+        let has_init = has_function("init");
+
+        let id = Uuid::new_v4().simple().to_string();
+        let mut script = Script {
+            output: ButtonWidget::new(config, &id),
+            id,
+            update_interval: block_config.interval,
+            text: String::new(),
+            engine,
+            ast,
+            state: Dynamic::from_map(Map::new()),
+            has_update,
+            has_click,
+        };
+
+        // Without an `update` function the whole script is evaluated on every update instead
+        if script.has_update {
+            script
+                .engine
+                .run_ast(&script.ast)
+                .map_err(|e| BlockError("script".to_owned(), format!("script failed: {}", e)))?;
+            if has_init {
+                script
+                    .call("init", ())
+                    .map(|_| ())
+                    .map_err(|e| BlockError("script".to_owned(), format!("init() failed: {}", e)))?;
+            }
+        }
+
+        Ok(script)
+    }
+}
+
+impl Script {
+    /// Calls a function of the script, with the block's state as `this`.
+    fn call(&mut self, name: &str, args: impl FuncArgs) -> ScriptResult<Dynamic> {
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        self.engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, name, args)
+    }
+
+    /// Shows what the script returned, which is either the text or a map with `text`,
+    /// `short_text`, `icon` and `state`.
+    fn set_output(&mut self, output: ScriptResult<Dynamic>) {
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => return self.set_error(&e.to_string()),
+        };
+
+        if !output.is_map() {
+            self.text = if output.is_unit() { String::new() } else { output.to_string() };
+            self.output.set_icon("");
+            self.output.set_short_text(None);
+            self.output.set_state(State::Idle);
+            self.output.set_text(self.text.clone());
+            return;
+        }
+
+        let output = output.cast::<Map>();
+        let field = |key: &str| output.get(key).filter(|value| !value.is_unit()).map(Dynamic::to_string);
+        let state = match field("state") {
+            Some(state) => match parse_state(&state) {
+                Some(state) => state,
+                None => return self.set_error(&format!("unknown state {:?}", state)),
+            },
+            None => State::Idle,
+        };
+
+        self.text = field("text").unwrap_or_default();
+        self.output.set_icon(&field("icon").unwrap_or_default());
+        self.output.set_short_text(field("short_text"));
+        self.output.set_state(state);
+        self.output.set_text(self.text.clone());
+    }
+
+    fn set_error(&mut self, error: &str) {
+        warn!("script failed: {}", error);
+        self.text = "script error".to_owned();
+        self.output.set_icon("");
+        self.output.set_short_text(None);
+        self.output.set_state(State::Critical);
+        self.output.set_text(self.text.clone());
+    }
+}
+
+impl Block for Script {
+    fn update(&mut self) -> Result<Option<Duration>> {
+        let output = if self.has_update {
+            self.call("update", ())
+        } else {
+            self.engine.eval_ast::<Dynamic>(&self.ast)
+        };
+        self.set_output(output);
+
+        Ok(Some(self.update_interval))
+    }
+
+    fn view(&self) -> Vec<&I3BarWidget> {
+        vec![&self.output]
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.name.as_ref().map_or(true, |name| name != &self.id) || !self.has_click {
+            return Ok(());
+        }
+
+        match self.call("click", (button_name(&event.button).to_owned(),)) {
+            Ok(_) => {
+                self.update()?;
+            }
+            Err(e) => self.set_error(&e.to_string()),
+        }
+
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        values.insert("{text}".to_owned(), self.text.clone());
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chan;
+
+    use super::Script;
+    use crate::block::{Block, ConfigBlock};
+    use crate::input::{I3BarEvent, MouseButton};
+    use crate::testing::config;
+    use crate::widget::State;
+
+    fn script(block_config: &str) -> Script {
+        let (tx_update_request, _rx_update_request) = chan::r#async();
+        Script::new(::toml::from_str(block_config).unwrap(), config(""), tx_update_request).unwrap()
+    }
+
+    fn text(block: &Script) -> String {
+        block.view()[0].get_rendered()["full_text"].as_str().unwrap().trim().to_owned()
+    }
+
+    fn click(block: &mut Script, name: &str, button: MouseButton) {
+        block
+            .click(&I3BarEvent {
+                name: Some(name.to_owned()),
+                instance: None,
+                x: 0,
+                y: 0,
+                button,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn endless_loops_run_out_of_operations() {
+        let mut block = script("script = 'loop { }'");
+        let started = Instant::now();
+        block.update().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(30));
+        assert_eq!(text(&block), "script error");
+        assert_eq!(block.state(), State::Critical);
+    }
+
+    #[test]
+    fn commands_are_killed_after_the_timeout() {
+        let mut block = script(
+            r#"
+            script = 'run("sleep", ["10"]).status'
+            timeout = 0.2
+            "#,
+        );
+        let started = Instant::now();
+        block.update().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(block.state(), State::Critical);
+
+        let mut block = script(r#"script = 'let r = run("sh", ["-c", "echo hi; exit 3"]); `${r.status} ${r.stdout}`'"#);
+        block.update().unwrap();
+        assert_eq!(block.state(), State::Idle);
+        assert_eq!(text(&block), "3 hi");
+    }
+
+    #[test]
+    fn state_is_kept_between_updates() {
+        let mut block = script(
+            r#"
+            script = '''
+                fn init() { this.count = 10; }
+                fn update() { this.count += 1; #{ text: `${this.count}`, state: if this.count > 11 { "Warning" } } }
+            '''
+            "#,
+        );
+        block.update().unwrap();
+        assert_eq!(text(&block), "11");
+        assert_eq!(block.state(), State::Idle);
+        block.update().unwrap();
+        assert_eq!(text(&block), "12");
+        assert_eq!(block.state(), State::Warning);
+        assert_eq!(block.placeholders()["{text}"], "12");
+    }
+
+    #[test]
+    fn clicks_on_the_block_call_click() {
+        let mut block = script(
+            r#"
+            script = '''
+                fn init() { this.button = "none"; }
+                fn click(button) { this.button = button; }
+                fn update() { this.button }
+            '''
+            "#,
+        );
+        block.update().unwrap();
+        assert_eq!(text(&block), "none");
+
+        click(&mut block, "other", MouseButton::Left);
+        assert_eq!(text(&block), "none");
+
+        let id = block.id().to_owned();
+        click(&mut block, &id, MouseButton::Right);
+        assert_eq!(text(&block), "right");
+        click(&mut block, &id, MouseButton::WheelUp);
+        assert_eq!(text(&block), "wheel_up");
+    }
+
+    #[test]
+    fn scripts_cannot_load_modules() {
+        let mut block = script(r#"script = 'import "x" as x; 1'"#);
+        block.update().unwrap();
+        assert_eq!(block.state(), State::Critical);
+    }
+}
@@ -0,0 +1,53 @@
+//! Stand-ins for the `script` and `composite` blocks in builds without the `scripting`
+//! feature, so that configs using them fail with an explanation instead of "Unknown block!".
+
+use chan::Sender;
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny};
+
+use crate::block::{Block, ConfigBlock};
+use crate::config::Config;
+use crate::errors::*;
+use crate::scheduler::Task;
+use crate::widget::I3BarWidget;
+
+/// Rejects any config, which makes creating or checking the block fail.
+pub struct ScriptingDisabled;
+
+impl<'de> Deserialize<'de> for ScriptingDisabled {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        IgnoredAny::deserialize(deserializer)?;
+        Err(de::Error::custom(
+            "the 'scripting' feature was not enabled at compile time",
+        ))
+    }
+}
+
+macro_rules! disabled_block {
+    ($block_type:ident) => {
+        pub struct $block_type;
+
+        impl Block for $block_type {
+            fn view(&self) -> Vec<&I3BarWidget> {
+                Vec::new()
+            }
+
+            fn id(&self) -> &str {
+                ""
+            }
+        }
+
+        impl ConfigBlock for $block_type {
+            type Config = ScriptingDisabled;
+
+            fn new(_: Self::Config, _: Config, _: Sender<Task>) -> Result<Self> {
+                Ok($block_type)
+            }
+        }
+    };
+}
+
+disabled_block!(Script);
+disabled_block!(Composite);
@@ -2,7 +2,7 @@
 use std::cmp::min;
 use std::cmp::max;
 use std::io::Read;
-use std::process::{Command, Stdio};
+use std::process::Stdio;
 use std::thread;
 use std::time::{Duration, Instant};
 #[cfg(feature = "pulseaudio")]
@@ -11,7 +11,6 @@ use std::rc::Rc;
 use std::cell::RefCell;
 #[cfg(feature = "pulseaudio")]
 use std::sync::Mutex;
-#[cfg(feature = "pulseaudio")]
 use std::collections::HashMap;
 #[cfg(feature = "pulseaudio")]
 use std::ops::Deref;
@@ -26,7 +25,7 @@ use crate::errors::*;
 use crate::widgets::button::ButtonWidget;
 use crate::widget::{I3BarWidget, State};
 use crate::input::{I3BarEvent, MouseButton};
-use crate::subprocess::{parse_command, spawn_child_async};
+use crate::subprocess::{self, parse_command, spawn_child_async};
 
 #[cfg(feature = "pulseaudio")]
 use crate::pulse::mainloop::standard::Mainloop;
@@ -77,11 +76,9 @@ impl SoundDevice for AlsaSoundDevice {
     fn muted(&self) -> bool { self.muted }
 
     fn get_info(&mut self) -> Result<()> {
-        let output = Command::new("amixer")
-            .args(&["get", &self.name])
-            .output()
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
-            .block_error("sound", "could not run amixer to get sound info")?;
+        let output = subprocess::run("amixer", &["get", &self.name], subprocess::DEFAULT_TIMEOUT)
+            .block_error("sound", "could not run amixer to get sound info")?
+            .into_stdout("sound", "could not get sound info")?;
 
         let last_line = &output
             .lines()
@@ -107,14 +104,12 @@ impl SoundDevice for AlsaSoundDevice {
     fn set_volume(&mut self, step: i32) -> Result<()> {
         let volume = max(0, self.volume as i32 + step) as u32;
 
-        Command::new("amixer")
-            .args(&[
-                "set",
-                &self.name,
-                &format!("{}%", volume),
-            ])
-            .output()
-            .block_error("sound", "failed to set volume")?;
+        subprocess::run(
+            "amixer",
+            &["set", &self.name, &format!("{}%", volume)],
+            subprocess::DEFAULT_TIMEOUT,
+        ).block_error("sound", "failed to set volume")?
+            .into_stdout("sound", "failed to set volume")?;
 
         self.volume = volume;
 
@@ -122,10 +117,9 @@ impl SoundDevice for AlsaSoundDevice {
     }
 
     fn toggle(&mut self) -> Result<()> {
-        Command::new("amixer")
-            .args(&["set", &self.name, "toggle"])
-            .output()
-            .block_error("sound", "failed to toggle mute")?;
+        subprocess::run("amixer", &["set", &self.name, "toggle"], subprocess::DEFAULT_TIMEOUT)
+            .block_error("sound", "failed to toggle mute")?
+            .into_stdout("sound", "failed to toggle mute")?;
 
         self.muted = !self.muted;
 
@@ -135,30 +129,42 @@ impl SoundDevice for AlsaSoundDevice {
     fn monitor(&mut self, id: String, tx_update_request: Sender<Task>) -> Result<()> {
         // Monitor volume changes in a separate thread.
         thread::spawn(move || {
-            // Line-buffer to reduce noise.
-            let mut monitor = Command::new("stdbuf")
-                .args(&["-oL", "alsactl", "monitor"])
-                .stdout(Stdio::piped())
-                .spawn()
-                .expect("Failed to start alsactl monitor")
-                .stdout
-                .expect("Failed to pipe alsactl monitor output");
+            // Line-buffer to reduce noise. The monitor runs as long as the bar and is
+            // stopped along with it.
+            let spawned = subprocess::spawn_long_running(
+                subprocess::command("stdbuf", &["-oL", "alsactl", "monitor"])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped()),
+            );
+            let mut child = match spawned {
+                Ok(child) => child,
+                Err(e) => {
+                    error!("failed to start alsactl monitor: {}", e);
+                    return;
+                }
+            };
+            let mut monitor = child.stdout.take().expect("alsactl monitor output is piped");
 
             let mut buffer = [0; 1024]; // Should be more than enough.
             loop {
                 // Block until we get some output. Doesn't really matter what
                 // the output actually is -- these are events -- we just update
                 // the sound information if *something* happens.
-                if monitor.read(&mut buffer).is_ok() {
-                    tx_update_request.send(Task {
+                match monitor.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => tx_update_request.send(Task {
                         id: id.clone(),
                         update_time: Instant::now(),
-                    });
+                    }),
                 }
                 // Don't update too often. Wait 1/4 second, fast enough for
                 // volume button mashing but slow enough to skip event spam.
                 thread::sleep(Duration::new(0, 250_000_000))
             }
+
+            let status = child.wait();
+            subprocess::forget_long_running(child.id());
+            warn!("alsactl monitor exited ({:?}), volume changes show on the next update", status);
         });
 
         Ok(())
@@ -713,6 +719,11 @@ impl Block for Sound {
         Ok(())
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        map_to_owned!("{volume}" => format!("{}", self.device.volume()),
+                      "{muted}" => format!("{}", self.device.muted()))
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
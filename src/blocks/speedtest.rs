@@ -1,5 +1,5 @@
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
-use std::process::Command;
 use std::thread::spawn;
 use std::sync::{Arc, Mutex};
 use chan::{r#async, Receiver, Sender};
@@ -12,6 +12,7 @@ use crate::errors::*;
 use crate::widgets::button::ButtonWidget;
 use crate::widget::{I3BarWidget, State};
 use crate::input::{I3BarEvent, MouseButton};
+use crate::subprocess;
 
 use uuid::Uuid;
 
@@ -45,17 +46,14 @@ impl SpeedTestConfig {
     }
 }
 
+/// A speed test that takes longer than this won't tell anything useful anyway
+const SPEEDTEST_TIMEOUT: Duration = Duration::from_secs(120);
+
 fn get_values(bytes: bool) -> Result<String> {
-    let mut cmd = Command::new("speedtest-cli");
-    cmd.arg("--simple");
-    if bytes {
-        cmd.arg("--bytes");
-    }
-    String::from_utf8(
-        cmd.output()
-            .block_error("speedtest", "could not get speedtest-cli output")?
-            .stdout,
-    ).block_error("speedtest", "could not parse speedtest-cli output")
+    let args: &[&str] = if bytes { &["--simple", "--bytes"] } else { &["--simple"] };
+    subprocess::run("speedtest-cli", args, SPEEDTEST_TIMEOUT)
+        .block_error("speedtest", "could not get speedtest-cli output")?
+        .into_stdout("speedtest", "speedtest-cli failed")
 }
 
 fn parse_values(output: &str) -> Result<Vec<f32>> {
@@ -169,6 +167,15 @@ impl Block for SpeedTest {
         Ok(())
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        match self.vals.lock() {
+            Ok(ref vals) if vals.1.len() == 3 => map_to_owned!("{ping}" => format!("{}", vals.1[0]),
+                                                                "{down}" => format!("{}", vals.1[1]),
+                                                                "{up}" => format!("{}", vals.1[2])),
+            _ => HashMap::new(),
+        }
+    }
+
     fn view(&self) -> Vec<&I3BarWidget> {
         let mut new: Vec<&I3BarWidget> = Vec::with_capacity(self.text.len());
         for w in &self.text {
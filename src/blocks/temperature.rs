@@ -1,13 +1,15 @@
+use std::collections::HashMap;
 use std::time::Duration;
-use std::process::Command;
 use crate::util::FormatTemplate;
 use chan::Sender;
 use crate::scheduler::Task;
 
 use crate::block::{Block, ConfigBlock};
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::{deserialize_duration, deserialize_opt_duration};
 use crate::errors::*;
+use crate::subprocess;
+use crate::threshold::{Thresholds, Trigger};
 use crate::widgets::button::ButtonWidget;
 use crate::widget::{I3BarWidget, State};
 use crate::input::{I3BarEvent, MouseButton};
@@ -20,11 +22,9 @@ pub struct Temperature {
     collapsed: bool,
     id: String,
     update_interval: Duration,
-    maximum_good: i64,
-    maximum_idle: i64,
-    maximum_info: i64,
-    maximum_warning: i64,
+    thresholds: Thresholds,
     format: FormatTemplate,
+    values: HashMap<String, String>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -57,6 +57,14 @@ pub struct TemperatureConfig {
     /// Format override
     #[serde(default = "TemperatureConfig::default_format")]
     pub format: String,
+
+    /// How far the temperature has to drop below a threshold before the state is relaxed again
+    #[serde(default = "TemperatureConfig::default_hysteresis")]
+    pub hysteresis: f64,
+
+    /// Minimum time a new state has to persist before it is displayed
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub min_dwell: Option<Duration>,
 }
 
 impl TemperatureConfig {
@@ -88,6 +96,9 @@ impl TemperatureConfig {
         80
     }
 
+    fn default_hysteresis() -> f64 {
+        0.
+    }
 }
 
 impl ConfigBlock for Temperature {
@@ -95,29 +106,30 @@ impl ConfigBlock for Temperature {
 
     fn new(block_config: Self::Config, config: Config, _tx_update_request: Sender<Task>) -> Result<Self> {
         let id = Uuid::new_v4().simple().to_string();
+        let clock = config.clock.clone();
         Ok(Temperature {
             update_interval: block_config.interval,
             text: ButtonWidget::new(config, &id).with_icon("thermometer"),
             output: String::new(),
             collapsed: block_config.collapsed,
             id,
-            maximum_good: block_config.good,
-            maximum_idle: block_config.idle,
-            maximum_info: block_config.info,
-            maximum_warning: block_config.warning,
+            thresholds: Thresholds::new(State::Good, block_config.hysteresis, block_config.min_dwell, clock)
+                .with_level(State::Idle, Trigger::Above, block_config.good as f64)
+                .with_level(State::Info, Trigger::Above, block_config.idle as f64)
+                .with_level(State::Warning, Trigger::Above, block_config.info as f64)
+                .with_level(State::Critical, Trigger::Above, block_config.warning as f64),
             format: FormatTemplate::from_string(&block_config.format)
                 .block_error("temperature", "Invalid format specified for temperature")?,
+            values: HashMap::new(),
         })
     }
 }
 
 impl Block for Temperature {
     fn update(&mut self) -> Result<Option<Duration>> {
-        let output = Command::new("sensors")
-            .args(&["-u"])
-            .output()
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
-            .unwrap_or_else(|e| e.description().to_owned());
+        let output = subprocess::run("sensors", &["-u"], subprocess::DEFAULT_TIMEOUT)
+            .map(|o| o.stdout.trim().to_owned())
+            .unwrap_or_else(|e| e.to_string());
 
         let mut temperatures: Vec<i64> = Vec::new();
 
@@ -137,7 +149,7 @@ impl Block for Temperature {
                         }
                         Ok(t) => {
                             // This error is recoverable and therefore should not stop the program
-                            eprintln!("Temperature ({}) outside of range ([-100, 150])", t);
+                            warn!("temperature ({}) outside of range ([-100, 150])", t);
                             Ok(())
                         }
                         Err(_) => Err(BlockError(
@@ -165,18 +177,12 @@ impl Block for Temperature {
                               "{max}" => max);
 
             self.output = self.format.render_static_str(&values)?;
+            self.values = values.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
             if !self.collapsed {
                 self.text.set_text(self.output.clone());
             }
 
-            let state = match max {
-                m if m <= self.maximum_good => State::Good,
-                m if m <= self.maximum_idle => State::Idle,
-                m if m <= self.maximum_info => State::Info,
-                m if m <= self.maximum_warning => State::Warning,
-                _ => State::Critical,
-            };
-
+            let state = self.thresholds.state(max as f64);
             self.text.set_state(state);
         }
 
@@ -187,6 +193,10 @@ impl Block for Temperature {
         vec![&self.text]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.values.clone()
+    }
+
     fn click(&mut self, e: &I3BarEvent) -> Result<()> {
         if let Some(ref name) = e.name {
             if name.as_str() == self.id && e.button == MouseButton::Left {
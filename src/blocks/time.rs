@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
 use crate::block::{Block, ConfigBlock};
@@ -19,6 +20,9 @@ pub struct Time {
     id: String,
     update_interval: Duration,
     format: String,
+    /// The time as shown and as a Unix timestamp, at the last update
+    text: String,
+    timestamp: i64,
     on_click: Option<String>,
     timezone: Option<Tz>,
 }
@@ -67,6 +71,8 @@ impl ConfigBlock for Time {
         Ok(Time {
             id: i.clone(),
             format: block_config.format,
+            text: String::new(),
+            timestamp: 0,
             time: ButtonWidget::new(config, i.as_str())
                 .with_text("")
                 .with_icon("time"),
@@ -79,11 +85,14 @@ impl ConfigBlock for Time {
 
 impl Block for Time {
     fn update(&mut self) -> Result<Option<Duration>> {
+        let now = Utc::now();
         let time = match self.timezone {
-            Some(tz) => Utc::now().with_timezone(&tz).format(&self.format),
-            None => Local::now().format(&self.format),
+            Some(tz) => now.with_timezone(&tz).format(&self.format),
+            None => now.with_timezone(&Local).format(&self.format),
         };
-        self.time.set_text(format!("{}", time));
+        self.text = format!("{}", time);
+        self.timestamp = now.timestamp();
+        self.time.set_text(self.text.clone());
         Ok(Some(self.update_interval))
     }
 
@@ -104,6 +113,11 @@ impl Block for Time {
         vec![&self.time]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        map_to_owned!("{time}" => self.text,
+                      "{timestamp}" => format!("{}", self.timestamp))
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
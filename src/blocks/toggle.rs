@@ -1,6 +1,5 @@
-use std::env;
+use std::collections::HashMap;
 use std::time::Duration;
-use std::process::Command;
 use chan::Sender;
 use crate::scheduler::Task;
 
@@ -11,6 +10,8 @@ use crate::errors::*;
 use crate::widgets::button::ButtonWidget;
 use crate::widget::I3BarWidget;
 use crate::input::I3BarEvent;
+use crate::persist;
+use crate::subprocess;
 
 use uuid::Uuid;
 
@@ -24,6 +25,7 @@ pub struct Toggle {
     update_interval: Option<Duration>,
     toggled: bool,
     id: String,
+    instance: String,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -69,15 +71,27 @@ impl ConfigBlock for Toggle {
 
     fn new(block_config: Self::Config, config: Config, _tx_update_request: Sender<Task>) -> Result<Self> {
         let id = Uuid::new_v4().simple().to_string();
+        let instance = config.instance.clone();
+        // Show the last known state until `command_state` has been run for the first time
+        let toggled = persist::load(&instance).unwrap_or(false);
+        let icon = if toggled {
+            &block_config.icon_on
+        } else {
+            &block_config.icon_off
+        };
+        let text = ButtonWidget::new(config, &id)
+            .with_content(block_config.text)
+            .with_icon(icon);
         Ok(Toggle {
-            text: ButtonWidget::new(config, &id).with_content(block_config.text),
+            text,
             command_on: block_config.command_on,
             command_off: block_config.command_off,
             command_state: block_config.command_state,
             icon_on: block_config.icon_on,
             icon_off: block_config.icon_off,
             id,
-            toggled: false,
+            instance,
+            toggled,
             update_interval: block_config.interval,
         })
     }
@@ -85,11 +99,9 @@ impl ConfigBlock for Toggle {
 
 impl Block for Toggle {
     fn update(&mut self) -> Result<Option<Duration>> {
-        let output = Command::new(env::var("SHELL").unwrap_or("sh".to_owned()))
-            .args(&["-c", &self.command_state])
-            .output()
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
-            .unwrap_or_else(|e| e.description().to_owned());
+        let output = subprocess::run_shell(&self.command_state, subprocess::DEFAULT_TIMEOUT)
+            .map(|o| o.stdout.trim().to_owned())
+            .unwrap_or_else(|e| e.to_string());
 
         self.text.set_icon(match output.trim_start() {
             "" => {
@@ -101,6 +113,7 @@ impl Block for Toggle {
                 self.icon_on.as_str()
             }
         });
+        persist::save(&self.instance, &self.toggled);
 
         Ok(self.update_interval)
     }
@@ -122,16 +135,18 @@ impl Block for Toggle {
                     &self.command_on
                 };
 
-                Command::new(env::var("SHELL").unwrap_or("sh".to_owned()))
-                    .args(&["-c", cmd])
-                    .output()
-                    .block_error("toggle", "failed to run toggle command")?;
+                subprocess::spawn_shell_async(cmd).block_error("toggle", "failed to run toggle command")?;
+                persist::save(&self.instance, &self.toggled);
             }
         }
 
         Ok(())
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        map_to_owned!("{toggled}" => format!("{}", self.toggled))
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::collections::HashMap;
 use std::time::Duration;
 
 use chan::Sender;
@@ -17,9 +17,12 @@ pub struct Uptime {
     text: TextWidget,
     id: String,
     update_interval: Duration,
+    /// Seconds since boot at the last update
+    seconds: u32,
+
+    config: Config,
 
     //useful, but optional
-    #[allow(dead_code)] config: Config,
     #[allow(dead_code)] tx_update_request: Sender<Task>,
 }
 
@@ -44,6 +47,7 @@ impl ConfigBlock for Uptime {
         Ok(Uptime {
             id: Uuid::new_v4().simple().to_string(),
             update_interval: block_config.interval,
+            seconds: 0,
             text: TextWidget::new(config.clone()).with_icon("uptime"),
             tx_update_request,
             config,
@@ -53,7 +57,7 @@ impl ConfigBlock for Uptime {
 
 impl Block for Uptime {
     fn update(&mut self) -> Result<Option<Duration>> {
-        let uptime_raw = match read_file("uptime", Path::new("/proc/uptime")) {
+        let uptime_raw = match read_file("uptime", &self.config.host_path("/proc/uptime")) {
             Ok(file) => file,
             Err(e) => {
                 return Err(BlockError(
@@ -107,6 +111,7 @@ impl Block for Uptime {
         } else {
             unreachable!()
         };
+        self.seconds = total_seconds;
         self.text.set_text(text);
         Ok(Some(self.update_interval))
     }
@@ -115,6 +120,10 @@ impl Block for Uptime {
         vec![&self.text]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        map_to_owned!("{seconds}" => format!("{}", self.seconds))
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::process::Command;
 use std::time::Duration;
 use chan::Sender;
 use serde_json;
@@ -11,7 +10,8 @@ use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::FormatTemplate;
+use crate::subprocess;
+use crate::util::{percent_encode, FormatTemplate};
 use crate::widgets::button::ButtonWidget;
 use crate::widget::I3BarWidget;
 
@@ -56,26 +56,19 @@ impl Weather {
                 ref city_id,
                 ref units,
             } => {
-                let output = Command::new("sh")
-                    .args(
-                        &[
-                            "-c",
-                            &format!(
-                                "curl -m 3 \"http://api.openweathermap.org/data/2.5/weather?id={city_id}&appid={api_key}&units={units}\" 2> /dev/null",
-                                city_id = city_id,
-                                api_key = api_key,
-                                units = match *units {
-                                    OpenWeatherMapUnits::Metric => "metric",
-                                    OpenWeatherMapUnits::Imperial => "imperial",
-                                },
-                            ),
-                        ],
-                    )
-                    .output()
-                    .block_error("weather", "Failed to exectute curl.")
-                    .and_then(|raw_output| {
-                        String::from_utf8(raw_output.stdout).block_error("weather", "Non-UTF8 SSID.")
-                    })?;
+                let url = format!(
+                    "http://api.openweathermap.org/data/2.5/weather?id={city_id}&appid={api_key}&units={units}",
+                    city_id = percent_encode(city_id),
+                    api_key = percent_encode(api_key),
+                    units = match *units {
+                        OpenWeatherMapUnits::Metric => "metric",
+                        OpenWeatherMapUnits::Imperial => "imperial",
+                    },
+                );
+                // A failed request leaves the output empty, see below
+                let output = subprocess::run("curl", &["-m", "3", "--silent", &url], Duration::from_secs(5))
+                    .block_error("weather", "Failed to exectute curl.")?
+                    .stdout;
 
                 // Don't error out on empty responses e.g. for when not
                 // connected to the internet.
@@ -236,6 +229,10 @@ impl Block for Weather {
         vec![&self.weather]
     }
 
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.weather_keys.clone()
+    }
+
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
         if event.matches_name(self.id()) {
             if let MouseButton::Left = event.button {
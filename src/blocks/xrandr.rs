@@ -1,5 +1,5 @@
+use std::collections::HashMap;
 use std::time::Duration;
-use std::process::Command;
 use std::str::FromStr;
 use chan::Sender;
 use crate::scheduler::Task;
@@ -13,6 +13,7 @@ use crate::errors::*;
 use crate::widgets::button::ButtonWidget;
 use crate::widget::I3BarWidget;
 use crate::input::{I3BarEvent, MouseButton};
+use crate::subprocess;
 
 use uuid::Uuid;
 
@@ -32,17 +33,10 @@ impl Monitor {
     }
 
     fn set_brightness(&mut self, step: i32) {
-        Command::new("sh")
-            .args(&[
-                "-c",
-                format!(
-                    "xrandr --output {} --brightness {}",
-                    self.name,
-                    (self.brightness as i32 + step) as f32 / 100.0
-                ).as_str(),
-            ])
-            .spawn()
-            .expect("Failed to set xrandr output.");
+        let brightness = ((self.brightness as i32 + step) as f32 / 100.0).to_string();
+        if let Err(e) = subprocess::spawn_child_async("xrandr", &["--output", &self.name, "--brightness", &brightness]) {
+            warn!("failed to set xrandr brightness: {}", e);
+        }
         self.brightness = (self.brightness as i32 + step) as u32;
     }
 }
@@ -108,16 +102,25 @@ macro_rules! unwrap_or_continue {
     )
 }
 
+/// Every monitor's `<name> connected ...` line in the output of `xrandr --verbose`, each
+/// followed by its brightness. Names are compared as they are, not as patterns.
+fn monitor_lines<'a>(output: &'a str, monitor_names: &[String]) -> Vec<&'a str> {
+    output
+        .lines()
+        .filter(|line| {
+            line.trim_start().starts_with("Brightness")
+                || monitor_names.iter().any(|name| line.starts_with(&format!("{} connected", name)))
+        })
+        .collect()
+}
+
 impl Xrandr {
     fn get_active_monitors() -> Result<Option<Vec<String>>> {
-        let active_montiors_cli = String::from_utf8(
-            Command::new("sh")
-                .args(&["-c", "xrandr --listactivemonitors | grep \\/"])
-                .output()
-                .block_error("xrandr", "couldn't collect active xrandr monitors")?
-                .stdout,
-        ).block_error("xrandr", "couldn't parse xrandr monitor list")?;
-        let monitors: Vec<&str> = active_montiors_cli.split('\n').collect();
+        let active_montiors_cli = subprocess::run("xrandr", &["--listactivemonitors"], subprocess::DEFAULT_TIMEOUT)
+            .block_error("xrandr", "couldn't collect active xrandr monitors")?
+            .into_stdout("xrandr", "couldn't collect active xrandr monitors")?;
+        // Monitors are listed like ` 0: +*eDP-1 1920/344x1080/194+0+0  eDP-1`
+        let monitors: Vec<&str> = active_montiors_cli.lines().filter(|line| line.contains('/')).collect();
         let mut active_monitors: Vec<String> = Vec::new();
         for monitor in monitors {
             if let Some((name, _)) = monitor
@@ -137,19 +140,11 @@ impl Xrandr {
 
     fn get_monitor_metrics(monitor_names: &[String]) -> Result<Option<Vec<Monitor>>> {
         let mut monitor_metrics: Vec<Monitor> = Vec::new();
-        let grep_arg = format!(
-            "xrandr --verbose | grep -w '{} connected\\|Brightness'",
-            monitor_names.join(" connected\\|")
-        );
-        let monitor_info_cli = String::from_utf8(
-            Command::new("sh")
-                .args(&["-c", grep_arg.as_str()])
-                .output()
-                .block_error("xrandr", "couldn't collect xrandr monitor info")?
-                .stdout,
-        ).block_error("xrandr", "couldn't parse xrandr monitor info")?;
-
-        let monitor_infos: Vec<&str> = monitor_info_cli.split('\n').collect();
+        let monitor_info_cli = subprocess::run("xrandr", &["--verbose"], subprocess::DEFAULT_TIMEOUT)
+            .block_error("xrandr", "couldn't collect xrandr monitor info")?
+            .into_stdout("xrandr", "couldn't collect xrandr monitor info")?;
+
+        let monitor_infos = monitor_lines(&monitor_info_cli, monitor_names);
         for i in 0..monitor_infos.len() {
             if i % 2 == 1 {
                 continue;
@@ -280,7 +275,54 @@ impl Block for Xrandr {
         Ok(())
     }
 
+    /// Values of the monitor shown
+    fn placeholders(&self) -> HashMap<String, String> {
+        match self.monitors.get(self.current_idx) {
+            Some(m) => map_to_owned!("{display}" => m.name,
+                                     "{brightness}" => m.brightness.to_string(),
+                                     "{resolution}" => m.resolution),
+            None => HashMap::new(),
+        }
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::monitor_lines;
+
+    const OUTPUT: &str = "\
+Screen 0: minimum 8 x 8, current 1920 x 1080, maximum 32767 x 32767
+eDP-1 connected primary 1920x1080+0+0 (0x48) normal (normal left inverted right x axis y axis) 344mm x 194mm
+\tBrightness: 0.80
+HDMI-1 connected 1280x1024+1920+0 (0x4a) normal (normal left inverted right x axis y axis) 376mm x 301mm
+\tBrightness: 1.00
+";
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn monitors_are_matched_by_name() {
+        let lines = monitor_lines(OUTPUT, &names(&["eDP-1", "HDMI-1"]));
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("eDP-1 connected"));
+        assert!(lines[2].starts_with("HDMI-1 connected"));
+    }
+
+    #[test]
+    fn hostile_names_are_matched_literally() {
+        let hostile = names(&[".*", "eDP", "[eH]DMI-1", "$(xrandr --off)", "eDP-1 & HDMI-1", "'eDP-1'", "\"HDMI-1\""]);
+        let lines = monitor_lines(OUTPUT, &hostile);
+        assert!(lines.iter().all(|line| line.trim_start().starts_with("Brightness")), "{:?}", lines);
+
+        let output = format!("{}$(xrandr --off) & x connected 800x600+0+0 (0x4c)\n", OUTPUT);
+        let lines = monitor_lines(&output, &names(&["$(xrandr --off) & x"]));
+        assert!(lines.contains(&"$(xrandr --off) & x connected 800x600+0+0 (0x4c)"));
+        assert!(!lines.iter().any(|line| line.starts_with("eDP-1") || line.starts_with("HDMI-1")));
+    }
+}
@@ -0,0 +1,359 @@
+//! `--check`: validates a config file without running the bar and reports every problem
+//! found, instead of stopping at the first one like a normal start does.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use regex::Regex;
+use toml::value::{Table, Value};
+
+use crate::blocks::{check_block_config, BLOCK_NAMES, COMMON_OPTION_KEYS};
+use crate::config::{include_paths, load_config, MAX_INCLUDE_DEPTH};
+use crate::errors::*;
+
+/// Keys that are allowed at the top level of the config file.
+const TOP_LEVEL_KEYS: &[&str] = &["icons", "theme", "block", "fs_root", "include", "redraw_delay"];
+
+struct Problem {
+    line: Option<usize>,
+    column: Option<usize>,
+    message: String,
+}
+
+/// Checks the config file at `path` and the files it includes, prints the problems found
+/// and returns the exit code.
+pub fn check(path: &Path) -> i32 {
+    report(path, &mut io::stdout())
+}
+
+/// `check`, writing the problems to `out`.
+fn report(path: &Path, out: &mut Write) -> i32 {
+    let mut count = check_file(path, 0, out);
+
+    // Files are checked one by one above, but `theme` and `icons` can be spread over several
+    // of them and are only complete once they are merged.
+    if count == 0 {
+        match load_config(path) {
+            Err(ConfigurationError(ref message, (ref cause, _))) => {
+                let _ = writeln!(out, "{}: {}: {}", path.display(), message, cause);
+                count += 1;
+            }
+            Err(e) => {
+                let _ = writeln!(out, "{}: {}", path.display(), e);
+                count += 1;
+            }
+            Ok(_) => (),
+        }
+    }
+
+    if count == 0 {
+        let _ = writeln!(out, "{}: OK", path.display());
+        0
+    } else {
+        let _ = writeln!(out, "{}: {} problem(s) found", path.display(), count);
+        1
+    }
+}
+
+/// Checks a single file on its own and recurses into its includes. Returns the number of
+/// problems found.
+fn check_file(path: &Path, depth: usize, out: &mut Write) -> usize {
+    let mut problems = Vec::new();
+    let mut includes = Vec::new();
+
+    if depth > MAX_INCLUDE_DEPTH {
+        problems.push(Problem {
+            line: None,
+            column: None,
+            message: "too many nested includes, is a file including itself?".to_owned(),
+        });
+    } else {
+        match File::open(path).and_then(|mut f| {
+            let mut source = String::new();
+            f.read_to_string(&mut source).map(|_| source)
+        }) {
+            Ok(source) => {
+                problems = check_source(&source);
+                if let Ok(root) = source.parse::<Value>() {
+                    match include_paths(path, &root) {
+                        Ok(paths) => includes = paths,
+                        Err(e) => problems.push(Problem {
+                            line: None,
+                            column: None,
+                            message: format!("{}", e),
+                        }),
+                    }
+                }
+            }
+            Err(e) => problems.push(Problem {
+                line: None,
+                column: None,
+                message: format!("failed to read file: {}", e),
+            }),
+        }
+    }
+
+    problems.sort_by_key(|problem| problem.line);
+    for problem in &problems {
+        let _ = match (problem.line, problem.column) {
+            (Some(line), Some(column)) => writeln!(out, "{}:{}:{}: {}", path.display(), line, column, problem.message),
+            (Some(line), None) => writeln!(out, "{}:{}: {}", path.display(), line, problem.message),
+            _ => writeln!(out, "{}: {}", path.display(), problem.message),
+        };
+    }
+
+    problems.len() + includes.iter().map(|include| check_file(include, depth + 1, out)).sum::<usize>()
+}
+
+fn check_source(source: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    let root = match source.parse::<Value>() {
+        Ok(root) => root,
+        Err(e) => {
+            // The parser stops at the first syntax error, so there is nothing more to check.
+            let position = e.line_col();
+            problems.push(Problem {
+                line: position.map(|(line, _)| line + 1),
+                column: position.map(|(_, column)| column + 1),
+                message: format!("invalid TOML: {}", e),
+            });
+            return problems;
+        }
+    };
+
+    let headers = block_headers(source);
+    let top_level_end = headers.first().cloned().unwrap_or_else(|| source.lines().count());
+    if let Some(table) = root.as_table() {
+        for key in table.keys() {
+            if !TOP_LEVEL_KEYS.contains(&key.as_str()) {
+                problems.push(Problem {
+                    line: find_line(source, 0, top_level_end, key),
+                    column: None,
+                    message: unknown("top level key", key, "", TOP_LEVEL_KEYS),
+                });
+            }
+        }
+    }
+
+    let blocks = match root.get("block") {
+        Some(&Value::Array(ref blocks)) => blocks.clone(),
+        Some(_) => {
+            problems.push(Problem {
+                line: None,
+                column: None,
+                message: "`block` has to be an array of tables, i.e. `[[block]]`".to_owned(),
+            });
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+
+    for (index, block) in blocks.into_iter().enumerate() {
+        let start = headers.get(index).cloned();
+        let end = headers.get(index + 1).cloned().unwrap_or_else(|| source.lines().count());
+        check_block(source, block, start, end, &mut problems);
+    }
+
+    problems
+}
+
+fn check_block(source: &str, block: Value, start: Option<usize>, end: usize, problems: &mut Vec<Problem>) {
+    let mut table: Table = match block {
+        Value::Table(table) => table,
+        _ => return,
+    };
+    let header_line = start.map(|start| start + 1);
+
+    let name = match table.remove("block") {
+        Some(Value::String(name)) => name,
+        Some(_) => {
+            problems.push(Problem {
+                line: header_line,
+                column: None,
+                message: "`block` has to be a string".to_owned(),
+            });
+            return;
+        }
+        None => {
+            problems.push(Problem {
+                line: header_line,
+                column: None,
+                message: "block without a `block = \"...\"` name".to_owned(),
+            });
+            return;
+        }
+    };
+
+    if !BLOCK_NAMES.contains(&name.as_str()) {
+        problems.push(Problem {
+            line: start.and_then(|start| find_line(source, start, end, "block")).or(header_line),
+            column: None,
+            message: unknown("block", &name, "", BLOCK_NAMES),
+        });
+        return;
+    }
+
+    // serde stops at the first bad key, so each one is reported and taken out before
+    // checking again, until the rest is fine or a problem can't be pinned to a key
+    while let Err(e) = check_block_config(&name, Value::Table(table.clone())) {
+        let detail = match e {
+            ConfigurationError(ref message, (ref cause, _)) => format!("{}: {}", message, cause),
+            BlockError(_, ref message) => message.clone(),
+            ref e => format!("{}", e),
+        };
+        // serde reports unknown keys as "unknown field `x`, expected one of `a`, `b`"
+        match unknown_field(&detail) {
+            Some((field, expected)) => {
+                let mut known: Vec<&str> = expected.iter().map(String::as_str).collect();
+                known.extend_from_slice(COMMON_OPTION_KEYS);
+                problems.push(Problem {
+                    line: start.and_then(|start| find_line(source, start, end, &field)).or(header_line),
+                    column: None,
+                    message: unknown("key", &field, &format!(" in block '{}'", name), &known),
+                });
+                // An unknown key of a nested table, e.g. of a block in a group, stops here
+                if table.remove(&field).is_none() {
+                    return;
+                }
+            }
+            None => {
+                let key = bad_key(&detail);
+                problems.push(Problem {
+                    line: key
+                        .as_ref()
+                        .and_then(|key| start.and_then(|start| find_line(source, start, end, key)))
+                        .or(header_line),
+                    column: None,
+                    message: format!("block '{}': {}", name, detail),
+                });
+                match key {
+                    Some(ref key) if table.remove(key).is_some() => (),
+                    _ => return,
+                }
+            }
+        }
+    }
+}
+
+/// Extracts the top level key from the "... for key `x.y`" that toml adds to errors with
+/// a value.
+fn bad_key(message: &str) -> Option<String> {
+    let rest = &message[message.rfind("for key `")? + "for key `".len()..];
+    let key = rest.split('`').next()?;
+    key.split('.').next().map(String::from)
+}
+
+/// Formats an "unknown ..." message, with a suggestion if there is a similar known name.
+fn unknown(what: &str, name: &str, context: &str, known: &[&str]) -> String {
+    let suggestion = known
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= (name.len() / 3).max(2))
+        .min_by_key(|&(distance, _)| distance);
+
+    match suggestion {
+        Some((_, candidate)) => format!("unknown {} '{}'{}, did you mean '{}'?", what, name, context, candidate),
+        None => format!("unknown {} '{}'{}", what, name, context),
+    }
+}
+
+/// Extracts the field and the expected fields from serde's unknown field error.
+fn unknown_field(message: &str) -> Option<(String, Vec<String>)> {
+    let rest = &message[message.find("unknown field `")? + "unknown field ".len()..];
+    let mut quoted = rest.split('`').skip(1).step_by(2).map(String::from);
+    let field = quoted.next()?;
+    Some((field, quoted.collect()))
+}
+
+/// 0-based line numbers of the `[[block]]` headers.
+fn block_headers(source: &str) -> Vec<usize> {
+    lazy_static! {
+        static ref HEADER: Regex = Regex::new(r"^\s*\[\[\s*block\s*\]\]\s*(#.*)?$").unwrap();
+    }
+    source
+        .lines()
+        .enumerate()
+        .filter(|&(_, line)| HEADER.is_match(line))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// 1-based line number of the first `key = ...` between the 0-based lines `start` and `end`.
+fn find_line(source: &str, start: usize, end: usize, key: &str) -> Option<usize> {
+    source
+        .lines()
+        .enumerate()
+        .skip(start)
+        .take(end - start)
+        .find(|&(_, line)| {
+            let line = line.trim_start();
+            line.starts_with(key) && line[key.len()..].trim_start().starts_with('=')
+        })
+        .map(|(index, _)| index + 1)
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, report, unknown};
+    use crate::testing::{assert_golden, testdata};
+
+    /// The report for the config file `testdata/check/<name>`, with the paths shortened.
+    fn report_of(name: &str) -> (i32, Vec<String>) {
+        let path = testdata("check").join(name);
+        let mut out = Vec::new();
+        let code = report(&path, &mut out);
+        let prefix = testdata("").display().to_string();
+        let lines = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.replace(&prefix, "testdata/"))
+            .collect();
+        (code, lines)
+    }
+
+    #[test]
+    fn bad_config_reports_every_problem() {
+        let (code, lines) = report_of("bad.toml");
+        assert_eq!(code, 1);
+        assert_golden("check_bad.txt", &lines);
+    }
+
+    #[test]
+    fn good_config_is_ok() {
+        let (code, lines) = report_of("good.toml");
+        assert_eq!(code, 0);
+        assert_eq!(lines, vec!["testdata/check/good.toml: OK"]);
+    }
+
+    #[test]
+    fn suggestions_are_close_names() {
+        let known = &["interval", "format", "timezone"];
+        assert_eq!(unknown("key", "intervall", "", known), "unknown key 'intervall', did you mean 'interval'?");
+        assert_eq!(unknown("key", "fromat", "", known), "unknown key 'fromat', did you mean 'format'?");
+        assert_eq!(unknown("key", "color", "", known), "unknown key 'color'");
+
+        assert_eq!(edit_distance("cpu", "cpu"), 0);
+        assert_eq!(edit_distance("cpuu", "cpu"), 1);
+        assert_eq!(edit_distance("alais", "alias"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -1,11 +1,16 @@
 use crate::de::*;
+use crate::errors::{self, ConfigurationError, ResultExtInternal};
 use crate::icons;
+use crate::scheduler::SharedClock;
+use crate::util::deserialize_file;
 use serde::de::{self, Deserialize, Deserializer};
 use toml::value;
 use std::collections::HashMap as Map;
 use std::marker::PhantomData;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 use crate::themes::{self, Theme};
 
 #[derive(Deserialize, Debug, Clone)]
@@ -14,8 +19,26 @@ pub struct Config {
     pub icons: Map<String, String>,
     #[serde(default = "themes::default", deserialize_with = "deserialize_themes")]
     pub theme: Theme,
-    #[serde(rename = "block", deserialize_with = "deserialize_blocks")]
+    #[serde(rename = "block", default, deserialize_with = "deserialize_blocks")]
     pub blocks: Vec<(String, value::Value)>,
+    /// Directory that `/sys` and `/proc` are looked up in, so that blocks can be run
+    /// against a snapshot of another machine.
+    #[serde(default = "Config::default_fs_root")]
+    pub fs_root: PathBuf,
+    /// Updates arriving within this time after one another are shown in a single redraw
+    #[serde(default = "Config::default_redraw_delay", deserialize_with = "deserialize_duration")]
+    pub redraw_delay: Duration,
+    /// Stable name of the block this copy of the config is handed to, used as key for
+    /// state that is persisted across restarts. Not read from the config file.
+    #[serde(skip)]
+    pub instance: String,
+    /// Outputs and id of the bar, as given with `--output` and `--bar-id`, that blocks
+    /// limited to some `outputs` are matched against. Not read from the config file.
+    #[serde(skip)]
+    pub bar_outputs: Vec<String>,
+    /// Clock that the bar schedules updates by. Not read from the config file.
+    #[serde(skip)]
+    pub clock: SharedClock,
 }
 
 impl Default for Config {
@@ -24,10 +47,166 @@ impl Default for Config {
             icons: icons::default(),
             theme: themes::default(),
             blocks: Vec::new(),
+            fs_root: Config::default_fs_root(),
+            redraw_delay: Config::default_redraw_delay(),
+            instance: String::new(),
+            bar_outputs: Vec::new(),
+            clock: SharedClock::default(),
         }
     }
 }
 
+impl Config {
+    fn default_fs_root() -> PathBuf {
+        PathBuf::from("/")
+    }
+
+    fn default_redraw_delay() -> Duration {
+        Duration::from_millis(20)
+    }
+
+    /// Resolves an absolute path like `/sys/class/net` against `fs_root`.
+    pub fn host_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        self.fs_root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+/// Included files can include further files, but not endlessly.
+pub(crate) const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Reads the config file at `path`, with the files it includes merged in.
+///
+/// `include = ["common.toml", "host-${HOSTNAME}.toml"]` lists files (relative to the
+/// including file) that are read first, in order, with the including file on top. Their
+/// blocks come before the blocks of the including file, `theme` and `icons` are merged
+/// key by key and any other key is taken from the last file setting it. A file listed as
+/// `{ path = "local.toml", optional = true }` is skipped if it doesn't exist.
+pub fn load_config(path: &Path) -> errors::Result<Config> {
+    let merged = read_layered(path, 0)?;
+    Config::deserialize(merged).configuration_error("failed to parse config")
+}
+
+/// An entry of `include` in its long form.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Include {
+    path: String,
+    #[serde(default)]
+    optional: bool,
+}
+
+/// Resolves the paths listed in the `include` key of a config file, leaving out optional
+/// files that don't exist.
+pub fn include_paths(path: &Path, config: &value::Value) -> errors::Result<Vec<PathBuf>> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let not_a_list = |value: &value::Value| {
+        ConfigurationError(
+            "include has to be a list of paths or of `{ path = ..., optional = true }` tables".to_owned(),
+            (value.to_string(), format!("{:?}", value)),
+        )
+    };
+    let includes = match config.get("include") {
+        None => return Ok(Vec::new()),
+        Some(&value::Value::Array(ref includes)) => includes,
+        Some(other) => return Err(not_a_list(other)),
+    };
+
+    let mut paths = Vec::new();
+    for include in includes {
+        let include = match *include {
+            value::Value::String(ref path) => Include {
+                path: path.clone(),
+                optional: false,
+            },
+            value::Value::Table(_) => include.clone().try_into::<Include>().map_err(|_| not_a_list(include))?,
+            _ => return Err(not_a_list(include)),
+        };
+        let path = dir.join(expand(&include.path));
+        if !include.optional || path.exists() {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+fn read_layered(path: &Path, depth: usize) -> errors::Result<value::Value> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(ConfigurationError(
+            "too many nested includes, is a file including itself?".to_owned(),
+            (path.to_string_lossy().into_owned(), String::new()),
+        ));
+    }
+
+    let mut layer: value::Value = deserialize_file(&path.to_string_lossy())?;
+    let mut merged = value::Table::new();
+    for include in include_paths(path, &layer)? {
+        merge_layer(&mut merged, read_layered(&include, depth + 1)?);
+    }
+    if let value::Value::Table(ref mut table) = layer {
+        table.remove("include");
+    }
+    merge_layer(&mut merged, layer);
+
+    Ok(value::Value::Table(merged))
+}
+
+fn merge_layer(base: &mut value::Table, layer: value::Value) {
+    let layer = match layer {
+        value::Value::Table(layer) => layer,
+        _ => return,
+    };
+
+    for (key, value) in layer {
+        match key.as_str() {
+            "block" => {
+                if let value::Value::Array(blocks) = value {
+                    match base.entry(key).or_insert_with(|| value::Value::Array(Vec::new())) {
+                        &mut value::Value::Array(ref mut base_blocks) => base_blocks.extend(blocks),
+                        other => *other = value::Value::Array(blocks),
+                    }
+                }
+            }
+            "theme" | "icons" => {
+                let value = name_table(value);
+                let merged = match base.remove(&key) {
+                    Some(base_value) => merge_tables(name_table(base_value), value),
+                    None => value,
+                };
+                base.insert(key, value::Value::Table(merged));
+            }
+            _ => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Brings the short form `theme = "name"` into the long one with `name` and `overrides`.
+fn name_table(value: value::Value) -> value::Table {
+    match value {
+        value::Value::Table(table) => table,
+        name => {
+            let mut table = value::Table::new();
+            table.insert("name".to_owned(), name);
+            table
+        }
+    }
+}
+
+fn merge_tables(mut base: value::Table, layer: value::Table) -> value::Table {
+    for (key, value) in layer {
+        let merged = match (base.remove(&key), value) {
+            (Some(value::Value::Table(base_table)), value::Value::Table(table)) => {
+                value::Value::Table(merge_tables(base_table, table))
+            }
+            (_, value) => value,
+        };
+        base.insert(key, merged);
+    }
+    base
+}
+
 fn deserialize_blocks<'de, D>(deserializer: D) -> Result<Vec<(String, value::Value)>, D::Error>
 where
     D: Deserializer<'de>,
@@ -70,3 +249,136 @@ where
 
     Deserialize::deserialize(de::value::MapDeserializer::new(intermediary.into_iter()))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{load_config, MAX_INCLUDE_DEPTH};
+    use crate::errors::*;
+    use crate::testing::scratch_dir;
+    use crate::themes;
+
+    /// Writes `files`, as pairs of name and content, to a scratch directory and returns it.
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = scratch_dir(name);
+        for &(file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+        }
+        dir
+    }
+
+    fn block_names(path: &Path) -> Vec<String> {
+        load_config(path).unwrap().blocks.into_iter().map(|(name, _)| name).collect()
+    }
+
+    fn error_message(path: &Path) -> String {
+        match load_config(path) {
+            Err(ConfigurationError(message, _)) => message,
+            Err(e) => format!("{}", e),
+            Ok(_) => panic!("{} should not load", path.display()),
+        }
+    }
+
+    #[test]
+    fn included_blocks_come_first_in_order() {
+        let dir = write_files(
+            "include_order",
+            &[
+                ("config.toml", "include = [\"a.toml\", \"b.toml\"]\n[[block]]\nblock = \"time\""),
+                ("a.toml", "[[block]]\nblock = \"cpu\"\n[[block]]\nblock = \"load\""),
+                ("b.toml", "[[block]]\nblock = \"uptime\""),
+            ],
+        );
+        assert_eq!(block_names(&dir.join("config.toml")), vec!["cpu", "load", "uptime", "time"]);
+    }
+
+    #[test]
+    fn later_files_override_keys() {
+        let dir = write_files(
+            "include_overrides",
+            &[
+                (
+                    "config.toml",
+                    "include = [\"a.toml\", \"b.toml\"]\nredraw_delay = 0.5\n[theme.overrides]\nidle_bg = \"#123456\"",
+                ),
+                ("a.toml", "fs_root = \"/a\"\nredraw_delay = 2\ntheme = \"solarized-dark\""),
+                ("b.toml", "fs_root = \"/b\""),
+            ],
+        );
+        let config = load_config(&dir.join("config.toml")).unwrap();
+        assert_eq!(config.fs_root, PathBuf::from("/b"));
+        assert_eq!(config.redraw_delay.as_millis(), 500);
+
+        // The theme of one file and the overrides of another are merged
+        let solarized = themes::get_theme("solarized-dark").unwrap();
+        assert_eq!(config.theme.idle_bg, "#123456");
+        assert_eq!(config.theme.critical_bg, solarized.critical_bg);
+    }
+
+    #[test]
+    fn includes_are_relative_to_the_including_file() {
+        let dir = write_files(
+            "include_relative",
+            &[
+                ("config.toml", "include = [\"sub/a.toml\"]"),
+                ("sub/a.toml", "include = [\"b.toml\"]\n[[block]]\nblock = \"cpu\""),
+                ("sub/b.toml", "[[block]]\nblock = \"load\""),
+                ("b.toml", "[[block]]\nblock = \"time\""),
+            ],
+        );
+        assert_eq!(block_names(&dir.join("config.toml")), vec!["load", "cpu"]);
+    }
+
+    #[test]
+    fn cycles_and_deep_nesting_are_errors() {
+        let dir = write_files(
+            "include_cycle",
+            &[
+                ("config.toml", "include = [\"a.toml\"]"),
+                ("a.toml", "include = [\"config.toml\"]"),
+            ],
+        );
+        assert!(error_message(&dir.join("config.toml")).contains("too many nested includes"));
+
+        // A chain of files, each including the next, is fine up to the limit
+        let chain = |length: usize| -> Vec<(String, String)> {
+            (0..length)
+                .map(|i| {
+                    let content = if i + 1 < length { format!("include = [\"{}.toml\"]", i + 1) } else { String::new() };
+                    (format!("{}.toml", i), content)
+                })
+                .collect()
+        };
+        for &(length, ok) in &[(MAX_INCLUDE_DEPTH + 1, true), (MAX_INCLUDE_DEPTH + 2, false)] {
+            let files = chain(length);
+            let files: Vec<(&str, &str)> = files.iter().map(|&(ref name, ref content)| (name.as_str(), content.as_str())).collect();
+            let dir = write_files("include_chain", &files);
+            assert_eq!(load_config(&dir.join("0.toml")).is_ok(), ok, "chain of {} files", length);
+        }
+    }
+
+    #[test]
+    fn optional_includes_may_be_missing() {
+        let dir = write_files(
+            "include_optional",
+            &[
+                (
+                    "config.toml",
+                    "include = [{ path = \"missing.toml\", optional = true }, { path = \"a.toml\", optional = true }]",
+                ),
+                ("a.toml", "[[block]]\nblock = \"cpu\""),
+                ("required.toml", "include = [\"missing.toml\"]"),
+                ("long.toml", "include = [{ path = \"missing.toml\" }]"),
+                ("bad.toml", "include = [{ file = \"a.toml\" }]"),
+            ],
+        );
+        assert_eq!(block_names(&dir.join("config.toml")), vec!["cpu"]);
+        assert!(load_config(&dir.join("required.toml")).is_err());
+        assert!(load_config(&dir.join("long.toml")).is_err());
+        assert!(error_message(&dir.join("bad.toml")).contains("include has to be a list"));
+    }
+}
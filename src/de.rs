@@ -8,6 +8,7 @@ use std::str::FromStr;
 use std::time::Duration;
 use toml::{self, value};
 use chrono_tz::Tz;
+use regex::{Captures, Regex};
 
 pub fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
 where
@@ -191,3 +192,152 @@ where
     let s = String::deserialize(deserializer)?;
     Tz::from_str(&s).map(Some).map_err(de::Error::custom)
 }
+
+lazy_static! {
+    static ref VARIABLE: Regex =
+        Regex::new(r"\$(?:\$|\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}|([A-Za-z_][A-Za-z0-9_]*))").unwrap();
+}
+
+/// Expands a leading `~` to the home directory and replaces `$VAR`, `${VAR}` and
+/// `${VAR:-default}` with the value of the environment variable. Unset variables expand to
+/// nothing (or the default) and `$$` is a literal `$`. `HOSTNAME` falls back to the actual
+/// hostname, as most shells don't export it.
+pub fn expand(s: &str) -> String {
+    let s = if s == "~" || s.starts_with("~/") {
+        format!("{}{}", ::std::env::var("HOME").unwrap_or_default(), &s[1..])
+    } else {
+        s.to_owned()
+    };
+
+    VARIABLE
+        .replace_all(&s, |captures: &Captures| {
+            let name = match captures.get(1).or_else(|| captures.get(3)) {
+                Some(name) => name.as_str(),
+                None => return "$".to_owned(),
+            };
+            match ::std::env::var(name) {
+                Ok(ref value) if !value.is_empty() => value.clone(),
+                _ => match captures.get(2) {
+                    Some(default) => default.as_str().to_owned(),
+                    None if name == "HOSTNAME" => {
+                        let mut buffer = [0u8; 256];
+                        ::nix::unistd::gethostname(&mut buffer)
+                            .map(|hostname| hostname.to_string_lossy().into_owned())
+                            .unwrap_or_default()
+                    }
+                    None => String::new(),
+                },
+            }
+        })
+        .into_owned()
+}
+
+/// Like `expand`, but only for the first word of a shell command, i.e. the program. The
+/// rest is left to the shell, which has its own idea of `$1` or `$NF`. So is a program
+/// with quotes or backslashes in it, as those mean something to the shell too.
+pub fn expand_command(command: &str) -> String {
+    let trimmed = command.trim_start();
+    let end = trimmed.find(char::is_whitespace).unwrap_or_else(|| trimmed.len());
+    let program = &trimmed[..end];
+    if program.contains(|c| c == '\'' || c == '"' || c == '\\') {
+        return trimmed.to_owned();
+    }
+    format!("{}{}", expand(program), &trimmed[end..])
+}
+
+/// Config values that contain strings which can be expanded, see `deserialize_expanded`.
+pub trait Expand {
+    fn expand_with(self, f: &Fn(&str) -> String) -> Self;
+}
+
+impl Expand for String {
+    fn expand_with(self, f: &Fn(&str) -> String) -> Self {
+        f(&self)
+    }
+}
+
+impl<T: Expand> Expand for Option<T> {
+    fn expand_with(self, f: &Fn(&str) -> String) -> Self {
+        self.map(|value| value.expand_with(f))
+    }
+}
+
+impl<T: Expand> Expand for Vec<T> {
+    fn expand_with(self, f: &Fn(&str) -> String) -> Self {
+        self.into_iter().map(|value| value.expand_with(f)).collect()
+    }
+}
+
+/// Deserializes a string (or an optional string or a list of them) and `expand`s it. Meant
+/// for paths, use it with `#[serde(deserialize_with = "deserialize_expanded")]`.
+pub fn deserialize_expanded<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Expand,
+{
+    T::deserialize(deserializer).map(|value| value.expand_with(&expand))
+}
+
+/// Like `deserialize_expanded`, but for shell commands, see `expand_command`.
+pub fn deserialize_expanded_command<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Expand,
+{
+    T::deserialize(deserializer).map(|value| value.expand_with(&expand_command))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{expand, expand_command};
+
+    fn home() -> String {
+        env::var("HOME").unwrap_or_default()
+    }
+
+    #[test]
+    fn home_directory_is_expanded() {
+        assert_eq!(expand("~"), home());
+        assert_eq!(expand("~/mail"), format!("{}/mail", home()));
+        assert_eq!(expand("~user/mail"), "~user/mail");
+        assert_eq!(expand("/tmp/~"), "/tmp/~");
+    }
+
+    #[test]
+    fn variables_are_expanded() {
+        env::set_var("CRATE_TEST_EXPAND_DIR", "/srv/data");
+        assert_eq!(expand("$CRATE_TEST_EXPAND_DIR/x"), "/srv/data/x");
+        assert_eq!(expand("${CRATE_TEST_EXPAND_DIR}x"), "/srv/datax");
+        assert_eq!(expand("${CRATE_TEST_EXPAND_DIR:-/tmp}"), "/srv/data");
+    }
+
+    #[test]
+    fn unset_variables_expand_to_nothing_or_the_default() {
+        env::remove_var("CRATE_TEST_EXPAND_UNSET");
+        assert_eq!(expand("/a/$CRATE_TEST_EXPAND_UNSET/b"), "/a//b");
+        assert_eq!(expand("${CRATE_TEST_EXPAND_UNSET:-/tmp}/b"), "/tmp/b");
+        assert_eq!(expand("${CRATE_TEST_EXPAND_UNSET:-}b"), "b");
+    }
+
+    #[test]
+    fn lone_dollars_are_kept() {
+        assert_eq!(expand("price$"), "price$");
+        assert_eq!(expand("$"), "$");
+        assert_eq!(expand("a$ b"), "a$ b");
+        assert_eq!(expand("$$HOME"), "$HOME");
+        assert_eq!(expand("${1}"), "${1}");
+    }
+
+    #[test]
+    fn commands_only_expand_an_unquoted_program() {
+        env::set_var("CRATE_TEST_EXPAND_BIN", "/opt/bin");
+        assert_eq!(expand_command("  $CRATE_TEST_EXPAND_BIN/x $1 '$HOME'"), "/opt/bin/x $1 '$HOME'");
+        assert_eq!(expand_command("~/bin/x \"$USER\""), format!("{}/bin/x \"$USER\"", home()));
+        assert_eq!(expand_command("'$CRATE_TEST_EXPAND_BIN/x' y"), "'$CRATE_TEST_EXPAND_BIN/x' y");
+        assert_eq!(expand_command("\"$CRATE_TEST_EXPAND_BIN\"/x"), "\"$CRATE_TEST_EXPAND_BIN\"/x");
+        assert_eq!(expand_command("\\$CRATE_TEST_EXPAND_BIN/x"), "\\$CRATE_TEST_EXPAND_BIN/x");
+        assert_eq!(expand_command("awk '{ print $NF }'"), "awk '{ print $NF }'");
+    }
+}
@@ -133,8 +133,26 @@ lazy_static! {
         "volume_empty" => " \u{e04e} ",
         // This icon has no spaces around it because it is manually set as text. (sound.rs)
         "volume_muted" => "\u{e04f}",
-        "thermometer" => " \u{f2c8} ", // TODO
+        "thermometer" => " \u{e1ff} ",
         "xrandr" => " \u{e31e} ",
+        "net_up" => " \u{e5d8} ",
+        "net_down" => " \u{e5db} ",
+        "net_wireless" => " \u{e63e} ",
+        "net_wired" => " \u{e8be} ",
+        "net_vpn" => " \u{e62f} ",
+        "ping" => " \u{e425} ",
+        "backlight_empty" => " \u{e1ad} ",
+        "backlight_partial1" => " \u{e3a9} ",
+        "backlight_partial2" => " \u{e1ae} ",
+        "backlight_partial3" => " \u{e3ab} ",
+        "backlight_full" => " \u{e1ac} ",
+        "weather_sun" => " \u{e430} ",
+        "weather_snow" => " \u{eb3b} ",
+        "weather_thunder" => " \u{e3e7} ",
+        "weather_clouds" => " \u{e2bd} ",
+        "weather_rain" => " \u{e798} ",
+        // Cloud symbol as default
+        "weather_default" => " \u{e2bd} ",
         // Same as time symbol.
         "uptime" => " \u{e192} ",
         "gpu" => " \u{e333} ",
@@ -145,11 +163,127 @@ lazy_static! {
         "keyboard" => " \u{e312}",
         "mouse" => " \u{e323}"
     };
+
+    /// Nerd Fonts (v3+). Font Awesome glyphs where they are good enough, Material Design
+    /// (`nf-md-*`) and Weather Icons (`nf-weather-*`) glyphs otherwise.
+    pub static ref NERD: Map<String, String> = map_to_owned! {
+        "" => "",
+        "time" => " \u{f017} ",
+        "music" => " \u{f001} ",
+        "music_play" => "  \u{f04b}  ",
+        "music_pause" => "  \u{f04c}  ",
+        "music_next" => " \u{f051} ",
+        "music_prev" => " \u{f048} ",
+        "cogs" => " \u{f085} ",
+        "memory_mem" => " \u{f035b} ",
+        "memory_swap" => " \u{f04e1} ",
+        "cpu" => " \u{f4bc} ",
+        "bat" => " \u{f007e} ",
+        "bat_full" => " \u{f0079} ",
+        "bat_charging" => " \u{f0084} ",
+        "bat_discharging" => " \u{f007c} ",
+        "update" => " \u{f06b0} ",
+        "toggle_off" => " \u{f204} ",
+        "toggle_on" => " \u{f205} ",
+        "volume_full" => " \u{f028} ",
+        "volume_half" => " \u{f027} ",
+        "volume_empty" => " \u{f026} ",
+        // This icon has no spaces around it because it is manually set as text. (sound.rs)
+        "volume_muted" => "\u{f0581}",
+        "thermometer" => " \u{f050f} ",
+        "xrandr" => " \u{f0379} ",
+        "net_up" => " \u{f005d} ",
+        "net_down" => " \u{f0045} ",
+        "net_wireless" => " \u{f05a9} ",
+        "net_wired" => " \u{f0200} ",
+        "net_vpn" => " \u{f0582} ",
+        "ping" => " \u{f051b} ",
+        "backlight_empty" => " \u{f00de} ",
+        "backlight_partial1" => " \u{f00de} ",
+        "backlight_partial2" => " \u{f00df} ",
+        "backlight_partial3" => " \u{f00df} ",
+        "backlight_full" => " \u{f00e0} ",
+        "weather_sun" => " \u{e30d} ",
+        "weather_snow" => " \u{e31a} ",
+        "weather_thunder" => " \u{e31d} ",
+        "weather_clouds" => " \u{e312} ",
+        "weather_rain" => " \u{e318} ",
+        // Cloud symbol as default
+        "weather_default" => " \u{e312} ",
+        // Same as time symbol.
+        "uptime" => " \u{f017} ",
+        "gpu" => " \u{f08ae} ",
+        "mail" => " \u{f01ee} ",
+        "bluetooth" => " \u{f00af}",
+        "headphones" => " \u{f02cb}",
+        "joystick" => " \u{f0297}",
+        "keyboard" => " \u{f030c}",
+        "mouse" => " \u{f037d}"
+    };
+
+    /// Material Design Icons (the community `materialdesignicons-webfont`, not Google's
+    /// Material Icons used by `MATERIAL`).
+    pub static ref MDI: Map<String, String> = map_to_owned! {
+        "" => "",
+        "time" => " \u{f0150} ",
+        "music" => " \u{f075a} ",
+        "music_play" => "  \u{f040a}  ",
+        "music_pause" => "  \u{f03e4}  ",
+        "music_next" => " \u{f04ad} ",
+        "music_prev" => " \u{f04ae} ",
+        "cogs" => " \u{f08d6} ",
+        "memory_mem" => " \u{f035b} ",
+        "memory_swap" => " \u{f04e1} ",
+        "cpu" => " \u{f061a} ",
+        "bat" => " \u{f007e} ",
+        "bat_full" => " \u{f0079} ",
+        "bat_charging" => " \u{f0084} ",
+        "bat_discharging" => " \u{f007c} ",
+        "update" => " \u{f06b0} ",
+        "toggle_off" => " \u{f0522} ",
+        "toggle_on" => " \u{f0521} ",
+        "volume_full" => " \u{f057e} ",
+        "volume_half" => " \u{f0580} ",
+        "volume_empty" => " \u{f057f} ",
+        // This icon has no spaces around it because it is manually set as text. (sound.rs)
+        "volume_muted" => "\u{f0581}",
+        "thermometer" => " \u{f050f} ",
+        "xrandr" => " \u{f0379} ",
+        "net_up" => " \u{f005d} ",
+        "net_down" => " \u{f0045} ",
+        "net_wireless" => " \u{f05a9} ",
+        "net_wired" => " \u{f0200} ",
+        "net_vpn" => " \u{f0582} ",
+        "ping" => " \u{f051b} ",
+        "backlight_empty" => " \u{f00de} ",
+        "backlight_partial1" => " \u{f00de} ",
+        "backlight_partial2" => " \u{f00df} ",
+        "backlight_partial3" => " \u{f00df} ",
+        "backlight_full" => " \u{f00e0} ",
+        "weather_sun" => " \u{f0599} ",
+        "weather_snow" => " \u{f0598} ",
+        "weather_thunder" => " \u{f0593} ",
+        "weather_clouds" => " \u{f0590} ",
+        "weather_rain" => " \u{f0597} ",
+        // Partly cloudy symbol as default
+        "weather_default" => " \u{f0595} ",
+        // Same as time symbol.
+        "uptime" => " \u{f0150} ",
+        "gpu" => " \u{f08ae} ",
+        "mail" => " \u{f01ee} ",
+        "bluetooth" => " \u{f00af}",
+        "headphones" => " \u{f02cb}",
+        "joystick" => " \u{f0297}",
+        "keyboard" => " \u{f030c}",
+        "mouse" => " \u{f037d}"
+    };
 }
 
 pub fn get_icons(name: &str) -> Option<Map<String, String>> {
     match name {
         "material" => Some(MATERIAL.clone()),
+        "mdi" => Some(MDI.clone()),
+        "nerd" => Some(NERD.clone()),
         "awesome" => Some(AWESOME.clone()),
         "none" => Some(NONE.clone()),
         _ => None,
@@ -159,3 +293,59 @@ pub fn get_icons(name: &str) -> Option<Map<String, String>> {
 pub fn default() -> Map<String, String> {
     NONE.clone()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use regex::Regex;
+
+    use super::get_icons;
+
+    /// Icon names the blocks pass to `set_icon` and `with_icon`, including those picked by a
+    /// `match` or `if` inside the call, with the file they are used in.
+    fn icons_used_by_blocks() -> Vec<(String, String)> {
+        let call = Regex::new(r"(?:set|with)_icon\(").unwrap();
+        let name = Regex::new(r#""([a-z][a-z0-9_]*)""#).unwrap();
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src/blocks");
+
+        let mut used = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+            for start in call.find_iter(&source) {
+                // The argument up to the parenthesis that closes the call, without nested calls
+                // like `field("icon")`
+                let mut argument = String::new();
+                let mut depth = 1;
+                for c in source[start.end()..].chars() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ if depth == 1 => argument.push(c),
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                for icon in name.captures_iter(&argument) {
+                    used.push((path.display().to_string(), icon[1].to_owned()));
+                }
+            }
+        }
+        used
+    }
+
+    #[test]
+    fn every_icon_set_has_the_icons_of_all_blocks() {
+        let used = icons_used_by_blocks();
+        assert!(used.iter().any(|&(_, ref icon)| icon == "weather_sun"));
+
+        for set in &["none", "awesome", "material", "nerd", "mdi"] {
+            let icons = get_icons(set).unwrap();
+            let missing: Vec<_> = used.iter().filter(|&&(_, ref icon)| !icons.contains_key(icon)).collect();
+            assert!(missing.is_empty(), "icons missing from the {} set: {:?}", set, missing);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{de, Deserializer};
 use serde_json;
 use std::fmt;
-use std::io;
+use std::io::BufRead;
 use std::option::Option;
 use std::string::*;
 use chan::Sender;
@@ -39,18 +39,40 @@ impl I3BarEvent {
     }
 }
 
-pub fn process_events(sender: Sender<I3BarEvent>) {
+/// Reads click events from `input` (stdin, unless driven by something else than i3bar)
+/// until it is closed.
+pub fn process_events<R: BufRead + Send + 'static>(mut input: R, sender: Sender<I3BarEvent>) {
     thread::spawn(move || loop {
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+        let mut line = String::new();
+        let read = match input.read_line(&mut line) {
+            Ok(read) => read,
+            Err(e) => {
+                warn!("failed to read click events: {}", e);
+                0
+            }
+        };
+        if read == 0 {
+            debug!("no more click events");
+            // No more clicks. The sender has to stay alive though, as the main loop
+            // would otherwise wake up for the closed channel over and over.
+            loop {
+                thread::park();
+            }
+        }
+        let input = line;
 
         // Take only the valid JSON object betweem curly braces (cut off leading bracket, commas and whitespace)
         let slice = input.trim_start_matches(|c| c != '{');
         let slice = slice.trim_end_matches(|c| c != '}');
 
         if !slice.is_empty() {
-            let e: I3BarEvent = serde_json::from_str(slice).unwrap();
-            sender.send(e);
+            match serde_json::from_str::<I3BarEvent>(slice) {
+                Ok(e) => {
+                    debug!("click event {:?}", e);
+                    sender.send(e);
+                }
+                Err(e) => warn!("ignoring malformed click event {:?}: {}", slice, e),
+            }
         }
     });
 }
@@ -72,7 +94,6 @@ where
         where
             E: de::Error,
         {
-            eprintln!("{}", value);
             Ok(match value {
                 1 => MouseButton::Left,
                 2 => MouseButton::Middle,
@@ -0,0 +1,85 @@
+//! Diagnostics for when something goes wrong outside of what a block can display.
+//!
+//! Messages go through the `log` macros and end up on stderr, or in the file given with
+//! `--log-file`, as i3bar usually discards stderr. Each line carries a timestamp, the level
+//! and the target, which is the module (e.g. `blocks::music`) or `block::<instance>` for
+//! messages about a specific block. Warnings and errors are always logged, `-v` adds
+//! informational messages, `-vv` debug and `-vvv` trace messages.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Local;
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+
+use crate::errors::*;
+
+struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let target = match record.target() {
+            "i3status_rs" => "main",
+            target => target.trim_start_matches("i3status_rs::"),
+        };
+        let line = format!(
+            "{} {:<5} [{}] {}\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.level(),
+            target,
+            record.args()
+        );
+        // There is nowhere left to report a failed write to
+        match self.file {
+            Some(ref file) => {
+                let _ = file.lock().unwrap().write_all(line.as_bytes());
+            }
+            None => {
+                let _ = io::stderr().write_all(line.as_bytes());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref file) = self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Sets up logging with the number of `-v` flags given and an optional file to append to.
+pub fn init(verbosity: u64, log_file: Option<&Path>) -> Result<()> {
+    let level = match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let file = match log_file {
+        Some(path) => Some(Mutex::new(OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .configuration_error(&format!("failed to open log file {}", path.display()))?)),
+        None => None,
+    };
+
+    log::set_boxed_logger(Box::new(Logger { level, file }))
+        .internal_error("logging", "logger was already set up")?;
+    log::set_max_level(level);
+    info!("starting i3status-rs, log level {}", level.to_level().unwrap_or(Level::Warn));
+    Ok(())
+}
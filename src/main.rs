@@ -7,6 +7,8 @@ extern crate serde;
 extern crate serde_json;
 #[macro_use]
 extern crate chan;
+#[macro_use]
+extern crate log;
 extern crate toml;
 extern crate clap;
 extern crate uuid;
@@ -24,14 +26,26 @@ mod de;
 #[macro_use]
 mod util;
 mod block;
+mod check;
 pub mod blocks;
 mod config;
 mod errors;
 mod input;
+mod logging;
+mod notification;
+mod persist;
+mod rate;
+mod registry;
 mod icons;
 mod themes;
 mod scheduler;
+mod stats;
 mod subprocess;
+#[cfg(test)]
+mod testing;
+mod threshold;
+mod unit;
+mod visibility;
 mod widget;
 mod widgets;
 
@@ -43,23 +57,26 @@ use cpuprofiler::PROFILER;
 extern crate progress;
 
 use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
 use std::time::Duration;
 use std::ops::DerefMut;
 
 use crate::block::Block;
 
-use crate::blocks::create_block;
-use crate::config::Config;
+use crate::blocks::{create_block, is_shown_on_bar};
+use crate::config::{load_config, Config};
 use crate::errors::*;
 use crate::input::{process_events, I3BarEvent};
 use crate::scheduler::{Task, UpdateScheduler};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::text::TextWidget;
 
-use crate::util::deserialize_file;
 
 use self::clap::{App, Arg, ArgMatches};
 use self::chan::{Receiver, Sender};
+use nix::sys::signal::{SigSet, Signal};
 
 fn main() {
     let mut builder = App::new("i3status-rs")
@@ -72,10 +89,72 @@ fn main() {
         .arg(
             Arg::with_name("config")
                 .value_name("CONFIG_FILE")
-                .help("sets a toml config file")
-                .required(true)
+                .help("sets a toml config file, $XDG_CONFIG_HOME/i3status-rust/config.toml by default")
                 .index(1),
         )
+        .arg(
+            Arg::with_name("check")
+                .help("check the config file for errors and exit, without running the bar")
+                .long("check")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("once")
+                .help("update every block once, print a single status line and exit")
+                .long("once")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("plain")
+                .help("with --once, print the status line as plain text instead of i3bar JSON")
+                .long("plain")
+                .requires("once")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("json-snapshot")
+                .help("update every block once, print its text, state and placeholder values as JSON and exit")
+                .long("json-snapshot")
+                .conflicts_with("once")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("output")
+                .value_name("OUTPUT")
+                .help("name of an output the bar is shown on, for blocks limited to some `outputs`; can be repeated")
+                .long("output")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bar-id")
+                .value_name("BAR_ID")
+                .help("id of the bar in the i3 or sway config, for blocks limited to some `outputs`")
+                .long("bar-id")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .help("print how long the updates of each block took to stderr on exit")
+                .long("stats")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .help("log more details, repeat for even more (-vv, -vvv)")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .value_name("LOG_FILE")
+                .help("append log messages to this file instead of printing them to stderr")
+                .long("log-file")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("exit-on-error")
                 .help(
@@ -103,11 +182,27 @@ fn main() {
     });
 
     let matches = builder.get_matches();
-    let exit_on_error = matches.is_present("exit-on-error");
+    let one_shot = matches.is_present("once") || matches.is_present("json-snapshot");
+    let exit_on_error = matches.is_present("exit-on-error") || one_shot;
+
+    if matches.is_present("check") {
+        ::std::process::exit(check::check(&config_path(&matches)));
+    }
+
+    if let Err(error) = logging::init(matches.occurrences_of("verbose"), matches.value_of("log-file").map(Path::new)) {
+        eprintln!("{:?}", error);
+        ::std::process::exit(1);
+    }
 
     // Run and match for potential error
-    if let Err(error) = run(&matches) {
+    let result = if one_shot {
+        run_once(&matches)
+    } else {
+        run(&matches)
+    };
+    if let Err(error) = result {
         if exit_on_error {
+            // Whoever runs it that way is watching the terminal, not the log
             eprintln!("{:?}", error);
             ::std::process::exit(1);
         }
@@ -121,7 +216,7 @@ fn main() {
             serde_json::to_string(&[error_rendered]).expect("failed to serialize error message")
         );
 
-        eprintln!("\n\n{:?}", error);
+        error!("{:?}", error);
         // Do nothing, so the error message keeps displayed
         loop {
             ::std::thread::sleep(Duration::from_secs(::std::u64::MAX));
@@ -129,73 +224,168 @@ fn main() {
     }
 }
 
-#[allow(unused_mut)] // TODO: Remove when fixed in chan_select
 fn run(matches: &ArgMatches) -> Result<()> {
-    // Now we can start to run the i3bar protocol
-    print!("{{\"version\": 1, \"click_events\": true}}\n[");
-
-    // Read & parse the config file
-    let config: Config = deserialize_file(matches.value_of("config").unwrap())?;
-
-    // Update request channel
-    let (tx_update_requests, rx_update_requests): (Sender<Task>, Receiver<Task>) = chan::r#async();
+    // Has to happen before any other thread is spawned, so that they inherit the signal mask
+    handle_signals(matches.is_present("stats"))?;
 
     // In dev build, we might diverge into profiling blocks here
     if let Some(name) = matches.value_of("profile") {
+        let config = load_bar_config(matches)?;
+        let (tx_update_requests, _): (Sender<Task>, Receiver<Task>) = chan::r#async();
         profile_config(name, matches.value_of("profile-runs").unwrap(), &config, &tx_update_requests)?;
         return Ok(());
     }
 
-    let mut config_alternating_tint = config.clone();
-    {
-        let tint_bg = &config.theme.alternating_tint_bg;
-        config_alternating_tint.theme.idle_bg = util::add_colors(&config_alternating_tint.theme.idle_bg, tint_bg)
-            .configuration_error("can't parse alternative_tint color code")?;
-        config_alternating_tint.theme.info_bg = util::add_colors(&config_alternating_tint.theme.info_bg, tint_bg)
-            .configuration_error("can't parse alternative_tint color code")?;
-        config_alternating_tint.theme.good_bg = util::add_colors(&config_alternating_tint.theme.good_bg, tint_bg)
-            .configuration_error("can't parse alternative_tint color code")?;
-        config_alternating_tint.theme.warning_bg = util::add_colors(&config_alternating_tint.theme.warning_bg, tint_bg)
-            .configuration_error("can't parse alternative_tint color code")?;
-        config_alternating_tint.theme.critical_bg = util::add_colors(&config_alternating_tint.theme.critical_bg, tint_bg)
-            .configuration_error("can't parse alternative_tint color code")?;
+    serve(|| load_bar_config(matches), BufReader::new(io::stdin()), &mut io::stdout())
+}
 
-        let tint_fg = &config.theme.alternating_tint_fg;
-        config_alternating_tint.theme.idle_fg = util::add_colors(&config_alternating_tint.theme.idle_fg, tint_fg)
-            .configuration_error("can't parse alternative_tint color code")?;
-        config_alternating_tint.theme.info_fg = util::add_colors(&config_alternating_tint.theme.info_fg, tint_fg)
-            .configuration_error("can't parse alternative_tint color code")?;
-        config_alternating_tint.theme.good_fg = util::add_colors(&config_alternating_tint.theme.good_fg, tint_fg)
-            .configuration_error("can't parse alternative_tint color code")?;
-        config_alternating_tint.theme.warning_fg = util::add_colors(&config_alternating_tint.theme.warning_fg, tint_fg)
-            .configuration_error("can't parse alternative_tint color code")?;
-        config_alternating_tint.theme.critical_fg = util::add_colors(&config_alternating_tint.theme.critical_fg, tint_fg)
-            .configuration_error("can't parse alternative_tint color code")?;
+/// Speaks the i3bar protocol on `input` and `out`: writes the header, loads the config
+/// with `load` and runs the bar. The header comes first, so that an error in the config
+/// can be shown on the bar.
+fn serve<F, R>(load: F, input: R, out: &mut Write) -> Result<()>
+where
+    F: FnOnce() -> Result<Config>,
+    R: BufRead + Send + 'static,
+{
+    write_header(out)?;
+    let config = load()?;
+    run_bar(&config, input, out)
+}
+
+/// What `run_once` prints.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Snapshot {
+    /// A status line as i3bar gets it
+    StatusLine,
+    /// `--plain`: the text of the blocks
+    Plain,
+    /// `--json-snapshot`: text, state and placeholders of every block
+    Json,
+}
+
+/// `--once` and `--json-snapshot`: updates every block once, prints the result to stdout
+/// and returns, without speaking the i3bar protocol.
+fn run_once(matches: &ArgMatches) -> Result<()> {
+    let config = load_bar_config(matches)?;
+    let snapshot = if matches.is_present("json-snapshot") {
+        Snapshot::Json
+    } else if matches.is_present("plain") {
+        Snapshot::Plain
+    } else {
+        Snapshot::StatusLine
+    };
+    write_snapshot(&config, snapshot, &mut io::stdout())?;
+
+    if matches.is_present("stats") {
+        stats::report(&mut io::stderr())?;
     }
 
-    let mut blocks: Vec<Box<Block>> = Vec::new();
+    persist::flush();
+    Ok(())
+}
 
-    let mut alternator = false;
-    // Initialize the blocks
-    for &(ref block_name, ref block_config) in &config.blocks {
-        blocks.push(create_block(
-            block_name,
-            block_config.clone(),
-            if alternator {
-                config_alternating_tint.clone()
-            } else {
-                config.clone()
-            },
-            tx_update_requests.clone(),
-        )?);
-        alternator = !alternator;
+/// Updates every block once and writes `snapshot` of the result to `out`.
+fn write_snapshot(config: &Config, snapshot: Snapshot, out: &mut Write) -> Result<()> {
+    // Blocks may request further updates, there is nobody to serve them.
+    let (tx_update_requests, _rx_update_requests): (Sender<Task>, Receiver<Task>) = chan::r#async();
+    let (names, mut blocks): (Vec<String>, Vec<Box<Block>>) =
+        create_blocks(config, &tx_update_requests)?.into_iter().unzip();
+
+    // All blocks are scheduled for right now, so one round updates each of them
+    let mut scheduler = UpdateScheduler::new(&blocks, Box::new(config.clock.clone()));
+    let order = blocks.iter().map(|x| String::from(x.id())).collect::<Vec<_>>();
+    let mut block_map: HashMap<String, &mut Block> = HashMap::new();
+    for block in &mut blocks {
+        block_map.insert(String::from(block.id()), (*block).deref_mut());
+    }
+    if !block_map.is_empty() {
+        scheduler.do_scheduled_updates(&mut block_map)?;
     }
 
+    match snapshot {
+        Snapshot::Json => {
+            let mut snapshot = serde_json::Map::new();
+            for (name, id) in names.iter().zip(&order) {
+                let block = &block_map[id];
+                let placeholders: serde_json::Map<String, serde_json::Value> = registry::values(name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(key, value)| (key, json!(value)))
+                    .collect();
+                snapshot.insert(
+                    name.clone(),
+                    json!({
+                        "text": util::plain_text(&**block),
+                        "state": block.state(),
+                        "placeholders": placeholders,
+                    }),
+                );
+            }
+            let snapshot = serde_json::to_string_pretty(&snapshot).internal_error("main", "failed to serialize snapshot")?;
+            writeln!(out, "{}", snapshot).internal_error("main", "failed to write snapshot")?;
+        }
+        Snapshot::Plain => {
+            let line = order
+                .iter()
+                .map(|id| util::plain_text(&*block_map[id]))
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join(" | ");
+            writeln!(out, "{}", line).internal_error("main", "failed to write status line")?;
+        }
+        Snapshot::StatusLine => {
+            let line = util::render_blocks(&order, &block_map, config)?;
+            writeln!(out, "{}", line).internal_error("main", "failed to write status line")?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the config file for the bar given by `--output` and `--bar-id`.
+fn load_bar_config(matches: &ArgMatches) -> Result<Config> {
+    let mut config = load_config(&config_path(matches))?;
+    config.bar_outputs = matches
+        .values_of("output")
+        .into_iter()
+        .flat_map(|outputs| outputs)
+        .chain(matches.value_of("bar-id"))
+        .map(String::from)
+        .collect();
+    Ok(config)
+}
+
+fn config_path(matches: &ArgMatches) -> PathBuf {
+    matches
+        .value_of("config")
+        .map(PathBuf::from)
+        .unwrap_or_else(util::default_config_path)
+}
+
+/// Writes the i3bar protocol header and opens the infinite array of status lines.
+fn write_header(out: &mut Write) -> Result<()> {
+    write!(out, "{{\"version\": 1, \"click_events\": true}}\n[")
+        .internal_error("main", "failed to write protocol header")?;
+    out.flush().internal_error("main", "failed to write protocol header")
+}
+
+/// Runs the bar: reads click events from `input`, writes status lines to `out` and
+/// schedules updates by the clock of `config`. The protocol header has to be written
+/// beforehand, see `serve`.
+#[allow(unused_mut)] // TODO: Remove when fixed in chan_select
+fn run_bar<R: BufRead + Send + 'static>(config: &Config, input: R, out: &mut Write) -> Result<()> {
+    // Update request channel
+    let (tx_update_requests, rx_update_requests): (Sender<Task>, Receiver<Task>) = chan::r#async();
+
+    let mut blocks: Vec<Box<Block>> = create_blocks(config, &tx_update_requests)?
+        .into_iter()
+        .map(|(_, block)| block)
+        .collect();
+
     // We save the order of the blocks here,
     // because they will be passed to an unordered HashMap
     let order = blocks.iter().map(|x| String::from(x.id())).collect::<Vec<_>>();
 
-    let mut scheduler = UpdateScheduler::new(&blocks);
+    let mut scheduler = UpdateScheduler::new(&blocks, Box::new(config.clock.clone()));
 
     let mut block_map: HashMap<String, &mut Block> = HashMap::new();
 
@@ -205,13 +395,22 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
     // We wait for click events in a separate thread, to avoid blocking to wait for stdin
     let (tx_clicks, rx_clicks): (Sender<I3BarEvent>, Receiver<I3BarEvent>) = chan::r#async();
-    process_events(tx_clicks);
+    process_events(input, tx_clicks);
 
     // Time to next update channel.
     // Fires immediately for first updates
-    let mut ttnu = chan::after_ms(0);
+    let mut ttnu = scheduler.clock().after(Duration::from_millis(0));
+
+    // Redraws are delayed by `redraw_delay`, so that a burst of updates ends up in a single
+    // status line. `idle` never fires and stands in while no redraw is pending.
+    let mut printer = util::StatusPrinter::default();
+    let (_tx_idle, idle): (Sender<()>, Receiver<()>) = chan::sync(0);
+    let mut redraw = idle.clone();
+    let mut redraw_pending = false;
 
     loop {
+        let mut dirty = false;
+
         // We use the message passing concept of channel selection
         // to avoid busy wait
 
@@ -221,7 +420,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
                     for block in block_map.values_mut() {
                         block.click(&event)?;
                     }
-                    util::print_blocks(&order, &block_map, &config)?;
+                    dirty = true;
             },
             // Receive async update requests
             rx_update_requests.recv() -> res => if let Some(request) = res {
@@ -230,25 +429,149 @@ fn run(matches: &ArgMatches) -> Result<()> {
                     .get_mut(&request.id)
                     .internal_error("scheduler", "could not get required block")?
                     .update()?;
-                util::print_blocks(&order, &block_map, &config)?;
+                dirty = true;
             },
             // Receive update timer events
             ttnu.recv() => {
                 scheduler.do_scheduled_updates(&mut block_map)?;
 
                 // redraw the blocks, state changed
-                util::print_blocks(&order, &block_map, &config)?;
+                dirty = true;
+            },
+            // Receive the end of the redraw delay
+            redraw.recv() => {
+                printer.print(out, &order, &block_map, config)?;
+                redraw_pending = false;
+            }
+        }
+
+        if !redraw_pending {
+            redraw = idle.clone();
+        }
+        if dirty {
+            if config.redraw_delay == Duration::from_secs(0) {
+                printer.print(out, &order, &block_map, config)?;
+            } else if !redraw_pending {
+                redraw = scheduler.clock().after(config.redraw_delay);
+                redraw_pending = true;
             }
         }
 
         // Set the time-to-next-update timer
         match scheduler.time_to_next_update() {
-            Some(time) => ttnu = chan::after(time),
-            None => ttnu = chan::after(Duration::from_secs(std::u64::MAX)),
+            Some(time) => ttnu = scheduler.clock().after(time),
+            None => ttnu = scheduler.clock().after(Duration::from_secs(std::u64::MAX)),
         }
     }
 }
 
+/// Creates the blocks of the config, each with its instance name, in the order they are
+/// displayed. Every other block gets the alternating tint.
+fn create_blocks(config: &Config, tx_update_requests: &Sender<Task>) -> Result<Vec<(String, Box<Block>)>> {
+    let mut config_alternating_tint = config.clone();
+    {
+        let tint_bg = &config.theme.alternating_tint_bg;
+        config_alternating_tint.theme.idle_bg = util::add_colors(&config_alternating_tint.theme.idle_bg, tint_bg)
+            .configuration_error("can't parse alternative_tint color code")?;
+        config_alternating_tint.theme.info_bg = util::add_colors(&config_alternating_tint.theme.info_bg, tint_bg)
+            .configuration_error("can't parse alternative_tint color code")?;
+        config_alternating_tint.theme.good_bg = util::add_colors(&config_alternating_tint.theme.good_bg, tint_bg)
+            .configuration_error("can't parse alternative_tint color code")?;
+        config_alternating_tint.theme.warning_bg = util::add_colors(&config_alternating_tint.theme.warning_bg, tint_bg)
+            .configuration_error("can't parse alternative_tint color code")?;
+        config_alternating_tint.theme.critical_bg = util::add_colors(&config_alternating_tint.theme.critical_bg, tint_bg)
+            .configuration_error("can't parse alternative_tint color code")?;
+
+        let tint_fg = &config.theme.alternating_tint_fg;
+        config_alternating_tint.theme.idle_fg = util::add_colors(&config_alternating_tint.theme.idle_fg, tint_fg)
+            .configuration_error("can't parse alternative_tint color code")?;
+        config_alternating_tint.theme.info_fg = util::add_colors(&config_alternating_tint.theme.info_fg, tint_fg)
+            .configuration_error("can't parse alternative_tint color code")?;
+        config_alternating_tint.theme.good_fg = util::add_colors(&config_alternating_tint.theme.good_fg, tint_fg)
+            .configuration_error("can't parse alternative_tint color code")?;
+        config_alternating_tint.theme.warning_fg = util::add_colors(&config_alternating_tint.theme.warning_fg, tint_fg)
+            .configuration_error("can't parse alternative_tint color code")?;
+        config_alternating_tint.theme.critical_fg = util::add_colors(&config_alternating_tint.theme.critical_fg, tint_fg)
+            .configuration_error("can't parse alternative_tint color code")?;
+    }
+
+    let mut blocks: Vec<(String, Box<Block>)> = Vec::new();
+
+    let mut alternator = false;
+    let mut instances: HashMap<&str, usize> = HashMap::new();
+    // Initialize the blocks
+    for &(ref block_name, ref block_config) in &config.blocks {
+        // Blocks that aren't shown still count, so that the others are named the same on every bar
+        let count = instances.entry(block_name.as_str()).or_insert(0);
+        *count += 1;
+        if !is_shown_on_bar(block_config, config) {
+            continue;
+        }
+
+        let mut shared_config = if alternator {
+            config_alternating_tint.clone()
+        } else {
+            config.clone()
+        };
+        // The n-th block of a type is called `<type>_<n>` unless it sets an `instance` name
+        shared_config.instance = if *count == 1 {
+            block_name.clone()
+        } else {
+            format!("{}_{}", block_name, count)
+        };
+
+        let instance = block_config
+            .get("instance")
+            .and_then(|instance| instance.as_str())
+            .map_or_else(|| shared_config.instance.clone(), String::from);
+        blocks.push((instance, create_block(
+            block_name,
+            block_config.clone(),
+            shared_config,
+            tx_update_requests.clone(),
+        )?));
+        alternator = !alternator;
+    }
+
+    Ok(blocks)
+}
+
+/// Prints the block statistics on SIGUSR1, and writes persisted block state (and prints
+/// the statistics if `print_stats` is set) before the bar is terminated by i3bar or the user.
+fn handle_signals(print_stats: bool) -> Result<()> {
+    let mut signals = SigSet::empty();
+    signals.add(Signal::SIGTERM);
+    signals.add(Signal::SIGINT);
+    signals.add(Signal::SIGHUP);
+    signals.add(Signal::SIGUSR1);
+    signals
+        .thread_block()
+        .internal_error("main", "failed to block signals")?;
+
+    thread::spawn(move || {
+        while let Ok(signal) = signals.wait() {
+            if signal == Signal::SIGUSR1 {
+                if let Err(error) = stats::report(&mut io::stderr()) {
+                    error!("{:?}", error);
+                }
+                continue;
+            }
+
+            if print_stats {
+                if let Err(error) = stats::report(&mut io::stderr()) {
+                    error!("{:?}", error);
+                }
+            }
+            info!("exiting on {:?}", signal);
+            subprocess::stop_all();
+            persist::flush();
+            ::std::process::exit(0);
+        }
+    });
+
+    Ok(())
+}
+
 #[cfg(feature = "profiling")]
 fn profile(iterations: i32, name: &str, block: &mut Block) {
     let mut bar = progress::Bar::new();
@@ -303,3 +626,97 @@ fn profile_config(_name: &str, _runs: &str, _config: &Config, _update: &Sender<T
         None,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{write_snapshot, Snapshot};
+    use crate::testing::{assert_golden, config, fixture_config, scratch_dir, widget_names, Bar, FakeClock};
+
+    #[test]
+    fn scheduled_updates_follow_the_clock() {
+        let counter = scratch_dir("scheduled_updates").join("count");
+        let config = config(&format!(
+            r#"
+            redraw_delay = 0
+
+            [[block]]
+            block = "custom"
+            interval = 60
+            command = "n=$(( $(cat {0} 2>/dev/null || echo 0) + 1 )); echo $n > {0}; echo update $n"
+            "#,
+            counter.display()
+        ));
+
+        let started = Instant::now();
+        let bar = Bar::start(config, FakeClock::new(Duration::from_secs(180)));
+        let lines = bar.wait_for_lines(5);
+
+        assert_golden("scheduled_updates.txt", &lines);
+        assert_eq!(bar.elapsed(), Duration::from_secs(180));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn clicks_reach_blocks() {
+        let config = config(
+            r#"
+            redraw_delay = 0
+
+            [[block]]
+            block = "custom"
+            cycle = ["echo first", "echo second"]
+
+            [[block]]
+            block = "toggle"
+            command_state = "true"
+            command_on = "true"
+            command_off = "true"
+            "#,
+        );
+
+        let mut bar = Bar::start(config, FakeClock::new(Duration::from_secs(0)));
+        // The first status line opens the infinite array of the protocol
+        let lines = bar.wait_for_lines(2);
+        let names = widget_names(&lines[1][1..]);
+
+        bar.click(&names[0], 1);
+        bar.wait_for_lines(3);
+        bar.click(&names[1], 1);
+        bar.wait_for_lines(4);
+        bar.click(&names[0], 1);
+        let lines = bar.wait_for_lines(5);
+
+        assert_golden("clicks.txt", &lines);
+    }
+
+    #[test]
+    fn json_snapshot_on_fixture() {
+        let config = fixture_config(
+            r#"
+            [[block]]
+            block = "load"
+
+            [[block]]
+            block = "uptime"
+
+            [[block]]
+            block = "backlight"
+            device = "intel_backlight"
+
+            [[block]]
+            block = "battery"
+
+            [[block]]
+            block = "memory"
+            format_mem = "{Mup}%"
+            display_type = "memory"
+            "#,
+        );
+        let mut out = Vec::new();
+        write_snapshot(&config, Snapshot::Json, &mut out).unwrap();
+        let lines: Vec<String> = String::from_utf8(out).unwrap().lines().map(String::from).collect();
+        assert_golden("fs_root/json_snapshot.txt", &lines);
+    }
+}
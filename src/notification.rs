@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chan::Sender;
+use dbus::arg::Variant;
+use dbus::{BusType, Connection, Message};
+
+use crate::block::Block;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::input::I3BarEvent;
+use crate::scheduler::Task;
+use crate::util::FormatTemplate;
+use crate::widget::{I3BarWidget, State};
+
+#[derive(Deserialize, Debug, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    fn from_state(state: State) -> Self {
+        match state {
+            State::Critical => Urgency::Critical,
+            State::Warning => Urgency::Normal,
+            _ => Urgency::Low,
+        }
+    }
+
+    /// The value of the `urgency` hint in the desktop notifications spec.
+    fn hint(self) -> u8 {
+        match self {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// States that trigger a notification when the block enters them
+    #[serde(default = "NotifyConfig::default_on")]
+    pub on: Vec<State>,
+
+    /// Urgency of the notification, derived from the state if not set
+    pub urgency: Option<Urgency>,
+
+    /// Format string for the notification summary
+    #[serde(default = "NotifyConfig::default_summary")]
+    pub summary: String,
+
+    /// Format string for the notification body
+    #[serde(default = "NotifyConfig::default_body")]
+    pub body: String,
+
+    /// Minimum time between two notifications of the same block
+    #[serde(default = "NotifyConfig::default_min_interval", deserialize_with = "deserialize_duration")]
+    pub min_interval: Duration,
+}
+
+impl NotifyConfig {
+    fn default_on() -> Vec<State> {
+        vec![State::Warning, State::Critical]
+    }
+
+    fn default_summary() -> String {
+        "{block}".to_owned()
+    }
+
+    fn default_body() -> String {
+        "{text}".to_owned()
+    }
+
+    fn default_min_interval() -> Duration {
+        Duration::from_secs(60)
+    }
+}
+
+/// Wraps a block and sends a desktop notification through `org.freedesktop.Notifications`
+/// whenever the block enters one of the configured states.
+///
+/// A notification that comes less than `min_interval` after the last one is held back and
+/// sent once the interval has passed, unless the block has left the state by then.
+pub struct Notifying {
+    inner: Box<Block>,
+    name: String,
+    on: Vec<State>,
+    urgency: Option<Urgency>,
+    summary: FormatTemplate,
+    body: FormatTemplate,
+    min_interval: Duration,
+    last_state: Option<State>,
+    last_sent: Option<Instant>,
+    /// State whose notification is held back by `min_interval`
+    pending: Option<State>,
+    notification_id: u32,
+    connection: Option<Connection>,
+    tx_update_request: Sender<Task>,
+}
+
+impl Notifying {
+    pub fn new(inner: Box<Block>, name: &str, config: NotifyConfig, tx_update_request: Sender<Task>) -> Result<Self> {
+        Ok(Notifying {
+            inner,
+            name: name.to_owned(),
+            on: config.on,
+            urgency: config.urgency,
+            summary: FormatTemplate::from_string(&config.summary)
+                .configuration_error("invalid notification summary format")?,
+            body: FormatTemplate::from_string(&config.body)
+                .configuration_error("invalid notification body format")?,
+            min_interval: config.min_interval,
+            last_state: None,
+            last_sent: None,
+            pending: None,
+            notification_id: 0,
+            connection: None,
+            tx_update_request,
+        })
+    }
+
+    fn check_transition(&mut self) {
+        let state = self.inner.state();
+        if self.last_state != Some(state) {
+            self.last_state = Some(state);
+            // A notification still held back for an earlier state is stale now.
+            self.pending = if self.on.contains(&state) { Some(state) } else { None };
+            if self.pending.is_some() {
+                if let Some(wait) = self.holdoff() {
+                    self.request_update_after(wait);
+                }
+            }
+        }
+
+        let state = match self.pending {
+            Some(state) if self.holdoff().is_none() => state,
+            _ => return,
+        };
+        self.pending = None;
+
+        // A notification that can't be delivered must not take the block down with it.
+        match self.notify(state) {
+            Ok(()) => self.last_sent = Some(Instant::now()),
+            Err(e) => warn!("failed to send notification: {}", e),
+        }
+    }
+
+    /// How long until `min_interval` has passed since the last notification, if it hasn't.
+    fn holdoff(&self) -> Option<Duration> {
+        let elapsed = self.last_sent?.elapsed();
+        if elapsed < self.min_interval {
+            Some(self.min_interval - elapsed)
+        } else {
+            None
+        }
+    }
+
+    /// Updates the block again after `wait` so that a held back notification goes out on time.
+    fn request_update_after(&self, wait: Duration) {
+        let tx_update_request = self.tx_update_request.clone();
+        let id = self.inner.id().to_owned();
+        thread::spawn(move || {
+            thread::sleep(wait);
+            tx_update_request.send(Task {
+                id,
+                update_time: Instant::now(),
+            });
+        });
+    }
+
+    fn notify(&mut self, state: State) -> Result<()> {
+        let text = self.inner
+            .view()
+            .iter()
+            .filter_map(|widget| widget.get_rendered()["full_text"].as_str().map(|t| t.trim().to_owned()))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut values = self.inner.placeholders();
+        values.insert("{block}".to_owned(), self.name.clone());
+        values.insert("{state}".to_owned(), format!("{:?}", state));
+        values.insert("{text}".to_owned(), text);
+        let values: HashMap<&str, &String> = values.iter().map(|(k, v)| (k.as_str(), v)).collect();
+
+        let summary = self.summary.render_static_str(&values)?;
+        let body = self.body.render_static_str(&values)?;
+
+        let mut hints = HashMap::new();
+        hints.insert("urgency", Variant(self.urgency.unwrap_or_else(|| Urgency::from_state(state)).hint()));
+
+        if self.connection.is_none() {
+            self.connection = Some(Connection::get_private(BusType::Session)
+                .block_error(&self.name, "failed to connect to the session bus for notifications")?);
+        }
+        let connection = self.connection
+            .as_ref()
+            .internal_error("notification", "no session bus connection")?;
+
+        let m = Message::new_method_call(
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "Notify",
+        ).block_error(&self.name, "failed to create notification message")?
+            .append3("i3status-rs", self.notification_id, "")
+            .append3(summary, body, Vec::<String>::new())
+            .append2(hints, -1i32);
+
+        let reply = connection
+            .send_with_reply_and_block(m, 1000)
+            .block_error(&self.name, "failed to send notification")?;
+        // Later notifications of this block replace the earlier one instead of piling up.
+        self.notification_id = reply.get1().unwrap_or(0);
+
+        Ok(())
+    }
+}
+
+impl Block for Notifying {
+    fn update(&mut self) -> Result<Option<Duration>> {
+        let next = self.inner.update()?;
+        self.check_transition();
+        Ok(next)
+    }
+
+    fn view(&self) -> Vec<&I3BarWidget> {
+        self.inner.view()
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        self.inner.click(event)?;
+        self.check_transition();
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    fn state(&self) -> State {
+        self.inner.state()
+    }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.inner.placeholders()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex, Once};
+    use std::thread;
+    use std::time::Duration;
+
+    use chan::{self, Receiver};
+    use dbus::arg::Variant;
+    use dbus::{BusType, Connection, ConnectionItem, NameFlag};
+
+    use super::{NotifyConfig, Notifying};
+    use crate::block::Block;
+    use crate::errors::*;
+    use crate::input::{I3BarEvent, MouseButton};
+    use crate::scheduler::Task;
+    use crate::testing::{config, private_session_bus};
+    use crate::widget::{I3BarWidget, State};
+    use crate::widgets::text::TextWidget;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Notification {
+        replaces_id: u32,
+        summary: String,
+        body: String,
+        urgency: u8,
+    }
+
+    lazy_static! {
+        static ref RECEIVED: Mutex<Vec<Notification>> = Mutex::new(Vec::new());
+    }
+
+    /// Starts a stand-in for the notification daemon on the private session bus, which
+    /// records what it is sent.
+    fn start_daemon() {
+        static START: Once = Once::new();
+        START.call_once(|| {
+            private_session_bus();
+            let (tx_ready, rx_ready) = chan::sync(0);
+            thread::spawn(move || {
+                let c = Connection::get_private(BusType::Session).unwrap();
+                c.register_name("org.freedesktop.Notifications", NameFlag::DoNotQueue as u32)
+                    .unwrap();
+                c.register_object_path("/org/freedesktop/Notifications").unwrap();
+                tx_ready.send(());
+
+                let mut last_id = 0;
+                loop {
+                    for item in c.iter(1000) {
+                        let m = match item {
+                            ConnectionItem::MethodCall(m) => m,
+                            _ => continue,
+                        };
+                        let (_, replaces_id, _, summary, body): (&str, u32, &str, &str, &str) = m.read5().unwrap();
+                        let mut args = m.iter_init();
+                        for _ in 0..6 {
+                            args.next();
+                        }
+                        let hints: HashMap<&str, Variant<u8>> = args.read().unwrap();
+
+                        RECEIVED.lock().unwrap().push(Notification {
+                            replaces_id,
+                            summary: summary.to_owned(),
+                            body: body.to_owned(),
+                            urgency: hints["urgency"].0,
+                        });
+                        last_id += 1;
+                        c.send(m.method_return().append1(last_id as u32)).unwrap();
+                    }
+                }
+            });
+            rx_ready.recv().unwrap();
+        });
+    }
+
+    /// Notifications the block `name` sent.
+    fn received(name: &str) -> Vec<Notification> {
+        RECEIVED
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|notification| notification.summary == name)
+            .cloned()
+            .collect()
+    }
+
+    /// A block that takes the state set through `next` on every update, and turns critical
+    /// when it's clicked.
+    struct Stub {
+        text: TextWidget,
+        next: Arc<Mutex<State>>,
+    }
+
+    impl Block for Stub {
+        fn update(&mut self) -> Result<Option<Duration>> {
+            let state = *self.next.lock().unwrap();
+            self.text.set_state(state);
+            self.text.set_text(format!("{:?}", state));
+            Ok(None)
+        }
+
+        fn view(&self) -> Vec<&I3BarWidget> {
+            vec![&self.text]
+        }
+
+        fn click(&mut self, _: &I3BarEvent) -> Result<()> {
+            self.text.set_state(State::Critical);
+            self.text.set_text("clicked".to_owned());
+            Ok(())
+        }
+
+        fn id(&self) -> &str {
+            "stub"
+        }
+    }
+
+    fn notifying(name: &str, notify: &str) -> (Notifying, Arc<Mutex<State>>, Receiver<Task>) {
+        start_daemon();
+        let next = Arc::new(Mutex::new(State::Idle));
+        let stub = Stub {
+            text: TextWidget::new(config("")),
+            next: next.clone(),
+        };
+        let (tx_update_request, rx_update_request) = chan::r#async();
+        let notify: NotifyConfig = ::toml::from_str(notify).unwrap();
+        let block = Notifying::new(Box::new(stub), name, notify, tx_update_request).unwrap();
+        (block, next, rx_update_request)
+    }
+
+    fn set(block: &mut Notifying, next: &Arc<Mutex<State>>, state: State) {
+        *next.lock().unwrap() = state;
+        block.update().unwrap();
+    }
+
+    #[test]
+    fn entering_a_state_notifies_once() {
+        let (mut block, next, _) = notifying("enter", "");
+        set(&mut block, &next, State::Idle);
+        set(&mut block, &next, State::Warning);
+        set(&mut block, &next, State::Warning);
+
+        assert_eq!(
+            received("enter"),
+            vec![Notification {
+                replaces_id: 0,
+                summary: "enter".to_owned(),
+                body: "Warning".to_owned(),
+                urgency: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn held_back_notification_is_sent_after_min_interval() {
+        let (mut block, next, rx_update_request) = notifying("held", "min_interval = 0.3");
+        set(&mut block, &next, State::Warning);
+        set(&mut block, &next, State::Idle);
+        set(&mut block, &next, State::Critical);
+        assert_eq!(received("held").len(), 1);
+
+        let timeout = chan::after(Duration::from_secs(5));
+        chan_select! {
+            rx_update_request.recv() -> task => assert_eq!(task.unwrap().id, "stub"),
+            timeout.recv() => panic!("no update was requested for the held back notification"),
+        }
+        block.update().unwrap();
+
+        let received = received("held");
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].body, "Critical");
+        assert_eq!(received[1].urgency, 2);
+        // The second notification replaces the first one.
+        assert_ne!(received[1].replaces_id, 0);
+    }
+
+    #[test]
+    fn held_back_notification_is_dropped_when_the_state_is_left() {
+        let (mut block, next, _) = notifying("left", "min_interval = 0.1");
+        set(&mut block, &next, State::Warning);
+        set(&mut block, &next, State::Idle);
+        set(&mut block, &next, State::Critical);
+        set(&mut block, &next, State::Idle);
+        thread::sleep(Duration::from_millis(200));
+        block.update().unwrap();
+
+        assert_eq!(received("left").len(), 1);
+    }
+
+    #[test]
+    fn clicks_that_change_the_state_notify() {
+        let (mut block, next, _) = notifying("click", "");
+        set(&mut block, &next, State::Idle);
+        block
+            .click(&I3BarEvent {
+                name: None,
+                instance: None,
+                x: 0,
+                y: 0,
+                button: MouseButton::Left,
+            })
+            .unwrap();
+
+        let received = received("click");
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].body, "clicked");
+        assert_eq!(received[0].urgency, 2);
+    }
+}
@@ -0,0 +1,143 @@
+//! A small key-value store for block state that should survive a restart of the bar.
+//!
+//! Blocks opt in by loading their state with their instance name (`Config::instance`) as
+//! key when they are created and saving it whenever it changes. Everything lives in a single
+//! JSON file under `$XDG_STATE_HOME/i3status-rust/`, which is replaced atomically on write.
+//! Bars sharing the file (e.g. one per output) each write only the keys they saved over
+//! what is in the file at that moment.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Map, Value};
+
+use crate::errors::*;
+use crate::util::xdg_state_home;
+
+/// State that changes on every update (e.g. graph history) is not written more often than this.
+const MIN_WRITE_INTERVAL: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref STORE: Mutex<Store> = Mutex::new(Store::open());
+}
+
+struct Store {
+    path: PathBuf,
+    values: Map<String, Value>,
+    /// Keys saved by this process, the others may belong to another bar on the same config
+    saved: HashSet<String>,
+    dirty: bool,
+    last_write: Option<Instant>,
+}
+
+/// Reads the state file. A missing or corrupt file just means there is nothing to restore.
+fn read(path: &Path) -> Map<String, Value> {
+    File::open(path)
+        .ok()
+        .and_then(|f| serde_json::from_reader(BufReader::new(f)).ok())
+        .unwrap_or_else(Map::new)
+}
+
+impl Store {
+    fn open() -> Self {
+        let path = xdg_state_home().join("i3status-rust").join("state.json");
+        Store {
+            values: read(&path),
+            path,
+            saved: HashSet::new(),
+            dirty: false,
+            last_write: None,
+        }
+    }
+
+    fn write(&mut self) -> Result<()> {
+        let dir = self.path
+            .parent()
+            .internal_error("persist", "state file has no parent directory")?;
+        fs::create_dir_all(dir).internal_error("persist", "failed to create state directory")?;
+
+        // Keep what other bars saved since this one read the file
+        let mut values = read(&self.path);
+        for key in &self.saved {
+            if let Some(value) = self.values.get(key) {
+                values.insert(key.clone(), value.clone());
+            }
+        }
+
+        let tmp_path = self.path.with_extension(format!("json.{}.tmp", process::id()));
+        let contents = serde_json::to_vec(&values).internal_error("persist", "failed to serialize state")?;
+        {
+            let mut tmp = File::create(&tmp_path).internal_error("persist", "failed to create state file")?;
+            tmp.write_all(&contents)
+                .internal_error("persist", "failed to write state file")?;
+            tmp.sync_all()
+                .internal_error("persist", "failed to write state file")?;
+        }
+        fs::rename(&tmp_path, &self.path).internal_error("persist", "failed to replace state file")?;
+
+        self.values = values;
+        self.dirty = false;
+        self.last_write = Some(Instant::now());
+        Ok(())
+    }
+}
+
+/// Returns the state saved for `key`, if there is any and it still has the expected shape.
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let store = STORE.lock().ok()?;
+    store
+        .values
+        .get(key)
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+}
+
+/// Saves the state of `key`. The file is written right away, unless it was written less than
+/// `MIN_WRITE_INTERVAL` ago, in which case it is written with the next save or on shutdown.
+///
+/// State that can't be saved is only lost after a restart, so failures are logged rather
+/// than taking the block down.
+pub fn save<T: Serialize>(key: &str, value: &T) {
+    if let Err(e) = try_save(key, value) {
+        warn!("failed to save state of {}: {}", key, e);
+    }
+}
+
+fn try_save<T: Serialize>(key: &str, value: &T) -> Result<()> {
+    let mut store = STORE
+        .lock()
+        .map_err(|_| InternalError("persist".to_owned(), "state store is poisoned".to_owned(), None))?;
+    let value = serde_json::to_value(value).internal_error("persist", "failed to serialize state")?;
+    if store.values.get(key) == Some(&value) {
+        return Ok(());
+    }
+    store.values.insert(key.to_owned(), value);
+    store.saved.insert(key.to_owned());
+    store.dirty = true;
+
+    let due = store
+        .last_write
+        .map_or(true, |last_write| last_write.elapsed() >= MIN_WRITE_INTERVAL);
+    if due {
+        store.write()
+    } else {
+        Ok(())
+    }
+}
+
+/// Writes pending changes, called on shutdown.
+pub fn flush() {
+    let result = STORE
+        .lock()
+        .map_err(|_| InternalError("persist".to_owned(), "state store is poisoned".to_owned(), None))
+        .and_then(|mut store| if store.dirty { store.write() } else { Ok(()) });
+    if let Err(e) = result {
+        warn!("failed to save state: {}", e);
+    }
+}
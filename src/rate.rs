@@ -0,0 +1,278 @@
+//! Rates of counters that only ever grow, like the bytes a network device has received, and
+//! averages that keep values from jumping around from one update to the next.
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::de::{self, Deserialize, Deserializer};
+
+/// Differences between readings of a counter.
+///
+/// A counter that went back since the last reading either wrapped around, if it is known to
+/// wrap at some maximum and went back by more than half of it, or was reset, e.g. because
+/// the device was removed and added again or the machine resumed from hibernation. A reset
+/// gives no difference, the next reading is taken against the new value.
+#[derive(Debug, Clone, Default)]
+pub struct Counter {
+    last: Option<u64>,
+    max: Option<u64>,
+}
+
+impl Counter {
+    pub fn new() -> Self {
+        Counter::default()
+    }
+
+    /// A counter whose first difference is taken against `value`, e.g. 0 for one that
+    /// counts since boot.
+    pub fn starting_at(value: u64) -> Self {
+        Counter {
+            last: Some(value),
+            max: None,
+        }
+    }
+
+    /// Makes the counter wrap around to 0 after `max`, e.g. `usize::max_value() as u64` for
+    /// one the kernel keeps in an `unsigned long`.
+    pub fn wrapping_at(mut self, max: u64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Feeds a new reading and returns how much the counter grew since the last one.
+    pub fn delta(&mut self, value: u64) -> Option<u64> {
+        let delta = match (self.last, self.max) {
+            (Some(last), _) if value >= last => Some(value - last),
+            (Some(last), Some(max)) if last - value > max / 2 => Some(max - last + value + 1),
+            _ => None,
+        };
+        self.last = Some(value);
+        delta
+    }
+}
+
+/// A counter read at irregular intervals, turned into its growth per second.
+#[derive(Debug, Clone)]
+pub struct Rate {
+    counter: Counter,
+    last_reading: Option<Instant>,
+}
+
+impl Rate {
+    pub fn new(counter: Counter) -> Self {
+        Rate {
+            counter,
+            last_reading: None,
+        }
+    }
+
+    /// Feeds a reading taken right now and returns the growth per second since the last
+    /// one, or `None` for the first reading and after the counter was reset.
+    pub fn update(&mut self, value: u64) -> Option<f64> {
+        let now = Instant::now();
+        let delta = self.counter.delta(value);
+        let elapsed = self.last_reading.map(|last| now.duration_since(last));
+        self.last_reading = Some(now);
+
+        match (delta, elapsed) {
+            (Some(delta), Some(elapsed)) if elapsed > Duration::from_secs(0) => Some(delta as f64 / seconds(elapsed)),
+            _ => None,
+        }
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.
+}
+
+/// How a block smoothes its values, e.g. `smoothing = "window 5"` in its config.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    /// Every value is shown as it is, `"none"`
+    None,
+    /// Exponential moving average, which moves by this share (above 0, at most 1) of the way
+    /// towards every new value, e.g. `"exponential 0.3"`
+    Exponential(f64),
+    /// Average of this many of the latest values, e.g. `"window 5"`
+    Window(usize),
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing::None
+    }
+}
+
+impl FromStr for Smoothing {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.as_slice() {
+            ["none"] => Ok(Smoothing::None),
+            ["exponential", share] => match share.parse::<f64>() {
+                Ok(share) if share > 0. && share <= 1. => Ok(Smoothing::Exponential(share)),
+                _ => Err(format!("exponential smoothing needs a share above 0 and at most 1, not {}", share)),
+            },
+            ["window", size] => match size.parse::<usize>() {
+                Ok(size) if size > 0 => Ok(Smoothing::Window(size)),
+                _ => Err(format!("window smoothing needs a number of values above 0, not {}", size)),
+            },
+            _ => Err(format!("unknown smoothing {:?}, expected none, \"exponential <share>\" or \"window <size>\"", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Smoothing {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A running average of values, see `Smoothing`.
+#[derive(Debug, Clone)]
+pub struct Average {
+    smoothing: Smoothing,
+    current: Option<f64>,
+    window: VecDeque<f64>,
+}
+
+impl Average {
+    pub fn new(smoothing: Smoothing) -> Self {
+        Average {
+            smoothing,
+            current: None,
+            window: VecDeque::new(),
+        }
+    }
+
+    /// Adds a value and returns the average including it.
+    pub fn add(&mut self, value: f64) -> f64 {
+        let average = match self.smoothing {
+            Smoothing::None => value,
+            Smoothing::Exponential(share) => self.current.map_or(value, |current| current + share * (value - current)),
+            Smoothing::Window(size) => {
+                self.window.push_back(value);
+                while self.window.len() > size {
+                    self.window.pop_front();
+                }
+                self.window.iter().sum::<f64>() / self.window.len() as f64
+            }
+        };
+        self.current = Some(average);
+        average
+    }
+
+    /// Adds a rate, or starts over with no traffic if its counter was reset.
+    pub fn add_rate(&mut self, rate: Option<f64>) -> f64 {
+        match rate {
+            Some(rate) => self.add(rate),
+            None => {
+                self.reset();
+                0.
+            }
+        }
+    }
+
+    /// Forgets all values, e.g. because the counter they came from was reset.
+    pub fn reset(&mut self) {
+        self.current = None;
+        self.window.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Average, Counter, Smoothing};
+
+    #[test]
+    fn counter_grows() {
+        let mut counter = Counter::starting_at(0);
+        assert_eq!(counter.delta(10), Some(10));
+        assert_eq!(counter.delta(10), Some(0));
+        assert_eq!(counter.delta(25), Some(15));
+    }
+
+    #[test]
+    fn first_reading_has_no_delta() {
+        let mut counter = Counter::new();
+        assert_eq!(counter.delta(1000), None);
+        assert_eq!(counter.delta(1500), Some(500));
+    }
+
+    #[test]
+    fn counter_wraps_around_at_max() {
+        let mut counter = Counter::starting_at(250).wrapping_at(255);
+        assert_eq!(counter.delta(4), Some(10));
+        assert_eq!(counter.delta(9), Some(5));
+
+        let mut counter = Counter::starting_at(u64::max_value() - 9).wrapping_at(u64::max_value());
+        assert_eq!(counter.delta(5), Some(15));
+    }
+
+    #[test]
+    fn counter_going_back_a_little_was_reset() {
+        // Going back by less than half the maximum can't be a wraparound
+        let mut counter = Counter::starting_at(100).wrapping_at(255);
+        assert_eq!(counter.delta(20), None);
+        assert_eq!(counter.delta(30), Some(10));
+
+        // Without a maximum any step back is a reset
+        let mut counter = Counter::starting_at(u64::max_value() - 9);
+        assert_eq!(counter.delta(5), None);
+        assert_eq!(counter.delta(8), Some(3));
+    }
+
+    #[test]
+    fn smoothing_is_parsed() {
+        assert_eq!("none".parse(), Ok(Smoothing::None));
+        assert_eq!("window 5".parse(), Ok(Smoothing::Window(5)));
+        assert_eq!("exponential 0.3".parse(), Ok(Smoothing::Exponential(0.3)));
+        for invalid in &["", "window", "window 0", "window -1", "exponential 0", "exponential 1.5", "median 3"] {
+            assert!(invalid.parse::<Smoothing>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn no_smoothing_shows_every_value() {
+        let mut average = Average::new(Smoothing::None);
+        assert_eq!(average.add(10.), 10.);
+        assert_eq!(average.add(2.), 2.);
+    }
+
+    #[test]
+    fn window_averages_the_latest_values() {
+        let mut average = Average::new(Smoothing::Window(3));
+        assert_eq!(average.add(3.), 3.);
+        assert_eq!(average.add(6.), 4.5);
+        assert_eq!(average.add(9.), 6.);
+        assert_eq!(average.add(12.), 9.);
+    }
+
+    #[test]
+    fn exponential_average_moves_towards_new_values() {
+        let mut average = Average::new(Smoothing::Exponential(0.5));
+        assert_eq!(average.add(8.), 8.);
+        assert_eq!(average.add(0.), 4.);
+        assert_eq!(average.add(0.), 2.);
+    }
+
+    #[test]
+    fn reset_forgets_earlier_values() {
+        let mut average = Average::new(Smoothing::Window(3));
+        average.add(100.);
+        average.add(200.);
+        average.reset();
+        assert_eq!(average.add(3.), 3.);
+
+        let mut average = Average::new(Smoothing::Exponential(0.5));
+        average.add(100.);
+        assert_eq!(average.add_rate(None), 0.);
+        assert_eq!(average.add_rate(Some(6.)), 6.);
+    }
+}
@@ -0,0 +1,84 @@
+//! The latest placeholder values of every block, for blocks that show values of others.
+//!
+//! Blocks publish their values through the `Timed` wrapper after every update and click,
+//! keyed by instance name and without the braces, e.g. `cpu` → `utilization` → `12`. The
+//! `composite` and `script` blocks read them, as does `--json-snapshot`.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+lazy_static! {
+    static ref VALUES: RwLock<HashMap<String, HashMap<String, String>>> = RwLock::new(HashMap::new());
+}
+
+/// Replaces the values of `instance` with `placeholders`, as returned by `Block::placeholders`.
+pub fn publish(instance: &str, placeholders: HashMap<String, String>) {
+    let values = placeholders
+        .into_iter()
+        .map(|(key, value)| (key.trim_matches(|c| c == '{' || c == '}').to_owned(), value))
+        .collect();
+    VALUES.write().unwrap().insert(instance.to_owned(), values);
+}
+
+/// The last published values of block `instance`.
+pub fn values(instance: &str) -> Option<HashMap<String, String>> {
+    VALUES.read().unwrap().get(instance).cloned()
+}
+
+/// The last published values of all blocks, by instance name.
+#[cfg(feature = "scripting")]
+pub fn all() -> HashMap<String, HashMap<String, String>> {
+    VALUES.read().unwrap().clone()
+}
+
+/// The last published value of `key` of block `instance`.
+#[cfg(feature = "scripting")]
+pub fn get(instance: &str, key: &str) -> Option<String> {
+    VALUES
+        .read()
+        .unwrap()
+        .get(instance)
+        .and_then(|values| values.get(key))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{publish, values};
+
+    #[test]
+    fn values_are_published_without_braces() {
+        let mut placeholders = HashMap::new();
+        placeholders.insert("{count}".to_owned(), "3".to_owned());
+        publish("registry_braces", placeholders);
+        assert_eq!(values("registry_braces").unwrap()["count"], "3");
+        assert_eq!(values("registry_nothing"), None);
+    }
+
+    #[test]
+    fn values_are_replaced_by_the_next_update() {
+        let mut placeholders = HashMap::new();
+        placeholders.insert("{a}".to_owned(), "1".to_owned());
+        publish("registry_replaced", placeholders);
+        let mut placeholders = HashMap::new();
+        placeholders.insert("{b}".to_owned(), "2".to_owned());
+        publish("registry_replaced", placeholders);
+
+        let values = values("registry_replaced").unwrap();
+        assert_eq!(values.get("a"), None);
+        assert_eq!(values["b"], "2");
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn single_values_can_be_looked_up() {
+        let mut placeholders = HashMap::new();
+        placeholders.insert("{percentage}".to_owned(), "80".to_owned());
+        publish("registry_get", placeholders);
+        assert_eq!(super::get("registry_get", "percentage"), Some("80".to_owned()));
+        assert_eq!(super::get("registry_get", "time"), None);
+        assert_eq!(super::all()["registry_get"]["percentage"], "80");
+    }
+}
@@ -2,9 +2,11 @@ use crate::block::Block;
 use crate::errors::*;
 use std::collections::{BinaryHeap, HashMap};
 use std::fmt;
+use std::sync::Arc;
 use std::thread;
 use std::cmp;
 use std::time::{Duration, Instant};
+use chan::{self, Receiver};
 
 #[derive(Debug, Clone)]
 pub struct Task {
@@ -38,15 +40,81 @@ impl cmp::Ord for Task {
     }
 }
 
+/// Source of time for the scheduler and the main loop, so that they can be driven by a
+/// simulated clock instead of waiting in real time.
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    /// Blocks until `duration` has passed.
+    fn sleep(&self, duration: Duration);
+
+    /// Returns a channel that fires once `duration` has passed.
+    fn after(&self, duration: Duration) -> Receiver<()>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    fn after(&self, duration: Duration) -> Receiver<()> {
+        chan::after(duration)
+    }
+}
+
+/// The clock of the bar, handed to blocks in their `Config` so that those that keep time
+/// themselves, like `group` or the `min_dwell` of `Thresholds`, follow the same clock.
+#[derive(Clone)]
+pub struct SharedClock(Arc<Clock + Send + Sync>);
+
+impl SharedClock {
+    pub fn new<C: Clock + Send + Sync + 'static>(clock: C) -> Self {
+        SharedClock(Arc::new(clock))
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        SharedClock::new(SystemClock)
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedClock")
+    }
+}
+
+impl Clock for SharedClock {
+    fn now(&self) -> Instant {
+        self.0.now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.0.sleep(duration)
+    }
+
+    fn after(&self, duration: Duration) -> Receiver<()> {
+        self.0.after(duration)
+    }
+}
+
 pub struct UpdateScheduler {
     schedule: BinaryHeap<Task>,
+    clock: Box<Clock>,
 }
 
 impl UpdateScheduler {
-    pub fn new(blocks: &[Box<Block>]) -> UpdateScheduler {
+    pub fn new(blocks: &[Box<Block>], clock: Box<Clock>) -> UpdateScheduler {
         let mut schedule = BinaryHeap::new();
 
-        let now = Instant::now();
+        let now = clock.now();
         for block in blocks.iter() {
             schedule.push(Task {
                 id: String::from(block.id()),
@@ -54,13 +122,17 @@ impl UpdateScheduler {
             });
         }
 
-        UpdateScheduler { schedule }
+        UpdateScheduler { schedule, clock }
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &*self.clock
     }
 
     pub fn time_to_next_update(&self) -> Option<Duration> {
         if let Some(peeked) = self.schedule.peek() {
             let next_update = peeked.update_time;
-            let now = Instant::now();
+            let now = self.clock.now();
 
             if next_update > now {
                 Some(next_update - now)
@@ -90,13 +162,14 @@ impl UpdateScheduler {
                 .internal_error("scheduler", "schedule is empty")?)
         }
 
-        let now = Instant::now();
+        let now = self.clock.now();
         if t.update_time > now {
-            thread::sleep(t.update_time - now);
+            self.clock.sleep(t.update_time - now);
         }
 
-        let now = Instant::now();
+        let now = self.clock.now();
 
+        trace!("running {} scheduled update(s)", tasks_next.len());
         for task in tasks_next {
             if let Some(dur) = block_map
                 .get_mut(&task.id)
@@ -0,0 +1,163 @@
+//! Lightweight timing statistics for every block, to find the one that makes the bar lag.
+//!
+//! Every block is wrapped in `Timed` when it is created, so updates are counted no matter
+//! whether they were scheduled or requested by the block itself. The statistics are
+//! printed to stderr on SIGUSR1 and, with `--stats`, when the bar exits. After every update
+//! the wrapper also publishes the placeholder values of its block to the `registry`.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::block::Block;
+use crate::errors::*;
+use crate::input::I3BarEvent;
+use crate::registry;
+use crate::widget::{I3BarWidget, State};
+
+lazy_static! {
+    /// Statistics of all blocks, in the order they were created
+    static ref STATS: Mutex<Vec<BlockStats>> = Mutex::new(Vec::new());
+}
+
+struct BlockStats {
+    name: String,
+    updates: u32,
+    errors: u32,
+    total: Duration,
+    max: Duration,
+    last: Duration,
+    last_success: Option<SystemTime>,
+}
+
+impl BlockStats {
+    fn record(&mut self, duration: Duration, success: bool) {
+        self.updates += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+        self.last = duration;
+        if success {
+            self.last_success = Some(SystemTime::now());
+        } else {
+            self.errors += 1;
+        }
+    }
+}
+
+/// Wraps a block and records how long its updates take and whether they fail.
+pub struct Timed {
+    inner: Box<Block>,
+    index: usize,
+    name: String,
+    target: String,
+}
+
+impl Timed {
+    pub fn new(inner: Box<Block>, name: &str) -> Self {
+        let mut stats = STATS.lock().unwrap();
+        stats.push(BlockStats {
+            name: name.to_owned(),
+            updates: 0,
+            errors: 0,
+            total: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+            last: Duration::from_secs(0),
+            last_success: None,
+        });
+
+        Timed {
+            inner,
+            index: stats.len() - 1,
+            name: name.to_owned(),
+            target: format!("block::{}", name),
+        }
+    }
+}
+
+impl Block for Timed {
+    fn update(&mut self) -> Result<Option<Duration>> {
+        let start = Instant::now();
+        let result = self.inner.update();
+        let elapsed = start.elapsed();
+        STATS.lock().unwrap()[self.index].record(elapsed, result.is_ok());
+
+        match result {
+            Ok(next) => {
+                trace!(target: &self.target, "updated in {:?}, next update in {:?}", elapsed, next);
+                registry::publish(&self.name, self.inner.placeholders());
+            }
+            Err(ref e) => error!(target: &self.target, "update failed after {:?}: {:?}", elapsed, e),
+        }
+        result
+    }
+
+    fn view(&self) -> Vec<&I3BarWidget> {
+        self.inner.view()
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        self.inner.click(event)?;
+        registry::publish(&self.name, self.inner.placeholders());
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    fn state(&self) -> State {
+        self.inner.state()
+    }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.inner.placeholders()
+    }
+}
+
+/// Writes a table with the statistics of every block, slowest average update first.
+pub fn report(out: &mut Write) -> Result<()> {
+    let stats = STATS.lock().unwrap();
+    let mut rows: Vec<&BlockStats> = stats.iter().collect();
+    rows.sort_by_key(|stats| stats.total.checked_div(stats.updates.max(1)));
+    rows.reverse();
+
+    let width = rows.iter().map(|stats| stats.name.len()).max().unwrap_or(0).max(5);
+    let mut table = format!(
+        "{:<width$} {:>8} {:>10} {:>10} {:>10} {:>7} {:>13}\n",
+        "block",
+        "updates",
+        "avg ms",
+        "max ms",
+        "last ms",
+        "errors",
+        "last success",
+        width = width
+    );
+    for stats in rows {
+        let average = stats.total.checked_div(stats.updates.max(1)).unwrap_or_default();
+        let last_success = match stats.last_success.map(|time| time.elapsed()) {
+            Some(Ok(elapsed)) => format!("{}s ago", elapsed.as_secs()),
+            Some(Err(_)) => "just now".to_owned(),
+            None => "never".to_owned(),
+        };
+        table.push_str(&format!(
+            "{:<width$} {:>8} {:>10.1} {:>10.1} {:>10.1} {:>7} {:>13}\n",
+            stats.name,
+            stats.updates,
+            millis(average),
+            millis(stats.max),
+            millis(stats.last),
+            stats.errors,
+            last_success,
+            width = width
+        ));
+    }
+
+    out.write_all(table.as_bytes())
+        .internal_error("stats", "failed to write statistics")
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000. + f64::from(duration.subsec_nanos()) / 1_000_000.
+}
@@ -1,6 +1,75 @@
-use std::io;
-use std::process::Command;
+//! Running external programs from blocks.
+//!
+//! Programs are started with their arguments passed as they are, without a shell in
+//! between, unless the command comes from the user's config (`run_shell`). Every program
+//! runs in its own process group, so that a hanging one can be killed together with
+//! everything it started once its timeout is up. The timeout also covers programs it left
+//! running in the background that still hold on to its output.
+//!
+//! Programs started on a click, which may well be long-running applications, are started
+//! with `spawn_child_async` or `spawn_shell_async` instead and never waited for.
+//!
+//! Programs that run as long as the bar, like `persistent` custom commands, are started
+//! with `spawn_long_running`. Being in a group of their own, they don't get the signal
+//! i3bar sends the bar's group when it exits or reloads, so they are stopped by the bar
+//! instead (`stop_long_running` and `stop_all`).
+
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
 use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::sys::signal::{kill, sigprocmask, SigSet, SigmaskHow, Signal};
+use nix::unistd::{setpgid, Pid};
+
+use crate::errors::*;
+
+/// How long a program may run unless a block knows better
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a running program is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+lazy_static! {
+    /// Children started by `spawn_child_async`, handed to the thread that reaps them
+    static ref REAPER: Mutex<Sender<(String, Child)>> = Mutex::new(start_reaper());
+
+    /// Ids of the children started by `spawn_long_running` that haven't been stopped, which
+    /// are also the ids of their process groups
+    static ref LONG_RUNNING: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+}
+
+/// What a program printed and how it exited.
+#[derive(Debug)]
+pub struct Output {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Output {
+    /// The output of a successful run, or an error with what the program printed to stderr.
+    pub fn into_stdout(self, block: &str, message: &str) -> Result<String> {
+        if self.status.success() {
+            Ok(self.stdout)
+        } else {
+            let stderr = self.stderr.trim();
+            Err(BlockError(
+                block.to_owned(),
+                if stderr.is_empty() {
+                    format!("{} ({})", message, self.status)
+                } else {
+                    format!("{}: {}", message, stderr)
+                },
+            ))
+        }
+    }
+}
 
 /// Splits a string into command name and arguments.
 pub fn parse_command(command: &str) -> (&str, Vec<&str>) {
@@ -10,9 +79,218 @@ pub fn parse_command(command: &str) -> (&str, Vec<&str>) {
     (name, args.to_vec())
 }
 
-// Spawns a new child process. This returns to the caller after the child has been started. A new thread waits for the child to exit.
+/// Builds a command that runs in a process group of its own, with no signals blocked. The
+/// bar blocks the signals it handles itself (see `handle_signals` in main.rs), which children
+/// would otherwise inherit, so that e.g. an application started on a click ignored `kill`.
+pub(crate) fn command(program: &str, args: &[&str]) -> Command {
+    let mut command = Command::new(program);
+    command.args(args);
+    unsafe {
+        command.pre_exec(|| {
+            setpgid(Pid::from_raw(0), Pid::from_raw(0)).map_err(|_| io::Error::last_os_error())?;
+            sigprocmask(SigmaskHow::SIG_SETMASK, Some(&SigSet::empty()), None)
+                .map_err(|_| io::Error::last_os_error())
+        });
+    }
+    command
+}
+
+/// Runs `program` with `args` and waits for it to exit. If it takes longer than `timeout`,
+/// it is killed along with its process group and an error of kind `TimedOut` is returned.
+pub fn run(program: &str, args: &[&str], timeout: Duration) -> io::Result<Output> {
+    trace!("running {} {:?}", program, args);
+    let mut child = command(program, args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Both pipes are drained while waiting, so that a chatty program can't fill them up
+    // and block forever.
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            kill_group(&child);
+            let _ = child.wait();
+            return Err(timed_out(program, args, timeout));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    // Processes it started in the background keep the pipes open after it exited
+    match (receive_before(&stdout, deadline), receive_before(&stderr, deadline)) {
+        (Some(stdout), Some(stderr)) => Ok(Output { status, stdout, stderr }),
+        _ => {
+            kill_group(&child);
+            Err(timed_out(program, args, timeout))
+        }
+    }
+}
+
+/// Runs a command from the config through the user's shell (`$SHELL`, or `sh`), see `run`.
+pub fn run_shell(command: &str, timeout: Duration) -> io::Result<Output> {
+    run(&shell(), &["-c", command], timeout)
+}
+
+/// The user's shell, `$SHELL`, or `sh`.
+pub(crate) fn shell() -> String {
+    env::var("SHELL").unwrap_or_else(|_| "sh".to_owned())
+}
+
+fn kill_group(child: &Child) {
+    let _ = kill(Pid::from_raw(-(child.id() as i32)), Signal::SIGKILL);
+}
+
+fn timed_out(program: &str, args: &[&str], timeout: Duration) -> io::Error {
+    warn!("{} {:?} timed out after {:?}, killed it", program, args, timeout);
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("{} timed out after {:?}", program, timeout),
+    )
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        let _ = tx.send(String::from_utf8_lossy(&bytes).into_owned());
+    });
+    rx
+}
+
+/// What was read from a pipe, or `None` if it is still open at `deadline`.
+fn receive_before(output: &Receiver<String>, deadline: Instant) -> Option<String> {
+    let now = Instant::now();
+    let timeout = if now < deadline { deadline - now } else { Duration::from_secs(0) };
+    match output.recv_timeout(timeout) {
+        Ok(output) => Some(output),
+        Err(mpsc::RecvTimeoutError::Timeout) => None,
+        Err(mpsc::RecvTimeoutError::Disconnected) => Some(String::new()),
+    }
+}
+
+/// Spawns a new child process and returns to the caller once it has been started. The
+/// child is reaped by a single shared thread when it exits.
 pub fn spawn_child_async(name: &str, args: &[&str]) -> io::Result<()> {
-    let mut child = Command::new(name).args(args).spawn()?;
-    thread::spawn(move || child.wait());
-    Ok(())
+    debug!("spawning {} {:?}", name, args);
+    let child = command(name, args).stdin(Stdio::null()).spawn()?;
+    REAPER
+        .lock()
+        .unwrap()
+        .send((name.to_owned(), child))
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "reaper thread is gone"))
+}
+
+/// Spawns a command from the config through the user's shell, see `spawn_child_async`.
+pub fn spawn_shell_async(command: &str) -> io::Result<()> {
+    spawn_child_async(&shell(), &["-c", command])
+}
+
+/// Spawns `command`, built by `command`, for a child that runs as long as the bar. Its
+/// process group is stopped by `stop_long_running`, or by `stop_all` when the bar exits.
+pub(crate) fn spawn_long_running(command: &mut Command) -> io::Result<Child> {
+    let child = command.spawn()?;
+    LONG_RUNNING.lock().unwrap().insert(child.id());
+    Ok(child)
+}
+
+/// Stops the process group of a child started by `spawn_long_running`, unless that has
+/// happened already.
+pub(crate) fn stop_long_running(id: u32) {
+    if LONG_RUNNING.lock().unwrap().remove(&id) {
+        let _ = kill(Pid::from_raw(-(id as i32)), Signal::SIGTERM);
+    }
+}
+
+/// Forgets a child started by `spawn_long_running` that exited on its own.
+pub(crate) fn forget_long_running(id: u32) {
+    LONG_RUNNING.lock().unwrap().remove(&id);
+}
+
+/// Stops the process groups of all children started by `spawn_long_running`, when the bar
+/// exits.
+pub fn stop_all() {
+    for id in LONG_RUNNING.lock().unwrap().drain() {
+        let _ = kill(Pid::from_raw(-(id as i32)), Signal::SIGTERM);
+    }
+}
+
+fn start_reaper() -> Sender<(String, Child)> {
+    let (tx, rx) = mpsc::channel::<(String, Child)>();
+    thread::spawn(move || {
+        let mut children: Vec<(String, Child)> = Vec::new();
+        loop {
+            // Nothing to reap, so sleep until the next child arrives
+            let timeout = if children.is_empty() {
+                Duration::from_secs(3600)
+            } else {
+                Duration::from_millis(500)
+            };
+            match rx.recv_timeout(timeout) {
+                Ok(child) => children.push(child),
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+
+            children = children
+                .into_iter()
+                .filter_map(|(name, mut child)| match child.try_wait() {
+                    Ok(Some(status)) => {
+                        if !status.success() {
+                            debug!("{} exited with {}", name, status);
+                        }
+                        None
+                    }
+                    Ok(None) => Some((name, child)),
+                    Err(e) => {
+                        warn!("failed to wait for {}: {}", name, e);
+                        None
+                    }
+                })
+                .collect();
+        }
+    });
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nix::sys::signal::{SigSet, Signal};
+
+    use super::run;
+
+    #[test]
+    fn children_have_no_signals_blocked() {
+        // Like the bar does in `handle_signals`, children are forked from this thread
+        let mut signals = SigSet::empty();
+        signals.add(Signal::SIGTERM);
+        signals.add(Signal::SIGINT);
+        signals.add(Signal::SIGHUP);
+        signals.add(Signal::SIGUSR1);
+        signals.thread_block().unwrap();
+        let output = run("grep", &["SigBlk", "/proc/self/status"], Duration::from_secs(5));
+        signals.thread_unblock().unwrap();
+
+        let blocked = output.unwrap().stdout;
+        let mask = blocked.trim().trim_start_matches("SigBlk:").trim();
+        assert!(mask.chars().all(|c| c == '0'), "{}", blocked);
+    }
+
+    #[test]
+    fn children_get_their_own_process_group() {
+        let output = run("sh", &["-c", "ps -o pgid= -p $$; echo $$"], Duration::from_secs(5)).unwrap();
+        let lines: Vec<&str> = output.stdout.split_whitespace().collect();
+        assert_eq!(lines[0], lines[1]);
+    }
 }
@@ -0,0 +1,282 @@
+//! Helpers for tests that run blocks or the whole bar without a real machine or i3bar.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::mem;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chan::{self, Receiver, Sender};
+use regex::Regex;
+
+use crate::blocks::create_block;
+use crate::config::Config;
+use crate::scheduler::{Clock, SharedClock, Task};
+
+/// Directory with the fixtures and golden files of the tests.
+pub fn testdata(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata").join(path)
+}
+
+/// A scratch directory for a test, emptied beforehand.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("i3status-rs-test-{}", process_id())).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn process_id() -> u32 {
+    ::std::process::id()
+}
+
+/// Keeps the state blocks persist out of the user's state directory.
+pub fn isolate_state() {
+    static ISOLATE: Once = Once::new();
+    ISOLATE.call_once(|| env::set_var("XDG_STATE_HOME", scratch_dir("state")));
+}
+
+/// Points the session bus at a private `dbus-daemon`, started on first use and stopped when
+/// the tests exit.
+pub fn private_session_bus() {
+    static START: Once = Once::new();
+    START.call_once(|| {
+        let dir = scratch_dir("dbus");
+        let socket = dir.join("bus");
+        let config = dir.join("session.conf");
+        fs::write(
+            &config,
+            format!(
+                r#"<busconfig>
+  <type>session</type>
+  <listen>unix:path={}</listen>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#,
+                socket.display()
+            ),
+        ).unwrap();
+
+        // The shell stops the daemon once its stdin closes, i.e. when the tests exit.
+        let child = Command::new("sh")
+            .args(&["-c", "dbus-daemon --nofork --config-file=\"$1\" & read _; kill $!; wait", "sh"])
+            .arg(&config)
+            .stdin(Stdio::piped())
+            .spawn()
+            .expect("failed to start dbus-daemon");
+        mem::forget(child);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !socket.exists() {
+            assert!(Instant::now() < deadline, "dbus-daemon didn't create {}", socket.display());
+            thread::sleep(Duration::from_millis(10));
+        }
+        env::set_var("DBUS_SESSION_BUS_ADDRESS", format!("unix:path={}", socket.display()));
+    });
+}
+
+/// Parses a config file, e.g. `[[block]]\nblock = "time"`.
+pub fn config(toml: &str) -> Config {
+    isolate_state();
+    ::toml::from_str(toml).unwrap()
+}
+
+/// Parses a config file for the machine whose `/sys` and `/proc` are in `testdata/fs_root`.
+pub fn fixture_config(toml: &str) -> Config {
+    config(&format!("fs_root = {:?}\n{}", testdata("fs_root").display().to_string(), toml))
+}
+
+/// Creates block `name` with the options in `block_config` on the machine whose `/sys` and
+/// `/proc` are in `testdata/fs_root`, updates it once and returns its widgets as i3bar
+/// renders them, one per line.
+pub fn render_on_fixture(name: &str, block_config: &str) -> Vec<String> {
+    let mut config = fixture_config("");
+    config.instance = name.to_owned();
+    let (tx_update_request, _rx_update_request) = chan::r#async::<Task>();
+    let mut block = create_block(name, ::toml::from_str(block_config).unwrap(), config, tx_update_request)
+        .unwrap_or_else(|e| panic!("failed to create {}: {:?}", name, e));
+    block
+        .update()
+        .unwrap_or_else(|e| panic!("failed to update {}: {:?}", name, e));
+    block
+        .view()
+        .iter()
+        .map(|widget| widget.get_rendered().to_string())
+        .collect()
+}
+
+/// Replaces what changes from run to run, i.e. the random names of the widgets.
+pub fn normalize(line: &str) -> String {
+    lazy_static! {
+        static ref NAME: Regex = Regex::new(r#""name":"[0-9a-f]{32}""#).unwrap();
+    }
+    NAME.replace_all(line, r#""name":"<id>""#).into_owned()
+}
+
+/// The names of the widgets in a status line, which click events are addressed to.
+pub fn widget_names(line: &str) -> Vec<String> {
+    let widgets: Vec<::serde_json::Value> = ::serde_json::from_str(line).unwrap();
+    widgets
+        .iter()
+        .filter_map(|widget| widget["name"].as_str().map(String::from))
+        .collect()
+}
+
+/// Compares `lines`, normalized, to the golden file `testdata/golden/<name>`, or rewrites
+/// the file if `UPDATE_GOLDEN` is set.
+pub fn assert_golden(name: &str, lines: &[String]) {
+    let path = testdata("golden").join(name);
+    let actual: String = lines.iter().map(|line| format!("{}\n", normalize(line))).collect();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+    assert_eq!(expected, actual, "output differs from {}", path.display());
+}
+
+/// A clock whose time only passes when the bar waits for it, so that hours of updates run
+/// in an instant. Timers that would fire after `until` never fire, which leaves the bar
+/// waiting for clicks.
+pub struct FakeClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+    until: Duration,
+    /// Senders of the timers that never fire, kept so that their channels stay open
+    pending: Mutex<Vec<Sender<()>>>,
+}
+
+impl FakeClock {
+    pub fn new(until: Duration) -> Self {
+        FakeClock {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::from_secs(0))),
+            until,
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// How much time has passed on the clock, shared so that it can be read while the bar
+    /// owns the clock.
+    pub fn elapsed(&self) -> Arc<Mutex<Duration>> {
+        self.elapsed.clone()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    fn after(&self, duration: Duration) -> Receiver<()> {
+        let (tx, rx) = chan::sync(1);
+        let mut elapsed = self.elapsed.lock().unwrap();
+        if *elapsed + duration <= self.until {
+            *elapsed += duration;
+            tx.send(());
+        } else {
+            self.pending.lock().unwrap().push(tx);
+        }
+        rx
+    }
+}
+
+/// Status lines written by the bar, without the commas between them.
+#[derive(Default)]
+struct Lines {
+    lines: Mutex<Vec<String>>,
+    changed: Condvar,
+}
+
+struct Recorder {
+    lines: Arc<Lines>,
+    partial: Vec<u8>,
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            self.lines
+                .lines
+                .lock()
+                .unwrap()
+                .push(line.trim_end().trim_end_matches(',').to_owned());
+            self.lines.changed.notify_all();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A bar running in the background, as i3bar would see it.
+pub struct Bar {
+    lines: Arc<Lines>,
+    clicks: UnixStream,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Bar {
+    pub fn start(mut config: Config, clock: FakeClock) -> Bar {
+        let lines = Arc::new(Lines::default());
+        let (mut clicks, input) = UnixStream::pair().unwrap();
+        clicks.write_all(b"[\n").unwrap();
+        let elapsed = clock.elapsed();
+        config.clock = SharedClock::new(clock);
+
+        let mut out = Recorder {
+            lines: lines.clone(),
+            partial: Vec::new(),
+        };
+        thread::spawn(move || {
+            if let Err(e) = crate::serve(move || Ok(config), BufReader::new(input), &mut out) {
+                panic!("bar failed: {:?}", e);
+            }
+        });
+
+        Bar { lines, clicks, elapsed }
+    }
+
+    /// Waits until the bar has written `count` lines, the protocol header included, and
+    /// returns them.
+    pub fn wait_for_lines(&self, count: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut lines = self.lines.lines.lock().unwrap();
+        while lines.len() < count {
+            let now = Instant::now();
+            assert!(now < deadline, "expected {} status lines, got {:#?}", count, *lines);
+            lines = self.lines.changed.wait_timeout(lines, deadline - now).unwrap().0;
+        }
+        lines.clone()
+    }
+
+    /// Clicks the widget `name` with mouse `button` (1 is left).
+    pub fn click(&mut self, name: &str, button: u64) {
+        let event = json!({ "name": name, "button": button, "x": 0, "y": 0 });
+        writeln!(self.clicks, "{},", event).unwrap();
+    }
+
+    /// Time that passed on the bar's clock.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
@@ -0,0 +1,238 @@
+use std::time::{Duration, Instant};
+
+use crate::scheduler::{Clock, SharedClock};
+use crate::widget::State;
+
+/// How a value is compared against the threshold of a level.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// The level applies while the value is above the threshold.
+    Above,
+    /// The level applies while the value is at or above the threshold.
+    AtLeast,
+    /// The level applies while the value is below the threshold.
+    Below,
+    /// The level applies while the value is at or below the threshold.
+    AtMost,
+}
+
+impl Trigger {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Trigger::Above => value > threshold,
+            Trigger::AtLeast => value >= threshold,
+            Trigger::Below => value < threshold,
+            Trigger::AtMost => value <= threshold,
+        }
+    }
+
+    /// Moves a threshold towards the less severe side, so that a level which has been
+    /// entered is only left once the value has receded by `hysteresis`.
+    fn relax(self, threshold: f64, hysteresis: f64) -> f64 {
+        match self {
+            Trigger::Above | Trigger::AtLeast => threshold - hysteresis,
+            Trigger::Below | Trigger::AtMost => threshold + hysteresis,
+        }
+    }
+}
+
+/// Maps a value onto a `State` using a ladder of thresholds.
+///
+/// Levels are added from the least to the most severe one, the most severe level whose
+/// threshold is crossed wins and `base` is used if none is. Two optional dampers keep a
+/// value hovering around a threshold from making the block flicker:
+///
+/// * `hysteresis`: a level is entered at its threshold, but only left again once the value
+///   has moved back past the threshold by this amount.
+/// * `min_dwell`: a new state only takes effect after it has been observed for this long,
+///   as measured by `clock`, the clock of the bar.
+#[derive(Debug, Clone)]
+pub struct Thresholds {
+    base: State,
+    levels: Vec<(State, Trigger, f64)>,
+    hysteresis: f64,
+    min_dwell: Option<Duration>,
+    clock: SharedClock,
+    current: Option<usize>,
+    pending: Option<(usize, Instant)>,
+}
+
+impl Thresholds {
+    pub fn new(base: State, hysteresis: f64, min_dwell: Option<Duration>, clock: SharedClock) -> Self {
+        Thresholds {
+            base,
+            levels: Vec::new(),
+            hysteresis,
+            min_dwell,
+            clock,
+            current: None,
+            pending: None,
+        }
+    }
+
+    /// Adds a level that is more severe than all previously added ones.
+    pub fn with_level(mut self, state: State, trigger: Trigger, threshold: f64) -> Self {
+        self.levels.push((state, trigger, threshold));
+        self
+    }
+
+    /// Feeds a new value and returns the state the block should be displayed in.
+    pub fn state(&mut self, value: f64) -> State {
+        let target = self.target(value);
+
+        match self.current {
+            Some(current) if current != target => match self.min_dwell {
+                Some(min_dwell) => {
+                    let now = self.clock.now();
+                    match self.pending {
+                        Some((pending, since)) if pending == target => {
+                            if now.duration_since(since) >= min_dwell {
+                                self.current = Some(target);
+                                self.pending = None;
+                            }
+                        }
+                        _ => self.pending = Some((target, now)),
+                    }
+                }
+                None => self.current = Some(target),
+            },
+            Some(_) => self.pending = None,
+            // The first value is taken as is, there is nothing to dampen yet.
+            None => self.current = Some(target),
+        }
+
+        self.level_state(self.current.unwrap_or(0))
+    }
+
+    /// Index of the level the value belongs to, `0` being `base` and `i` being `levels[i - 1]`.
+    fn target(&self, value: f64) -> usize {
+        let current = self.current.unwrap_or(0);
+        (1..=self.levels.len())
+            .rev()
+            .find(|&i| {
+                let (_, trigger, threshold) = self.levels[i - 1];
+                let threshold = if i <= current {
+                    trigger.relax(threshold, self.hysteresis)
+                } else {
+                    threshold
+                };
+                trigger.holds(value, threshold)
+            })
+            .unwrap_or(0)
+    }
+
+    fn level_state(&self, index: usize) -> State {
+        if index == 0 {
+            self.base
+        } else {
+            self.levels[index - 1].0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Thresholds, Trigger};
+    use crate::scheduler::{Clock, SharedClock};
+    use crate::testing::FakeClock;
+    use crate::widget::State;
+
+    fn clock() -> SharedClock {
+        SharedClock::new(FakeClock::new(Duration::from_secs(0)))
+    }
+
+    /// The battery's ladder: the lower the capacity, the more severe.
+    fn battery(hysteresis: f64, min_dwell: Option<Duration>, clock: &SharedClock) -> Thresholds {
+        Thresholds::new(State::Good, hysteresis, min_dwell, clock.clone())
+            .with_level(State::Info, Trigger::AtMost, 60.)
+            .with_level(State::Warning, Trigger::AtMost, 30.)
+            .with_level(State::Critical, Trigger::AtMost, 15.)
+    }
+
+    /// A temperature-like ladder: the higher the value, the more severe.
+    fn temperature(hysteresis: f64) -> Thresholds {
+        Thresholds::new(State::Good, hysteresis, None, clock())
+            .with_level(State::Warning, Trigger::Above, 60.)
+            .with_level(State::Critical, Trigger::Above, 80.)
+    }
+
+    #[test]
+    fn most_severe_crossed_level_wins() {
+        let mut thresholds = battery(0., None, &clock());
+        assert_eq!(thresholds.state(80.), State::Good);
+        assert_eq!(thresholds.state(60.), State::Info);
+        assert_eq!(thresholds.state(10.), State::Critical);
+        assert_eq!(thresholds.state(30.), State::Warning);
+        assert_eq!(thresholds.state(61.), State::Good);
+    }
+
+    #[test]
+    fn hysteresis_delays_relaxing_below() {
+        let mut thresholds = battery(5., None, &clock());
+        assert_eq!(thresholds.state(31.), State::Info);
+        // Entered right at the threshold
+        assert_eq!(thresholds.state(30.), State::Warning);
+        // Only left once the value has moved back by the hysteresis
+        assert_eq!(thresholds.state(34.), State::Warning);
+        assert_eq!(thresholds.state(35.), State::Warning);
+        assert_eq!(thresholds.state(35.1), State::Info);
+        // Re-entered at the threshold, not the relaxed one
+        assert_eq!(thresholds.state(31.), State::Info);
+        assert_eq!(thresholds.state(30.), State::Warning);
+    }
+
+    #[test]
+    fn hysteresis_delays_relaxing_above() {
+        let mut thresholds = temperature(3.);
+        assert_eq!(thresholds.state(60.), State::Good);
+        assert_eq!(thresholds.state(60.5), State::Warning);
+        assert_eq!(thresholds.state(58.), State::Warning);
+        assert_eq!(thresholds.state(57.), State::Good);
+        assert_eq!(thresholds.state(81.), State::Critical);
+        // Relaxing from critical keeps the warning level that still holds
+        assert_eq!(thresholds.state(76.5), State::Warning);
+    }
+
+    #[test]
+    fn hysteresis_across_several_levels() {
+        let mut thresholds = battery(5., None, &clock());
+        assert_eq!(thresholds.state(10.), State::Critical);
+        // Recovering all at once skips the levels whose relaxed thresholds were passed
+        assert_eq!(thresholds.state(64.), State::Info);
+        assert_eq!(thresholds.state(65.1), State::Good);
+    }
+
+    #[test]
+    fn min_dwell_delays_new_states() {
+        let dwell = Duration::from_secs(60);
+        let clock = clock();
+        let mut thresholds = battery(0., Some(dwell), &clock);
+        // The first value is taken right away
+        assert_eq!(thresholds.state(20.), State::Warning);
+        assert_eq!(thresholds.state(10.), State::Warning);
+        clock.sleep(dwell - Duration::from_secs(1));
+        assert_eq!(thresholds.state(10.), State::Warning);
+        clock.sleep(Duration::from_secs(1));
+        assert_eq!(thresholds.state(10.), State::Critical);
+    }
+
+    #[test]
+    fn min_dwell_starts_over_when_the_value_flickers() {
+        let dwell = Duration::from_secs(60);
+        let clock = clock();
+        let mut thresholds = battery(0., Some(dwell), &clock);
+        assert_eq!(thresholds.state(20.), State::Warning);
+        assert_eq!(thresholds.state(10.), State::Warning);
+        clock.sleep(dwell / 2);
+        // Back to the current state drops the pending one
+        assert_eq!(thresholds.state(20.), State::Warning);
+        clock.sleep(dwell / 2);
+        assert_eq!(thresholds.state(10.), State::Warning);
+        clock.sleep(dwell / 2);
+        assert_eq!(thresholds.state(10.), State::Warning);
+        clock.sleep(dwell / 2);
+        assert_eq!(thresholds.state(10.), State::Critical);
+    }
+}
@@ -0,0 +1,350 @@
+//! Conversion and formatting of byte counts and rates.
+//!
+//! Blocks hold their values in bytes and let the user pick how they are displayed with a
+//! `UnitSpec`: either always in one unit (`"GiB"`, `"Mb"`, ...) or scaled automatically to the
+//! largest prefix that keeps the number below 1000 (SI) or 1024 (IEC). Unit symbols follow
+//! the usual conventions, `B` for bytes and `b` for bits, `k`/`K`, `M`, `G`, `T`, `P` for SI
+//! prefixes and `Ki`, `Mi`, `Gi`, `Ti`, `Pi` for IEC ones.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum System {
+    /// Powers of 1000
+    Si,
+    /// Powers of 1024
+    Iec,
+}
+
+impl System {
+    fn base(self) -> f64 {
+        match self {
+            System::Si => 1000.,
+            System::Iec => 1024.,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Quantity {
+    Bytes,
+    Bits,
+}
+
+const PREFIXES: [&str; 6] = ["", "k", "M", "G", "T", "P"];
+
+/// A unit like `MB`, `GiB` or `kb`: `1000^exponent` (or `1024^exponent`) bytes or bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Unit {
+    pub exponent: usize,
+    pub system: System,
+    pub quantity: Quantity,
+}
+
+pub const MIB: Unit = Unit {
+    exponent: 2,
+    system: System::Iec,
+    quantity: Quantity::Bytes,
+};
+
+pub const GIB: Unit = Unit {
+    exponent: 3,
+    system: System::Iec,
+    quantity: Quantity::Bytes,
+};
+
+pub const GB: Unit = Unit {
+    exponent: 3,
+    system: System::Si,
+    quantity: Quantity::Bytes,
+};
+
+impl Unit {
+    /// Converts a number of bytes into this unit.
+    pub fn convert(self, bytes: f64) -> f64 {
+        let value = match self.quantity {
+            Quantity::Bytes => bytes,
+            Quantity::Bits => bytes * 8.,
+        };
+        value / self.system.base().powi(self.exponent as i32)
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = match (self.system, self.exponent) {
+            (_, 0) => "",
+            // `KiB`, but `kB`
+            (System::Iec, 1) => "K",
+            (_, exponent) => PREFIXES[exponent],
+        };
+        let iec = if self.system == System::Iec && self.exponent > 0 {
+            "i"
+        } else {
+            ""
+        };
+        let quantity = match self.quantity {
+            Quantity::Bytes => "B",
+            Quantity::Bits => "b",
+        };
+        write!(f, "{}{}{}", prefix, iec, quantity)
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rest, quantity) = if s.ends_with('B') {
+            (&s[..s.len() - 1], Quantity::Bytes)
+        } else if s.ends_with('b') {
+            (&s[..s.len() - 1], Quantity::Bits)
+        } else {
+            return Err(format!("unit '{}' has to end in B (bytes) or b (bits)", s));
+        };
+        let (prefix, system) = if rest.len() > 1 && rest.ends_with('i') {
+            (&rest[..rest.len() - 1], System::Iec)
+        } else {
+            (rest, System::Si)
+        };
+        let exponent = PREFIXES
+            .iter()
+            .position(|p| p.eq_ignore_ascii_case(prefix))
+            .ok_or_else(|| format!("unknown unit prefix in '{}'", s))?;
+
+        Ok(Unit {
+            exponent,
+            system,
+            quantity,
+        })
+    }
+}
+
+/// How a value is displayed: always in the same unit, or in the largest unit of a system
+/// that keeps the number small.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnitSpec {
+    Fixed(Unit),
+    Auto(System, Quantity),
+}
+
+impl UnitSpec {
+    /// Picks the unit `bytes` are displayed in with `digits` significant digits. A number that
+    /// reaches the base once rounded moves on to the next prefix, e.g. 999.96 B is 1.00 kB.
+    pub fn unit_for(self, bytes: f64, digits: usize) -> Unit {
+        match self {
+            UnitSpec::Fixed(unit) => unit,
+            UnitSpec::Auto(system, quantity) => {
+                let mut unit = Unit {
+                    exponent: 0,
+                    system,
+                    quantity,
+                };
+                while unit.exponent < PREFIXES.len() - 1
+                    && round_significant(unit.convert(bytes), digits).abs() >= system.base()
+                {
+                    unit.exponent += 1;
+                }
+                unit
+            }
+        }
+    }
+
+    /// Length of the longest unit symbol this can produce, to keep the width of a block steady.
+    pub fn symbol_width(self) -> usize {
+        match self {
+            UnitSpec::Fixed(unit) => unit.to_string().len(),
+            UnitSpec::Auto(system, quantity) => Unit {
+                exponent: 1,
+                system,
+                quantity,
+            }.to_string()
+                .len(),
+        }
+    }
+
+    /// Converts `bytes` and formats the number with `digits` significant digits (at least
+    /// all of the integer part), returning the number and the unit separately.
+    pub fn format(self, bytes: f64, digits: usize) -> (String, Unit) {
+        let unit = self.unit_for(bytes, digits);
+        (format_significant(unit.convert(bytes), digits), unit)
+    }
+}
+
+impl Default for UnitSpec {
+    fn default() -> Self {
+        UnitSpec::Auto(System::Si, Quantity::Bytes)
+    }
+}
+
+impl FromStr for UnitSpec {
+    type Err = String;
+
+    /// `auto` (SI bytes), `auto_iec`, `auto_bits` and `auto_iec_bits` scale automatically,
+    /// anything else is taken as a fixed unit.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => UnitSpec::Auto(System::Si, Quantity::Bytes),
+            "auto_iec" => UnitSpec::Auto(System::Iec, Quantity::Bytes),
+            "auto_bits" => UnitSpec::Auto(System::Si, Quantity::Bits),
+            "auto_iec_bits" => UnitSpec::Auto(System::Iec, Quantity::Bits),
+            unit => UnitSpec::Fixed(unit.parse()?),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for UnitSpec {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Most significant digits a `digits` option accepts, about what an `f64` holds.
+pub const MAX_DIGITS: usize = 15;
+
+/// Default of the `digits` option of the blocks that display amounts of data.
+pub fn default_digits() -> usize {
+    3
+}
+
+/// Deserializes a `digits` option: the number of significant digits automatically scaled
+/// values are shown with, from 1 to `MAX_DIGITS`. Use it with
+/// `#[serde(default = "unit::default_digits", deserialize_with = "unit::deserialize_digits")]`.
+pub fn deserialize_digits<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let digits = usize::deserialize(deserializer)?;
+    if digits < 1 || digits > MAX_DIGITS {
+        return Err(de::Error::custom(format!("digits has to be between 1 and {}, not {}", MAX_DIGITS, digits)));
+    }
+    Ok(digits)
+}
+
+/// Number of decimals that leave `digits` significant digits (or all of the integer part) of
+/// `value`.
+fn decimals(value: f64, digits: usize) -> usize {
+    let integer_digits = if value.abs() < 1. {
+        1
+    } else {
+        value.abs().log10().floor() as usize + 1
+    };
+    digits.saturating_sub(integer_digits)
+}
+
+/// `value` rounded the way `format_significant` shows it.
+fn round_significant(value: f64, digits: usize) -> f64 {
+    format_significant(value, digits).parse().unwrap_or(value)
+}
+
+/// Formats `value` with `digits` significant digits, e.g. `1.23`, `12.3` and `123` for 3.
+pub fn format_significant(value: f64, digits: usize) -> String {
+    let rounded = format!("{:.*}", decimals(value, digits), value);
+    // Rounding can carry into another integer digit, e.g. 9.996 is 10.0 rather than 10.00
+    match rounded.parse::<f64>() {
+        Ok(rounded) if decimals(rounded, digits) < decimals(value, digits) => {
+            format!("{:.*}", decimals(rounded, digits), value)
+        }
+        _ => rounded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use super::{default_digits, deserialize_digits, format_significant, Quantity, System, Unit, UnitSpec, GB, MIB};
+
+    #[test]
+    fn significant_digits() {
+        assert_eq!(format_significant(0., 3), "0.00");
+        assert_eq!(format_significant(1.234, 3), "1.23");
+        assert_eq!(format_significant(12.34, 3), "12.3");
+        assert_eq!(format_significant(123.4, 3), "123");
+        assert_eq!(format_significant(12345.6, 3), "12346");
+        assert_eq!(format_significant(-1.234, 3), "-1.23");
+    }
+
+    #[test]
+    fn rounding_carries_into_the_next_digit() {
+        assert_eq!(format_significant(9.996, 3), "10.0");
+        assert_eq!(format_significant(99.96, 3), "100");
+        assert_eq!(format_significant(0.9996, 3), "1.00");
+        assert_eq!(format_significant(999.96, 3), "1000");
+    }
+
+    #[test]
+    fn automatic_units_keep_three_digits() {
+        let si = UnitSpec::Auto(System::Si, Quantity::Bytes);
+        assert_eq!(si.format(0., 3), ("0.00".to_owned(), "B".parse().unwrap()));
+        assert_eq!(si.format(999., 3), ("999".to_owned(), "B".parse().unwrap()));
+        assert_eq!(si.format(999.96, 3), ("1.00".to_owned(), "kB".parse().unwrap()));
+        assert_eq!(si.format(1_234_567., 3), ("1.23".to_owned(), "MB".parse().unwrap()));
+        assert_eq!(si.format(999_960_000., 3), ("1.00".to_owned(), GB));
+
+        let iec = UnitSpec::Auto(System::Iec, Quantity::Bytes);
+        assert_eq!(iec.format(1023.9 * 1024., 3), ("1.00".to_owned(), MIB));
+        assert_eq!(iec.format(1023. * 1024., 3), ("1023".to_owned(), "KiB".parse().unwrap()));
+        assert_eq!(iec.format(1536. * 1024., 3), ("1.50".to_owned(), MIB));
+
+        let bits = UnitSpec::Auto(System::Si, Quantity::Bits);
+        assert_eq!(bits.format(125_000., 3), ("1.00".to_owned(), "Mb".parse().unwrap()));
+    }
+
+    #[test]
+    fn automatic_units_keep_other_digits() {
+        let si = UnitSpec::Auto(System::Si, Quantity::Bytes);
+        assert_eq!(si.format(999.96, 5), ("999.96".to_owned(), "B".parse().unwrap()));
+        assert_eq!(si.format(1_234_567., 5), ("1.2346".to_owned(), "MB".parse().unwrap()));
+        assert_eq!(si.format(1_234_567., 2), ("1.2".to_owned(), "MB".parse().unwrap()));
+        assert_eq!(si.format(1_234_567., 1), ("1".to_owned(), "MB".parse().unwrap()));
+
+        // The integer part is never cut, however few digits are asked for
+        let iec = UnitSpec::Auto(System::Iec, Quantity::Bytes);
+        assert_eq!(iec.format(1023. * 1024., 2), ("1023".to_owned(), "KiB".parse().unwrap()));
+        assert_eq!(iec.format(1023.9 * 1024., 6), ("1023.90".to_owned(), "KiB".parse().unwrap()));
+    }
+
+    #[test]
+    fn digits_are_validated() {
+        assert_eq!(default_digits(), 3);
+        for &digits in &[1, 5, 15] {
+            assert_eq!(deserialize_digits(Value::Integer(digits)).unwrap(), digits as usize);
+        }
+        for &digits in &[0, 16, -1] {
+            assert!(deserialize_digits(Value::Integer(digits)).is_err(), "{}", digits);
+        }
+        assert!(deserialize_digits(Value::String("3".to_owned())).is_err());
+    }
+
+    #[test]
+    fn fixed_units_never_change() {
+        let spec = UnitSpec::Fixed(MIB);
+        assert_eq!(spec.unit_for(1e12, 3), MIB);
+        assert_eq!(spec.format(512. * 1024., 3), ("0.50".to_owned(), MIB));
+    }
+
+    #[test]
+    fn largest_prefix_is_kept() {
+        let si = UnitSpec::Auto(System::Si, Quantity::Bytes);
+        assert_eq!(si.unit_for(1e18, 3), "PB".parse().unwrap());
+    }
+
+    #[test]
+    fn units_are_parsed_and_displayed() {
+        for symbol in &["B", "kB", "MB", "GiB", "KiB", "Mb", "Kib", "PiB"] {
+            let unit: Unit = symbol.parse().unwrap();
+            assert_eq!(&unit.to_string(), symbol);
+        }
+        assert_eq!("KB".parse::<Unit>().unwrap().to_string(), "kB");
+        assert!("GX".parse::<Unit>().is_err());
+        assert!("G".parse::<Unit>().is_err());
+    }
+}
@@ -22,6 +22,17 @@ pub fn xdg_config_home() -> PathBuf {
     PathBuf::from(&config_path)
 }
 
+/// Path of the config file that is used if none is given on the command line.
+pub fn default_config_path() -> PathBuf {
+    xdg_config_home().join("i3status-rust").join("config.toml")
+}
+
+pub fn xdg_state_home() -> PathBuf {
+    let state_path = std::env::var("XDG_STATE_HOME")
+        .unwrap_or(format!("{}/.local/state", std::env::var("HOME").unwrap_or("".to_string())));
+    PathBuf::from(&state_path)
+}
+
 pub fn deserialize_file<T>(file: &str) -> Result<T>
 where
     T: DeserializeOwned,
@@ -106,13 +117,36 @@ impl PrintState {
     }
 }
 
-pub fn print_blocks(order: &[String], block_map: &HashMap<String, &mut Block>, config: &Config) -> Result<()> {
+/// Writes status lines, skipping any that is identical to the one before, as i3bar would
+/// redraw the bar anyway.
+#[derive(Default)]
+pub struct StatusPrinter {
+    last_line: Option<String>,
+}
+
+impl StatusPrinter {
+    pub fn print(&mut self, out: &mut Write, order: &[String], block_map: &HashMap<String, &mut Block>, config: &Config) -> Result<()> {
+        let line = render_blocks(order, block_map, config)?;
+        if self.last_line.as_ref() == Some(&line) {
+            trace!("status line unchanged");
+            return Ok(());
+        }
+
+        writeln!(out, "{},", line).internal_error("util", "failed to write status line")?;
+        out.flush().internal_error("util", "failed to write status line")?;
+        self.last_line = Some(line);
+        Ok(())
+    }
+}
+
+/// Renders the blocks in `order`, with separators, as one i3bar status line (a JSON array).
+pub fn render_blocks(order: &[String], block_map: &HashMap<String, &mut Block>, config: &Config) -> Result<String> {
     let mut state = PrintState {
         has_predecessor: false,
         last_bg: None,
     };
 
-    print!("[");
+    let mut line = String::from("[");
     for block_id in order {
         let block = &(*(block_map
             .get(block_id)
@@ -146,15 +180,15 @@ pub fn print_blocks(order: &[String], block_map: &HashMap<String, &mut Block>, c
                     "color": sep_fg,
                     "markup": "pango"
                 });
-        print!("{}{},", if state.has_predecessor { "," } else { "" },
-               separator.to_string());
-        print!("{}", first.to_string());
+        line.push_str(&format!("{}{},", if state.has_predecessor { "," } else { "" },
+               separator.to_string()));
+        line.push_str(&first.to_string());
         state.set_last_bg(color.to_owned());
         state.set_predecessor(true);
 
         for widget in widgets.iter().skip(1) {
-            print!("{}{}", if state.has_predecessor { "," } else { "" },
-                   widget.to_string());
+            line.push_str(&format!("{}{}", if state.has_predecessor { "," } else { "" },
+                   widget.to_string()));
             state.set_last_bg(String::from(
                 widget.get_rendered()["background"]
                     .as_str()
@@ -163,9 +197,48 @@ pub fn print_blocks(order: &[String], block_map: &HashMap<String, &mut Block>, c
             state.set_predecessor(true);
         }
     }
-    println!("],");
+    line.push(']');
+
+    Ok(line)
+}
+
+/// The text of all widgets of a block without pango markup, for output that isn't
+/// shown by i3bar.
+pub fn plain_text(block: &Block) -> String {
+    lazy_static! {
+        static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    }
+
+    let text = block
+        .view()
+        .iter()
+        .map(|widget| {
+            let rendered = widget.get_rendered();
+            let text = rendered["full_text"].as_str().unwrap_or("");
+            if rendered["markup"] == "pango" {
+                TAG.replace_all(text, "")
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&quot;", "\"")
+                    .replace("&apos;", "'")
+                    .replace("&amp;", "&")
+            } else {
+                text.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
 
-    Ok(())
+/// Escapes everything but unreserved characters (RFC 3986) for use in a URL.
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
 }
 
 pub fn color_from_rgba(color: &str) -> ::std::result::Result<(u8, u8, u8, u8), ParseIntError> {
@@ -204,8 +277,8 @@ impl FormatTemplate {
     pub fn from_string(s: &str) -> Result<FormatTemplate> {
         let s_as_bytes = s.as_bytes();
 
-        //valid var tokens: {} containing any amount of alphanumericals
-        let re = Regex::new(r"\{[a-zA-Z0-9]+?\}")
+        //valid var tokens: {} containing any amount of alphanumericals and underscores
+        let re = Regex::new(r"\{[a-zA-Z0-9_]+?\}")
             .internal_error("util", "invalid regex")?;
 
         let mut token_vec: Vec<FormatTemplate> = vec![];
@@ -315,3 +388,22 @@ macro_rules! mapped_struct {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::percent_encode;
+
+    #[test]
+    fn percent_encode_keeps_unreserved_characters() {
+        assert_eq!(percent_encode("Az09-._~"), "Az09-._~");
+    }
+
+    #[test]
+    fn percent_encode_escapes_hostile_values() {
+        assert_eq!(
+            percent_encode("2643743&appid=x 'a' \"b\" $(id) `id`;#?/%"),
+            "2643743%26appid%3Dx%20%27a%27%20%22b%22%20%24%28id%29%20%60id%60%3B%23%3F%2F%25"
+        );
+        assert_eq!(percent_encode("ü"), "%C3%BC");
+    }
+}
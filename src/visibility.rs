@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::block::Block;
+use crate::errors::*;
+use crate::input::I3BarEvent;
+use crate::subprocess;
+use crate::widget::{I3BarWidget, State};
+
+/// How long the `command` of a rule may run, as it holds up the update of the block
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A condition under which a block is hidden. All keys that are set have to match.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HideRule {
+    /// Matches while the block is in one of these states
+    pub state: Option<Vec<State>>,
+
+    /// Placeholder (e.g. `{percentage}`) that `equals`, `below` and `above` are compared against
+    pub placeholder: Option<String>,
+
+    /// Matches while the placeholder has exactly this value
+    pub equals: Option<String>,
+
+    /// Matches while the placeholder is a number below this one
+    pub below: Option<f64>,
+
+    /// Matches while the placeholder is a number above this one
+    pub above: Option<f64>,
+
+    /// Shell command that matches when it exits with a non-zero status
+    pub command: Option<String>,
+
+    /// Matches while this file doesn't exist
+    pub missing_file: Option<String>,
+}
+
+impl HideRule {
+    pub fn validate(&self, name: &str) -> Result<()> {
+        let compares = self.equals.is_some() || self.below.is_some() || self.above.is_some();
+        if compares && self.placeholder.is_none() {
+            return Err(BlockError(
+                name.to_owned(),
+                "hide_when: `equals`, `below` and `above` need a `placeholder`".to_owned(),
+            ));
+        }
+        if self.placeholder.is_some() && !compares {
+            return Err(BlockError(
+                name.to_owned(),
+                "hide_when: `placeholder` needs one of `equals`, `below` or `above`".to_owned(),
+            ));
+        }
+        if self.state.is_none() && self.placeholder.is_none() && self.command.is_none() && self.missing_file.is_none() {
+            return Err(BlockError(name.to_owned(), "hide_when: empty rule".to_owned()));
+        }
+        Ok(())
+    }
+
+    fn matches(&self, state: State, placeholders: &HashMap<String, String>) -> bool {
+        if let Some(ref states) = self.state {
+            if !states.contains(&state) {
+                return false;
+            }
+        }
+
+        if let Some(ref placeholder) = self.placeholder {
+            // A placeholder the block doesn't provide (yet) never hides it.
+            let value = match placeholders.get(placeholder) {
+                Some(value) => value.trim(),
+                None => return false,
+            };
+            if let Some(ref equals) = self.equals {
+                if value != equals {
+                    return false;
+                }
+            }
+            if self.below.is_some() || self.above.is_some() {
+                let number = match value.trim_end_matches('%').trim().parse::<f64>() {
+                    Ok(number) => number,
+                    Err(_) => return false,
+                };
+                if self.below.map_or(false, |below| number >= below)
+                    || self.above.map_or(false, |above| number <= above)
+                {
+                    return false;
+                }
+            }
+        }
+
+        if let Some(ref missing_file) = self.missing_file {
+            if Path::new(missing_file).exists() {
+                return false;
+            }
+        }
+
+        // Checked last, so that the command only runs if everything else matched
+        if let Some(ref command) = self.command {
+            let succeeded = subprocess::run_shell(command, COMMAND_TIMEOUT)
+                .map(|output| output.status.success())
+                .unwrap_or(false);
+            if succeeded {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Wraps a block and hides it, i.e. makes `view()` return no widgets, while any of
+/// its `hide_when` rules matches. The rules are evaluated after every update and click.
+pub struct Hiding {
+    inner: Box<Block>,
+    rules: Vec<HideRule>,
+    hidden: bool,
+}
+
+impl Hiding {
+    pub fn new(inner: Box<Block>, name: &str, rules: Vec<HideRule>) -> Result<Self> {
+        for rule in &rules {
+            rule.validate(name)?;
+        }
+
+        Ok(Hiding {
+            inner,
+            rules,
+            hidden: false,
+        })
+    }
+
+    fn check_rules(&mut self) {
+        let state = self.inner.state();
+        let placeholders = self.inner.placeholders();
+        self.hidden = self.rules.iter().any(|rule| rule.matches(state, &placeholders));
+    }
+}
+
+impl Block for Hiding {
+    fn update(&mut self) -> Result<Option<Duration>> {
+        let next = self.inner.update()?;
+        self.check_rules();
+        Ok(next)
+    }
+
+    fn view(&self) -> Vec<&I3BarWidget> {
+        if self.hidden {
+            Vec::new()
+        } else {
+            self.inner.view()
+        }
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        self.inner.click(event)?;
+        self.check_rules();
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    fn state(&self) -> State {
+        self.inner.state()
+    }
+
+    fn placeholders(&self) -> HashMap<String, String> {
+        self.inner.placeholders()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    use super::{HideRule, Hiding};
+    use crate::block::Block;
+    use crate::errors::*;
+    use crate::input::{I3BarEvent, MouseButton};
+    use crate::testing::{config, scratch_dir};
+    use crate::widget::{I3BarWidget, State};
+    use crate::widgets::text::TextWidget;
+
+    /// A block with a fixed placeholder `{value}`, whose state a click makes critical.
+    struct Fixed {
+        text: TextWidget,
+        value: String,
+    }
+
+    impl Block for Fixed {
+        fn update(&mut self) -> Result<Option<Duration>> {
+            Ok(None)
+        }
+
+        fn view(&self) -> Vec<&I3BarWidget> {
+            vec![&self.text]
+        }
+
+        fn click(&mut self, _: &I3BarEvent) -> Result<()> {
+            self.text.set_state(State::Critical);
+            Ok(())
+        }
+
+        fn id(&self) -> &str {
+            "fixed"
+        }
+
+        fn placeholders(&self) -> HashMap<String, String> {
+            map_to_owned!("{value}" => self.value)
+        }
+    }
+
+    fn rule(toml: &str) -> HideRule {
+        ::toml::from_str(toml).unwrap()
+    }
+
+    /// Whether `rule` hides a block in state `Idle` with `value` as its placeholder.
+    fn hides(rule_toml: &str, value: &str) -> bool {
+        let block = Fixed {
+            text: TextWidget::new(config("")).with_text("x"),
+            value: value.to_owned(),
+        };
+        let mut hiding = Hiding::new(Box::new(block), "fixed", vec![rule(rule_toml)]).unwrap();
+        hiding.update().unwrap();
+        hiding.view().is_empty()
+    }
+
+    #[test]
+    fn state_rules() {
+        assert!(hides(r#"state = ["Idle", "Good"]"#, ""));
+        assert!(!hides(r#"state = ["Critical"]"#, ""));
+    }
+
+    #[test]
+    fn placeholder_rules() {
+        assert!(hides(r#"placeholder = "{value}"
+                         equals = "100""#, " 100 "));
+        assert!(!hides(r#"placeholder = "{value}"
+                          equals = "100""#, "99"));
+
+        assert!(hides(r#"placeholder = "{value}"
+                         below = 10"#, "5%"));
+        assert!(!hides(r#"placeholder = "{value}"
+                          below = 10"#, "10"));
+        assert!(hides(r#"placeholder = "{value}"
+                         above = 10"#, "10.5"));
+        assert!(!hides(r#"placeholder = "{value}"
+                          above = 10"#, "10"));
+        assert!(hides(r#"placeholder = "{value}"
+                         above = 10
+                         below = 20"#, "15"));
+        assert!(!hides(r#"placeholder = "{value}"
+                          above = 10
+                          below = 20"#, "25"));
+
+        // Values that aren't numbers, and placeholders the block doesn't have, never hide it
+        assert!(!hides(r#"placeholder = "{value}"
+                          below = 10"#, "none"));
+        assert!(!hides(r#"placeholder = "{other}"
+                          equals = """#, ""));
+    }
+
+    #[test]
+    fn all_keys_of_a_rule_have_to_match() {
+        assert!(hides(r#"state = ["Idle"]
+                         placeholder = "{value}"
+                         equals = "0""#, "0"));
+        assert!(!hides(r#"state = ["Critical"]
+                          placeholder = "{value}"
+                          equals = "0""#, "0"));
+        assert!(!hides(r#"state = ["Idle"]
+                          placeholder = "{value}"
+                          equals = "0""#, "1"));
+    }
+
+    #[test]
+    fn command_rules() {
+        assert!(hides(r#"command = "exit 1""#, ""));
+        assert!(!hides(r#"command = "true""#, ""));
+
+        // A command that takes too long counts as failed, and doesn't hold up the bar
+        let started = Instant::now();
+        assert!(hides(r#"command = "sleep 10""#, ""));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn missing_file_rules() {
+        let file = scratch_dir("missing_file").join("present");
+        fs::write(&file, "").unwrap();
+        assert!(!hides(&format!("missing_file = {:?}", file.display().to_string()), ""));
+        assert!(hides(&format!("missing_file = {:?}", file.with_file_name("absent").display().to_string()), ""));
+    }
+
+    #[test]
+    fn clicks_check_the_rules_again() {
+        let block = Fixed {
+            text: TextWidget::new(config("")).with_text("x"),
+            value: String::new(),
+        };
+        let mut hiding = Hiding::new(Box::new(block), "fixed", vec![rule(r#"state = ["Critical"]"#)]).unwrap();
+        hiding.update().unwrap();
+        assert!(!hiding.view().is_empty());
+
+        hiding
+            .click(&I3BarEvent {
+                name: Some("fixed".to_owned()),
+                instance: None,
+                x: 0,
+                y: 0,
+                button: MouseButton::Left,
+            })
+            .unwrap();
+        assert!(hiding.view().is_empty());
+    }
+
+    #[test]
+    fn rules_are_validated() {
+        assert!(rule(r#"state = ["Idle"]"#).validate("x").is_ok());
+        assert!(rule(r#"placeholder = "{a}"
+                        below = 1"#).validate("x").is_ok());
+        assert!(rule(r#"missing_file = "/x""#).validate("x").is_ok());
+        assert!(rule(r#"command = "true""#).validate("x").is_ok());
+
+        for toml in &[r#"equals = "1""#, "below = 1", "above = 1", r#"placeholder = "{a}""#, ""] {
+            assert!(rule(toml).validate("x").is_err(), "{:?} should be invalid", toml);
+        }
+        assert!(::toml::from_str::<HideRule>("hidden = true").is_err());
+    }
+}
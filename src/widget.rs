@@ -1,7 +1,7 @@
 use crate::themes::Theme;
 use serde_json::value::Value;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum State {
     Idle,
     Info,
@@ -21,9 +21,23 @@ impl State {
             Critical => (&theme.critical_bg, &theme.critical_fg),
         }
     }
+
+    /// Orders states by how much attention they ask for, used to pick the
+    /// state of a block that consists of several widgets.
+    pub fn severity(self) -> u8 {
+        use self::State::*;
+        match self {
+            Idle => 0,
+            Good => 1,
+            Info => 2,
+            Warning => 3,
+            Critical => 4,
+        }
+    }
 }
 
 pub trait I3BarWidget {
     fn to_string(&self) -> String;
     fn get_rendered(&self) -> &Value;
+    fn get_state(&self) -> State;
 }
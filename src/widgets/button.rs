@@ -6,6 +6,7 @@ use super::super::widget::I3BarWidget;
 #[derive(Clone, Debug)]
 pub struct ButtonWidget {
     content: Option<String>,
+    short_content: Option<String>,
     icon: Option<String>,
     state: State,
     id: String,
@@ -18,6 +19,7 @@ impl ButtonWidget {
     pub fn new(config: Config, id: &str) -> Self {
         ButtonWidget {
             content: None,
+            short_content: None,
             icon: None,
             state: State::Idle,
             id: String::from(id),
@@ -63,6 +65,12 @@ impl ButtonWidget {
         self.update();
     }
 
+    /// Sets the text i3bar shows instead of the full one when the bar runs out of space
+    pub fn set_short_text(&mut self, content: Option<String>) {
+        self.short_content = content;
+        self.update();
+    }
+
     pub fn set_icon(&mut self, name: &str) {
         self.icon = self.config.icons.get(name).cloned();
         self.update();
@@ -87,6 +95,11 @@ impl ButtonWidget {
             "color": key_fg,
             "markup": "pango"
         });
+        if let Some(ref short_content) = self.short_content {
+            self.rendered["short_text"] = json!(format!("{}{} ",
+                                self.icon.clone().unwrap_or_else(|| String::from(" ")),
+                                short_content));
+        }
 
         self.cached_output = Some(self.rendered.to_string());
     }
@@ -102,4 +115,8 @@ impl I3BarWidget for ButtonWidget {
     fn get_rendered(&self) -> &Value {
         &self.rendered
     }
+
+    fn get_state(&self) -> State {
+        self.state
+    }
 }
@@ -115,4 +115,8 @@ impl I3BarWidget for GraphWidget {
     fn get_rendered(&self) -> &Value {
         &self.rendered
     }
+
+    fn get_state(&self) -> State {
+        self.state
+    }
 }
@@ -173,4 +173,8 @@ impl I3BarWidget for RotatingTextWidget {
     fn get_rendered(&self) -> &Value {
         &self.rendered
     }
+
+    fn get_state(&self) -> State {
+        self.state
+    }
 }
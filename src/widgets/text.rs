@@ -91,4 +91,8 @@ impl I3BarWidget for TextWidget {
     fn get_rendered(&self) -> &Value {
         &self.rendered
     }
+
+    fn get_state(&self) -> State {
+        self.state
+    }
 }